@@ -4,27 +4,66 @@ use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
-use super::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
+use super::front_matter::{split_front_matter, Metadata};
+use super::summary::{
+    parse_summary, restart_numbering_per_part, Link, SectionNumber, Summary, SummaryItem,
+};
 use config::BuildConfig;
 use errors::*;
 
 /// Load a book into memory from its `src/` directory.
 pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book> {
     let src_dir = src_dir.as_ref();
+    let summary = load_summary(&src_dir.join("SUMMARY.md"), cfg)?;
+
+    if cfg.create_missing {
+        create_missing(&src_dir, &summary).chain_err(|| "Unable to create missing chapters")?;
+    }
+
+    load_book_from_disk(&summary, src_dir, None)
+}
+
+/// Load one translation of a multilingual book from its `src/<language>/`
+/// directory.
+///
+/// Chapters which haven't been translated yet are loaded from the default
+/// language's `fallback_dir` instead, as is its `SUMMARY.md` if the
+/// translation doesn't have one of its own.
+pub fn load_translation<P, Q>(src_dir: P, fallback_dir: Q, cfg: &BuildConfig) -> Result<Book>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let src_dir = src_dir.as_ref();
+    let fallback_dir = fallback_dir.as_ref();
+
     let summary_md = src_dir.join("SUMMARY.md");
+    let summary = if summary_md.exists() {
+        load_summary(&summary_md, cfg)?
+    } else {
+        debug!(
+            "{} doesn't exist, using the default language's SUMMARY.md",
+            summary_md.display()
+        );
+        load_summary(&fallback_dir.join("SUMMARY.md"), cfg)?
+    };
 
+    load_book_from_disk(&summary, src_dir, Some(fallback_dir))
+}
+
+fn load_summary(summary_md: &Path, cfg: &BuildConfig) -> Result<Summary> {
     let mut summary_content = String::new();
     File::open(summary_md)
         .chain_err(|| "Couldn't open SUMMARY.md")?
         .read_to_string(&mut summary_content)?;
 
-    let summary = parse_summary(&summary_content).chain_err(|| "Summary parsing failed")?;
+    let mut summary = parse_summary(&summary_content).chain_err(|| "Summary parsing failed")?;
 
-    if cfg.create_missing {
-        create_missing(&src_dir, &summary).chain_err(|| "Unable to create missing chapters")?;
+    if cfg.restart_numbering_per_part {
+        restart_numbering_per_part(&mut summary.numbered_chapters);
     }
 
-    load_book_from_disk(&summary, src_dir)
+    Ok(summary)
 }
 
 fn create_missing(src_dir: &Path, summary: &Summary) -> Result<()> {
@@ -40,7 +79,7 @@ fn create_missing(src_dir: &Path, summary: &Summary) -> Result<()> {
 
         if let SummaryItem::Link(ref link) = *next {
             let filename = src_dir.join(&link.location);
-            if !filename.exists() {
+            if !link.is_draft() && !filename.exists() {
                 if let Some(parent) = filename.parent() {
                     if !parent.exists() {
                         fs::create_dir_all(parent)?;
@@ -131,6 +170,9 @@ pub enum BookItem {
     Chapter(Chapter),
     /// A section separator.
     Separator,
+    /// The title of a part of the book, which groups together the numbered
+    /// chapters after it.
+    PartTitle(String),
 }
 
 impl From<Chapter> for BookItem {
@@ -155,6 +197,10 @@ pub struct Chapter {
     pub path: PathBuf,
     /// An ordered list of the names of each chapter above this one, in the hierarchy.
     pub parent_names: Vec<String>,
+    /// The metadata from the chapter's front matter (e.g. `title`, `author`
+    /// or `description`).
+    #[serde(default)]
+    pub metadata: Metadata,
 }
 
 impl Chapter {
@@ -173,13 +219,33 @@ impl Chapter {
             ..Default::default()
         }
     }
+
+    /// Create a draft chapter, which is listed in the table of contents but
+    /// has no content or source file yet.
+    pub fn new_draft(name: &str, parent_names: Vec<String>) -> Chapter {
+        Chapter {
+            name: name.to_string(),
+            parent_names,
+            ..Default::default()
+        }
+    }
+
+    /// Is this a draft chapter (i.e. one without a source file, or whose
+    /// front matter says `draft: true`)?
+    pub fn is_draft(&self) -> bool {
+        self.path.as_os_str().is_empty()
+    }
 }
 
 /// Use the provided `Summary` to load a `Book` from disk.
 ///
 /// You need to pass in the book's source directory because all the links in
 /// `SUMMARY.md` give the chapter locations relative to it.
-fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<Book> {
+fn load_book_from_disk<P: AsRef<Path>>(
+    summary: &Summary,
+    src_dir: P,
+    fallback_dir: Option<&Path>,
+) -> Result<Book> {
     debug!("Loading the book from disk");
     let src_dir = src_dir.as_ref();
 
@@ -192,7 +258,7 @@ fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<
     let mut chapters = Vec::new();
 
     for summary_item in summary_items {
-        let chapter = load_summary_item(summary_item, src_dir, Vec::new())?;
+        let chapter = load_summary_item(summary_item, src_dir, fallback_dir, Vec::new())?;
         chapters.push(chapter);
     }
 
@@ -205,12 +271,14 @@ fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<
 fn load_summary_item<P: AsRef<Path>>(
     item: &SummaryItem,
     src_dir: P,
+    fallback_dir: Option<&Path>,
     parent_names: Vec<String>,
 ) -> Result<BookItem> {
     match *item {
         SummaryItem::Separator => Ok(BookItem::Separator),
+        SummaryItem::PartTitle(ref title) => Ok(BookItem::PartTitle(title.clone())),
         SummaryItem::Link(ref link) => {
-            load_chapter(link, src_dir, parent_names).map(BookItem::Chapter)
+            load_chapter(link, src_dir, fallback_dir, parent_names).map(BookItem::Chapter)
         }
     }
 }
@@ -218,17 +286,72 @@ fn load_summary_item<P: AsRef<Path>>(
 fn load_chapter<P: AsRef<Path>>(
     link: &Link,
     src_dir: P,
+    fallback_dir: Option<&Path>,
     parent_names: Vec<String>,
 ) -> Result<Chapter> {
-    debug!("Loading {} ({})", link.name, link.location.display());
     let src_dir = src_dir.as_ref();
 
-    let location = if link.location.is_absolute() {
+    let mut ch = if link.is_draft() {
+        debug!("Loading {} (draft)", link.name);
+        Chapter::new_draft(&link.name, parent_names.clone())
+    } else {
+        debug!("Loading {} ({})", link.name, link.location.display());
+        let (content, path) = read_chapter(link, src_dir, fallback_dir)?;
+        let (metadata, content) = split_front_matter(&content).chain_err(|| {
+            format!("Unable to load \"{}\" ({})", link.name, path.display())
+        })?;
+
+        let mut ch = if metadata.get("draft") == Some(&json!(true)) {
+            debug!("\"{}\" is marked as a draft", link.name);
+            Chapter::new_draft(&link.name, parent_names.clone())
+        } else {
+            Chapter::new(&link.name, content.to_string(), path, parent_names.clone())
+        };
+        ch.metadata = metadata;
+        ch
+    };
+    ch.number = link.number.clone();
+
+    let mut sub_item_parents = parent_names;
+    sub_item_parents.push(link.name.clone());
+    let sub_items = link
+        .nested_items
+        .iter()
+        .map(|i| load_summary_item(i, src_dir, fallback_dir, sub_item_parents.clone()))
+        .collect::<Result<Vec<_>>>()?;
+
+    ch.sub_items = sub_items;
+
+    Ok(ch)
+}
+
+/// Read a (non-draft) chapter's source file, returning its contents and its
+/// location relative to the directory it was found in.
+fn read_chapter(
+    link: &Link,
+    src_dir: &Path,
+    fallback_dir: Option<&Path>,
+) -> Result<(String, PathBuf)> {
+    let mut base = src_dir;
+
+    let mut location = if link.location.is_absolute() {
         link.location.clone()
     } else {
         src_dir.join(&link.location)
     };
 
+    if let Some(fallback_dir) = fallback_dir {
+        if !location.exists() && !link.location.is_absolute() {
+            debug!(
+                "\"{}\" hasn't been translated, falling back to {}",
+                link.name,
+                fallback_dir.display()
+            );
+            base = fallback_dir;
+            location = fallback_dir.join(&link.location);
+        }
+    }
+
     let mut f = File::open(&location)
         .chain_err(|| format!("Chapter file not found, {}", link.location.display()))?;
 
@@ -237,23 +360,10 @@ fn load_chapter<P: AsRef<Path>>(
         .chain_err(|| format!("Unable to read \"{}\" ({})", link.name, location.display()))?;
 
     let stripped = location
-        .strip_prefix(&src_dir)
+        .strip_prefix(base)
         .expect("Chapters are always inside a book");
 
-    let mut sub_item_parents = parent_names.clone();
-    let mut ch = Chapter::new(&link.name, content, stripped, parent_names);
-    ch.number = link.number.clone();
-
-    sub_item_parents.push(link.name.clone());
-    let sub_items = link
-        .nested_items
-        .iter()
-        .map(|i| load_summary_item(i, src_dir, sub_item_parents.clone()))
-        .collect::<Result<Vec<_>>>()?;
-
-    ch.sub_items = sub_items;
-
-    Ok(ch)
+    Ok((content, stripped.to_path_buf()))
 }
 
 /// A depth-first iterator over the items in a book.
@@ -356,15 +466,48 @@ And here is some \
             Vec::new(),
         );
 
-        let got = load_chapter(&link, temp_dir.path(), Vec::new()).unwrap();
+        let got = load_chapter(&link, temp_dir.path(), None, Vec::new()).unwrap();
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn untranslated_chapters_fall_back_to_the_default_language() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let en = temp.path().join("en");
+        let ja = temp.path().join("ja");
+        fs::create_dir_all(&en).unwrap();
+        fs::create_dir_all(&ja).unwrap();
+
+        File::create(en.join("SUMMARY.md"))
+            .unwrap()
+            .write_all(b"- [First](first.md)\n- [Second](second.md)")
+            .unwrap();
+        File::create(en.join("first.md")).unwrap().write_all(b"First").unwrap();
+        File::create(en.join("second.md")).unwrap().write_all(b"Second").unwrap();
+        File::create(ja.join("first.md")).unwrap().write_all(b"Ichi").unwrap();
+
+        let got = load_translation(&ja, &en, &BuildConfig::default()).unwrap();
+
+        let contents: Vec<_> = got
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some((ch.path.clone(), ch.content.clone())),
+                _ => None,
+            }).collect();
+        assert_eq!(
+            contents,
+            vec![
+                (PathBuf::from("first.md"), String::from("Ichi")),
+                (PathBuf::from("second.md"), String::from("Second")),
+            ]
+        );
+    }
+
     #[test]
     fn cant_load_a_nonexistent_chapter() {
         let link = Link::new("Chapter 1", "/foo/bar/baz.md");
 
-        let got = load_chapter(&link, "", Vec::new());
+        let got = load_chapter(&link, "", None, Vec::new());
         assert!(got.is_err());
     }
 
@@ -379,6 +522,7 @@ And here is some \
             path: PathBuf::from("second.md"),
             parent_names: vec![String::from("Chapter 1")],
             sub_items: Vec::new(),
+            metadata: Metadata::new(),
         };
         let should_be = BookItem::Chapter(Chapter {
             name: String::from("Chapter 1"),
@@ -391,9 +535,11 @@ And here is some \
                 BookItem::Separator,
                 BookItem::Chapter(nested.clone()),
             ],
+            metadata: Metadata::new(),
         });
 
-        let got = load_summary_item(&SummaryItem::Link(root), temp.path(), Vec::new()).unwrap();
+        let got =
+            load_summary_item(&SummaryItem::Link(root), temp.path(), None, Vec::new()).unwrap();
         assert_eq!(got, should_be);
     }
 
@@ -414,7 +560,7 @@ And here is some \
             ..Default::default()
         };
 
-        let got = load_book_from_disk(&summary, temp.path()).unwrap();
+        let got = load_book_from_disk(&summary, temp.path(), None).unwrap();
 
         assert_eq!(got, should_be);
     }
@@ -465,6 +611,7 @@ And here is some \
                             Vec::new(),
                         )),
                     ],
+                    metadata: Metadata::new(),
                 }),
                 BookItem::Separator,
             ],
@@ -516,6 +663,7 @@ And here is some \
                             Vec::new(),
                         )),
                     ],
+                    metadata: Metadata::new(),
                 }),
                 BookItem::Separator,
             ],
@@ -531,7 +679,7 @@ And here is some \
     }
 
     #[test]
-    fn cant_load_chapters_with_an_empty_path() {
+    fn chapters_with_an_empty_path_are_loaded_as_drafts() {
         let (_, temp) = dummy_link();
         let summary = Summary {
             numbered_chapters: vec![SummaryItem::Link(Link {
@@ -541,9 +689,15 @@ And here is some \
             })],
             ..Default::default()
         };
+        let should_be = Chapter::new_draft("Empty", Vec::new());
 
-        let got = load_book_from_disk(&summary, temp.path());
-        assert!(got.is_err());
+        let got = load_book_from_disk(&summary, temp.path(), None).unwrap();
+
+        assert_eq!(got.sections, vec![BookItem::Chapter(should_be)]);
+        match got.sections[0] {
+            BookItem::Chapter(ref ch) => assert!(ch.is_draft()),
+            ref other => panic!("Expected a draft chapter, found {:?}", other),
+        }
     }
 
     #[test]
@@ -561,7 +715,7 @@ And here is some \
             ..Default::default()
         };
 
-        let got = load_book_from_disk(&summary, temp.path());
+        let got = load_book_from_disk(&summary, temp.path(), None);
         assert!(got.is_err());
     }
 }
@@ -0,0 +1,343 @@
+//! Checking a book for broken internal links.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, Options, Parser, Tag, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use regex::{Captures, Regex};
+
+use super::{Book, BookItem, Chapter};
+use config::HtmlConfig;
+use renderer::{header_id, unique_id, HEADER};
+use utils;
+use utils::fs::resolve_relative_path;
+
+/// A link in one of the book's chapters which doesn't point anywhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    /// The chapter containing the link, relative to the book's `src`
+    /// directory.
+    pub chapter: PathBuf,
+    /// The line the link is on (starting from 1).
+    pub line: usize,
+    /// The column the link starts at, i.e. its opening `[` (starting from 1).
+    pub column: usize,
+    /// The link's target, exactly as it was written.
+    pub target: String,
+    /// Why the link is considered broken.
+    pub reason: BrokenLinkReason,
+}
+
+/// The different ways a link can be broken.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrokenLinkReason {
+    /// The link points at a markdown file which isn't in `SUMMARY.md`.
+    ChapterNotFound(PathBuf),
+    /// The chapter exists, but none of its headings would generate the
+    /// requested `#fragment`.
+    AnchorNotFound(PathBuf, String),
+}
+
+impl Display for BrokenLink {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: broken link \"{}\", ",
+            self.chapter.display(),
+            self.line,
+            self.column,
+            self.target
+        )?;
+
+        match self.reason {
+            BrokenLinkReason::ChapterNotFound(ref path) => {
+                write!(f, "{} is not a chapter in SUMMARY.md", path.display())
+            }
+            BrokenLinkReason::AnchorNotFound(ref path, ref anchor) => {
+                write!(f, "{} has no heading with the id \"{}\"", path.display(), anchor)
+            }
+        }
+    }
+}
+
+/// Walk every chapter in the `Book`, making sure each relative link to
+/// another chapter (and its `#fragment`, if present) can be resolved.
+///
+/// Links to external websites and to files which aren't markdown are not
+/// checked. Headings are given ids the same way the HTML renderer would with
+/// `html_config`.
+pub fn check_links(book: &Book, html_config: &HtmlConfig) -> Vec<BrokenLink> {
+    let chapters: HashMap<PathBuf, &Chapter> = book
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft() => {
+                Some((resolve_relative_path(&ch.path), ch))
+            }
+            _ => None,
+        }).collect();
+
+    let mut anchors: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut broken = Vec::new();
+
+    for item in book.iter() {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft() => ch,
+            _ => continue,
+        };
+        let base = ch.path.parent().unwrap_or_else(|| Path::new(""));
+
+        for (offset, target) in find_links(&ch.content) {
+            let (path, fragment) = match split_target(&target) {
+                Some(parts) => parts,
+                None => continue,
+            };
+
+            let resolved = match path {
+                Some(path) => resolve_relative_path(base.join(path)),
+                None => resolve_relative_path(&ch.path),
+            };
+
+            let reason = match chapters.get(&resolved) {
+                None => BrokenLinkReason::ChapterNotFound(resolved),
+                Some(target_chapter) => match fragment {
+                    Some(fragment) => {
+                        let known = anchors
+                            .entry(resolved.clone())
+                            .or_insert_with(|| collect_ids(&target_chapter.content, html_config));
+                        if known.contains(fragment) {
+                            continue;
+                        }
+                        BrokenLinkReason::AnchorNotFound(resolved, fragment.to_string())
+                    }
+                    None => continue,
+                },
+            };
+
+            let (line, column) = utils::line_and_column(&ch.content, offset);
+            broken.push(BrokenLink {
+                chapter: ch.path.clone(),
+                line,
+                column,
+                target,
+                reason,
+            });
+        }
+    }
+
+    broken
+}
+
+/// Find the destination of every link in some markdown, along with the byte
+/// offset it was written at.
+fn find_links(content: &str) -> Vec<(usize, String)> {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    let mut parser = Parser::new_ext(content, opts);
+
+    let mut links = Vec::new();
+
+    loop {
+        // the parser is just before the link's opening bracket when it's
+        // about to start the link
+        let offset = parser.get_offset().min(content.len());
+        match parser.next() {
+            Some(Event::Start(Tag::Link(dest, _))) => links.push((offset, dest.into_owned())),
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    links
+}
+
+/// Split a link target into the (optional) path and `#fragment`, returning
+/// `None` if it isn't a link to another part of the book.
+fn split_target(target: &str) -> Option<(Option<&str>, Option<&str>)> {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.\-]*:").unwrap();
+    }
+
+    if target.is_empty() || target.starts_with("//") || SCHEME.is_match(target) {
+        return None;
+    }
+
+    let (path, fragment) = match target.find('#') {
+        Some(i) => (&target[..i], Some(&target[i + 1..])),
+        None => (target, None),
+    };
+    let path = match path.find('?') {
+        Some(i) => &path[..i],
+        None => path,
+    };
+
+    if path.is_empty() {
+        Some((None, fragment))
+    } else if Path::new(path).extension() == Some(OsStr::new("md")) {
+        Some((Some(path), fragment))
+    } else {
+        None
+    }
+}
+
+/// Collect every id the HTML renderer would give the chapter's headings, plus
+/// any ids from raw HTML.
+fn collect_ids(content: &str, html_config: &HtmlConfig) -> HashSet<String> {
+    lazy_static! {
+        static ref ID_ATTRIBUTE: Regex = Regex::new(r#"\b(?:id|name)="([^"]+)""#).unwrap();
+    }
+
+    let html = utils::render_markdown_with_config(content, html_config);
+    let mut id_counter = HashMap::new();
+    let mut ids: HashSet<_> = HEADER
+        .captures_iter(&html)
+        .map(|caps| unique_id(&header_id(&caps), &mut id_counter))
+        .collect();
+
+    ids.extend(
+        ID_ATTRIBUTE
+            .captures_iter(&html)
+            .map(|caps: Captures| caps[1].to_string()),
+    );
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book_with(chapters: Vec<Chapter>) -> Book {
+        let mut book = Book::new();
+        for ch in chapters {
+            book.push_item(ch);
+        }
+        book
+    }
+
+    #[test]
+    fn valid_links_are_not_reported() {
+        let book = book_with(vec![
+            Chapter::new(
+                "First",
+                "# First\n\n## Some Heading\n\n[second](nested/second.md#details)".to_string(),
+                "first.md",
+                Vec::new(),
+            ),
+            Chapter::new(
+                "Second",
+                "# Second\n\n## Details\n\n[back](../first.md#some-heading) [up](#second)"
+                    .to_string(),
+                "nested/second.md",
+                Vec::new(),
+            ),
+        ]);
+
+        assert_eq!(check_links(&book, &HtmlConfig::default()), Vec::new());
+    }
+
+    #[test]
+    fn external_and_non_markdown_links_are_ignored() {
+        let book = book_with(vec![Chapter::new(
+            "First",
+            "[a](https://example.com/missing.md) [b](mailto:me@example.com) [c](image.png)"
+                .to_string(),
+            "first.md",
+            Vec::new(),
+        )]);
+
+        assert_eq!(check_links(&book, &HtmlConfig::default()), Vec::new());
+    }
+
+    #[test]
+    fn report_missing_chapters_with_their_location() {
+        let book = book_with(vec![Chapter::new(
+            "First",
+            "# First\n\nSee the [other chapter](./other.md) too.".to_string(),
+            "dir/first.md",
+            Vec::new(),
+        )]);
+
+        let got = check_links(&book, &HtmlConfig::default());
+
+        assert_eq!(
+            got,
+            vec![BrokenLink {
+                chapter: PathBuf::from("dir/first.md"),
+                line: 3,
+                column: 9,
+                target: String::from("./other.md"),
+                reason: BrokenLinkReason::ChapterNotFound(PathBuf::from("dir/other.md")),
+            }]
+        );
+    }
+
+    #[test]
+    fn report_missing_anchors() {
+        let book = book_with(vec![
+            Chapter::new(
+                "First",
+                "# First\n\n[ok](second.md#foo-1)\n[bad](second.md#bar)\n[self](#nope)"
+                    .to_string(),
+                "first.md",
+                Vec::new(),
+            ),
+            Chapter::new(
+                "Second",
+                "## Foo\n\n## Foo".to_string(),
+                "second.md",
+                Vec::new(),
+            ),
+        ]);
+
+        let got = check_links(&book, &HtmlConfig::default());
+
+        assert_eq!(got.len(), 2);
+        assert_eq!((got[0].line, got[0].column), (4, 1));
+        assert_eq!(
+            got[0].reason,
+            BrokenLinkReason::AnchorNotFound(PathBuf::from("second.md"), String::from("bar"))
+        );
+        assert_eq!((got[1].line, got[1].column), (5, 1));
+        assert_eq!(
+            got[1].reason,
+            BrokenLinkReason::AnchorNotFound(PathBuf::from("first.md"), String::from("nope"))
+        );
+    }
+
+    #[test]
+    fn custom_heading_ids_are_used_when_enabled() {
+        let book = book_with(vec![Chapter::new(
+            "First",
+            "## Title {#custom}\n\n[a](#custom) [b](#title-custom)".to_string(),
+            "first.md",
+            Vec::new(),
+        )]);
+        let mut html_config = HtmlConfig::default();
+        html_config.markdown.heading_attributes = true;
+
+        let got = check_links(&book, &html_config);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].target, "#title-custom");
+    }
+
+    #[test]
+    fn links_are_located_by_the_parser() {
+        let content = "Read other.md, then [it](other.md).\n\n> `[no](other.md)` [yes](other.md)";
+        let got: Vec<_> = find_links(content)
+            .into_iter()
+            .map(|(offset, target)| (utils::line_and_column(content, offset), target))
+            .collect();
+
+        assert_eq!(
+            got,
+            vec![
+                ((1, 21), String::from("other.md")),
+                ((3, 20), String::from("other.md")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! Running the Rust code blocks in a book as tests.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use pulldown_cmark::{Event, Parser, Tag};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use tempfile::Builder as TempFileBuilder;
+
+use super::{Book, BookItem, Chapter};
+use config::Config;
+use errors::*;
+use utils;
+
+/// Code block attributes which only mean something to mdBook, and don't change
+/// whether a block is Rust.
+const MDBOOK_ATTRIBUTES: &[&str] = &["editable", "noplaypen", "mdbook-runnable"];
+
+/// A Rust code block in one of the book's chapters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RustBlock {
+    /// The line the block starts on (starting from 1).
+    pub line: usize,
+    /// The block's info string (e.g. `rust,no_run`).
+    pub info: String,
+    /// The code inside the block.
+    pub code: String,
+}
+
+impl RustBlock {
+    /// The block's attributes (e.g. `should_panic` or `edition2018`).
+    pub fn attributes(&self) -> Vec<&str> {
+        split_attributes(&self.info)
+    }
+
+    /// Should this block be skipped when testing?
+    pub fn is_ignored(&self) -> bool {
+        self.attributes().contains(&"ignore")
+    }
+}
+
+/// The outcome of testing every Rust code block in a chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterTests {
+    /// The chapter's name.
+    pub name: String,
+    /// The chapter's location, relative to the book's `src` directory.
+    pub path: PathBuf,
+    /// How many code blocks passed.
+    pub passed: usize,
+    /// How many code blocks were marked with `ignore`.
+    pub ignored: usize,
+    /// The code blocks which failed.
+    pub failures: Vec<TestFailure>,
+}
+
+impl ChapterTests {
+    fn new(ch: &Chapter) -> ChapterTests {
+        ChapterTests {
+            name: ch.name.clone(),
+            path: ch.path.clone(),
+            passed: 0,
+            ignored: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    /// The number of code blocks in the chapter.
+    pub fn total(&self) -> usize {
+        self.passed + self.ignored + self.failures.len()
+    }
+}
+
+/// A code block which didn't compile, or whose test failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestFailure {
+    /// The line the block starts on in the chapter's file (starting from 1),
+    /// or `None` if a preprocessor added the block, e.g. from an
+    /// `{{#include}}`.
+    pub line: Option<usize>,
+    /// Everything `rustdoc` printed.
+    pub output: String,
+}
+
+enum Outcome {
+    Passed,
+    Ignored,
+    Failed(String),
+}
+
+/// Test every Rust code block in the (preprocessed) book with `rustdoc`, using
+/// up to `build.jobs` threads. `source` is the book as it was loaded, which
+/// is used to find the lines failed blocks were written on.
+///
+/// The results are in the same order as the book's chapters.
+pub fn test_book(
+    book: &Book,
+    source: &Book,
+    root: &Path,
+    config: &Config,
+    library_paths: &[&str],
+) -> Result<Vec<ChapterTests>> {
+    let temp_dir = TempFileBuilder::new().prefix("mdbook-").tempdir()?;
+    let rustdoc = Rustdoc::new(root, config, library_paths)?;
+
+    let chapters: Vec<&Chapter> = book
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft() => Some(ch),
+            _ => None,
+        }).collect();
+
+    let written: HashMap<&Path, &str> = source
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => Some((ch.path.as_path(), ch.content.as_str())),
+            _ => None,
+        }).collect();
+
+    let blocks: Vec<(usize, RustBlock, Option<usize>)> = chapters
+        .iter()
+        .enumerate()
+        .flat_map(|(i, ch)| {
+            let blocks = find_rust_blocks(&ch.content);
+            let written_blocks = match written.get(ch.path.as_path()) {
+                Some(content) => find_rust_blocks(content),
+                None => Vec::new(),
+            };
+            let lines: Vec<_> = (0..blocks.len())
+                .map(|n| source_line(n, &blocks, &written_blocks))
+                .collect();
+
+            blocks
+                .into_iter()
+                .zip(lines)
+                .map(move |(block, line)| (i, block, line))
+        }).collect();
+
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(config.build.jobs.unwrap_or(0))
+        .build()
+        .chain_err(|| "Unable to start the testing threads")?;
+
+    let outcomes: Vec<Result<Outcome>> = pool.install(|| {
+        blocks
+            .par_iter()
+            .enumerate()
+            .map(|(n, (_, block, _))| {
+                let path = temp_dir.path().join(format!("block-{}.md", n));
+                run_block(block, &path, &rustdoc)
+            }).collect()
+    });
+
+    let mut results: Vec<ChapterTests> =
+        chapters.iter().map(|ch| ChapterTests::new(ch)).collect();
+
+    for (&(chapter, _, line), outcome) in blocks.iter().zip(outcomes) {
+        let result = &mut results[chapter];
+
+        match outcome? {
+            Outcome::Passed => result.passed += 1,
+            Outcome::Ignored => result.ignored += 1,
+            Outcome::Failed(output) => result.failures.push(TestFailure { line, output }),
+        }
+    }
+
+    Ok(results)
+}
+
+/// Find the line the `n`th of a preprocessed chapter's `blocks` was written
+/// on, given the blocks `written` in the chapter's file.
+///
+/// When preprocessing kept the same blocks in the same order (e.g. it only
+/// filled one in with an `{{#include}}`), they match up one to one.
+/// Otherwise a block can only be found if it's unchanged and not repeated.
+fn source_line(n: usize, blocks: &[RustBlock], written: &[RustBlock]) -> Option<usize> {
+    let same_blocks = blocks.len() == written.len()
+        && blocks.iter().zip(written).all(|(a, b)| a.info == b.info);
+    if same_blocks {
+        return Some(written[n].line);
+    }
+
+    let block = &blocks[n];
+    let mut matching = written
+        .iter()
+        .filter(|other| other.info == block.info && other.code == block.code);
+    match (matching.next(), matching.next()) {
+        (Some(other), None) => Some(other.line),
+        _ => None,
+    }
+}
+
+/// How to invoke `rustdoc`, according to the `[rust]` and `[test]` tables.
+struct Rustdoc {
+    args: Vec<OsString>,
+    env: BTreeMap<String, String>,
+}
+
+impl Rustdoc {
+    fn new(root: &Path, config: &Config, library_paths: &[&str]) -> Result<Rustdoc> {
+        let test_config = config.test_config()?;
+        let mut args: Vec<OsString> = vec!["--test".into()];
+
+        if let Some(edition) = config.rust.edition {
+            args.push("--edition".into());
+            args.push(edition.to_string().into());
+        }
+
+        for library_path in library_paths {
+            args.push("-L".into());
+            args.push(library_path.into());
+        }
+
+        for (name, path) in &test_config.externs {
+            let mut extern_arg = OsString::from(format!("{}=", name));
+            extern_arg.push(root.join(path));
+            args.push("--extern".into());
+            args.push(extern_arg);
+        }
+
+        args.extend(test_config.rustdoc_flags.into_iter().map(OsString::from));
+
+        Ok(Rustdoc {
+            args,
+            env: test_config.env,
+        })
+    }
+
+    fn command(&self, path: &Path) -> Command {
+        let mut cmd = Command::new("rustdoc");
+        cmd.arg(path).args(&self.args).envs(&self.env);
+        cmd
+    }
+}
+
+fn run_block(block: &RustBlock, path: &Path, rustdoc: &Rustdoc) -> Result<Outcome> {
+    if block.is_ignored() {
+        return Ok(Outcome::Ignored);
+    }
+
+    // Give rustdoc a markdown file containing just this block, so it still
+    // takes care of hidden lines and attributes like `should_panic`
+    let mut f = utils::fs::create_file(path)?;
+    write!(f, "```{}\n{}", block.info, block.code)?;
+    if !block.code.ends_with('\n') {
+        writeln!(f)?;
+    }
+    writeln!(f, "```")?;
+
+    let output = rustdoc
+        .command(path)
+        .output()
+        .chain_err(|| "Unable to run rustdoc")?;
+
+    if output.status.success() {
+        Ok(Outcome::Passed)
+    } else {
+        let mut message = String::from_utf8_lossy(&output.stdout).into_owned();
+        message.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(Outcome::Failed(message))
+    }
+}
+
+/// Find every code block `rustdoc` would treat as Rust.
+pub fn find_rust_blocks(content: &str) -> Vec<RustBlock> {
+    let mut parser = Parser::new(content);
+    let mut blocks = Vec::new();
+    let mut current: Option<RustBlock> = None;
+
+    while let Some(event) = parser.next() {
+        match event {
+            Event::Start(Tag::CodeBlock(ref info)) if is_rust(info) => {
+                // the parser is now at the start of the code, so step back
+                // over the opening fence (if there is one)
+                let offset = parser.get_offset().min(content.len());
+                let (mut line, _) = utils::line_and_column(content, offset);
+                if is_after_fence(&content[..offset]) {
+                    line -= 1;
+                }
+
+                current = Some(RustBlock {
+                    line,
+                    info: info.to_string(),
+                    code: String::new(),
+                });
+            }
+            Event::Text(ref text) => {
+                if let Some(ref mut block) = current {
+                    block.code.push_str(text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => blocks.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+fn is_after_fence(previous_text: &str) -> bool {
+    let previous_line = previous_text
+        .trim_end_matches(&['\n', '\r'][..])
+        .lines()
+        .last()
+        .unwrap_or("")
+        .trim_start();
+
+    previous_line.starts_with("```") || previous_line.starts_with("~~~")
+}
+
+fn split_attributes(info: &str) -> Vec<&str> {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|attr| attr.trim())
+        .filter(|attr| !attr.is_empty())
+        .collect()
+}
+
+/// Would `rustdoc` treat a code block with this info string as Rust?
+///
+/// This follows `rustdoc`'s rules, where most of its attributes only make a
+/// block Rust if they come before any other language, e.g. `ignore,toml`
+/// is Rust but `toml,ignore` isn't.
+fn is_rust(info: &str) -> bool {
+    let mut seen_rust_attributes = false;
+    let mut seen_other_attributes = false;
+
+    for attr in split_attributes(info) {
+        match attr {
+            "rust" => seen_rust_attributes = true,
+            "ignore" | "no_run" | "should_panic" | "allow_fail" => {
+                seen_rust_attributes = !seen_other_attributes;
+            }
+            "compile_fail" | "test_harness" => {
+                seen_rust_attributes = seen_rust_attributes || !seen_other_attributes;
+            }
+            attr if is_error_code(attr) => {
+                seen_rust_attributes = seen_rust_attributes || !seen_other_attributes;
+            }
+            attr if is_edition(attr) || MDBOOK_ATTRIBUTES.contains(&attr) => {}
+            _ => seen_other_attributes = true,
+        }
+    }
+
+    seen_rust_attributes || !seen_other_attributes
+}
+
+fn is_edition(attr: &str) -> bool {
+    attr.starts_with("edition") && attr["edition".len()..].parse::<u32>().is_ok()
+}
+
+fn is_error_code(attr: &str) -> bool {
+    attr.len() == 5 && attr.starts_with('E') && attr[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_rust_code_blocks_and_their_lines() {
+        let content = "# Chapter\n\n```rust,should_panic\npanic!();\n```\n\n\
+                       ```text\nnot rust\n```\n\n- a list\n\n  ~~~\n  let x = 1;\n  ~~~\n\n\
+                       Indented:\n\n    let y = 2;\n\n```toml,ignore\n[rust]\n```\n\n\
+                       ```ignore\nlet z = 3;\n```\n";
+
+        let got = find_rust_blocks(content);
+
+        assert_eq!(
+            got,
+            vec![
+                RustBlock {
+                    line: 3,
+                    info: String::from("rust,should_panic"),
+                    code: String::from("panic!();\n"),
+                },
+                RustBlock {
+                    line: 13,
+                    info: String::new(),
+                    code: String::from("let x = 1;\n"),
+                },
+                RustBlock {
+                    line: 19,
+                    info: String::new(),
+                    code: String::from("let y = 2;\n"),
+                },
+                RustBlock {
+                    line: 25,
+                    info: String::from("ignore"),
+                    code: String::from("let z = 3;\n"),
+                },
+            ]
+        );
+        assert!(got[3].is_ignored());
+    }
+
+    #[test]
+    fn find_the_lines_blocks_were_written_on() {
+        let written = find_rust_blocks("```rust\n{{#include a.rs}}\n```\n\n```\nlet x = 1;\n```\n");
+        let filled_in = find_rust_blocks("```rust\nfn a() {}\n```\n\n```\nlet x = 1;\n```\n");
+        let added = find_rust_blocks(
+            "# Title\n\n```\nlet y = 2;\n```\n\n```rust\nfn a() {}\n```\n\n\
+             ```\nlet x = 1;\n```\n",
+        );
+
+        assert_eq!(source_line(0, &filled_in, &written), Some(1));
+        assert_eq!(source_line(1, &filled_in, &written), Some(5));
+        assert_eq!(source_line(0, &added, &written), None);
+        assert_eq!(source_line(1, &added, &written), None);
+        assert_eq!(source_line(2, &added, &written), Some(5));
+    }
+
+    #[test]
+    fn recognise_the_same_blocks_as_rustdoc() {
+        let rust = [
+            "",
+            "rust",
+            "rust,ignore",
+            "ignore,toml",
+            "text,rust",
+            "no_run",
+            "compile_fail,E0382",
+            "edition2018",
+            "rust editable",
+            "noplaypen",
+        ];
+        let not_rust = [
+            "text",
+            "toml",
+            "console",
+            "edition",
+            "hbs,editable",
+            "toml,ignore",
+            "text,should_panic",
+            "text,edition2018",
+        ];
+
+        for info in &rust {
+            assert!(is_rust(info), "{:?} should be Rust", info);
+        }
+        for info in &not_rust {
+            assert!(!is_rust(info), "{:?} shouldn't be Rust", info);
+        }
+    }
+}
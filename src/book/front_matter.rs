@@ -0,0 +1,144 @@
+//! Metadata at the top of a chapter's source file.
+
+use std::collections::BTreeMap;
+
+use serde_json::{self, Value};
+use serde_yaml;
+use toml;
+
+use errors::*;
+
+/// The metadata from a chapter's front matter.
+pub type Metadata = BTreeMap<String, Value>;
+
+/// Split a chapter's front matter from the rest of its content.
+///
+/// Front matter is either TOML between a pair of `+++` lines or YAML between a
+/// pair of `---` lines, right at the start of the file. A pair of `---` lines
+/// could just as well be horizontal rules, so a YAML block which can't be
+/// parsed or isn't a table of keys is treated as ordinary markdown, but a table
+/// whose keys or values can't be used as metadata is an error.
+pub fn split_front_matter(content: &str) -> Result<(Metadata, &str)> {
+    if let Some((block, rest)) = find_block(content, "+++") {
+        return Ok((parse_toml(block)?, rest));
+    }
+
+    if let Some((block, rest)) = find_block(content, "---") {
+        if let Ok(value @ serde_yaml::Value::Mapping(_)) = serde_yaml::from_str(block) {
+            let value: Value =
+                serde_json::to_value(value).chain_err(|| "Invalid YAML front matter")?;
+
+            if let Value::Object(map) = value {
+                return Ok((map.into_iter().collect(), rest));
+            }
+        }
+    }
+
+    Ok((Metadata::new(), content))
+}
+
+/// Find the text between a pair of `delimiter` lines at the start of
+/// `content`, returning it and everything after the closing delimiter.
+fn find_block<'a>(content: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let mut lines = content.split('\n');
+    if lines.next()?.trim_end() != delimiter {
+        return None;
+    }
+
+    let start = content.find('\n')? + 1;
+    let mut end = start;
+
+    for line in lines {
+        let next = (end + line.len() + 1).min(content.len());
+
+        if line.trim_end() == delimiter {
+            return Some((&content[start..end], &content[next..]));
+        }
+
+        end = next;
+    }
+
+    None
+}
+
+fn parse_toml(block: &str) -> Result<Metadata> {
+    let table: BTreeMap<String, toml::Value> =
+        toml::from_str(block).chain_err(|| "Invalid TOML front matter")?;
+
+    Ok(table
+        .into_iter()
+        .map(|(key, value)| (key, toml_to_json(value)))
+        .collect())
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect::<serde_json::Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_without_front_matter_is_left_alone() {
+        let inputs = [
+            "# Chapter\n\nSome text\n",
+            "---\n\nJust a horizontal rule\n\n---\n",
+            "+++ not front matter\n",
+            "---\ntitle: never closed\n",
+            "---\nNote: see: here\n---\n\nMore text\n",
+            "---\n- [a list, between rules\n---\n",
+        ];
+
+        for input in &inputs {
+            let (metadata, content) = split_front_matter(input).unwrap();
+
+            assert!(metadata.is_empty(), "{:?}", input);
+            assert_eq!(content, *input);
+        }
+    }
+
+    #[test]
+    fn parse_toml_front_matter() {
+        let src = "+++\r\ntitle = \"Custom\"\r\ndraft = true\r\ntags = [\"a\", \"b\"]\r\n\
+                   date = 2018-09-30T12:00:00Z\r\n+++\r\n# Chapter\r\n";
+
+        let (metadata, content) = split_front_matter(src).unwrap();
+
+        assert_eq!(content, "# Chapter\r\n");
+        assert_eq!(metadata["title"], json!("Custom"));
+        assert_eq!(metadata["draft"], json!(true));
+        assert_eq!(metadata["tags"], json!(["a", "b"]));
+        assert_eq!(metadata["date"], json!("2018-09-30T12:00:00Z"));
+    }
+
+    #[test]
+    fn parse_yaml_front_matter() {
+        let src = "---\ntitle: Custom\nauthor:\n  name: Someone\n---\n# Chapter\n";
+
+        let (metadata, content) = split_front_matter(src).unwrap();
+
+        assert_eq!(content, "# Chapter\n");
+        assert_eq!(metadata["title"], json!("Custom"));
+        assert_eq!(metadata["author"], json!({ "name": "Someone" }));
+    }
+
+    #[test]
+    fn invalid_front_matter_is_an_error() {
+        assert!(split_front_matter("+++\ntitle = \n+++\n").is_err());
+        assert!(split_front_matter("---\n? [complex, key]\n: value\n---\n").is_err());
+    }
+}
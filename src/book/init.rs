@@ -7,6 +7,7 @@ use super::MDBook;
 use config::Config;
 use errors::*;
 use theme;
+use utils::fs::CACHE_DIR;
 
 /// A helper for setting up a new book and its directory structure.
 #[derive(Debug, Clone, PartialEq)]
@@ -164,6 +165,7 @@ impl BookBuilder {
         let mut f = File::create(self.root.join(".gitignore"))?;
 
         writeln!(f, "{}", self.config.build.build_dir.display())?;
+        writeln!(f, "{}", CACHE_DIR)?;
 
         Ok(())
     }
@@ -6,23 +6,31 @@
 //! [1]: ../index.html
 
 mod book;
+mod check;
+mod doctest;
+mod front_matter;
 mod init;
 mod summary;
 
-pub use self::book::{load_book, Book, BookItem, BookItems, Chapter};
+pub use self::book::{load_book, load_translation, Book, BookItem, BookItems, Chapter};
+pub use self::check::{check_links, BrokenLink, BrokenLinkReason};
+pub use self::front_matter::Metadata;
 pub use self::init::BookBuilder;
 pub use self::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
 
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::Command;
-use tempfile::Builder as TempFileBuilder;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use toml::Value;
 
+use self::doctest::test_book;
+
 use errors::*;
 use preprocess::{
-    CmdPreprocessor, IndexPreprocessor, LinkPreprocessor, Preprocessor, PreprocessorContext,
+    CmdPreprocessor, DiagramPreprocessor, GlossaryPreprocessor, IndexPreprocessor,
+    LinkPreprocessor, Preprocessor, PreprocessorContext,
 };
+#[cfg(feature = "epub")]
+use renderer::EpubRenderer;
 use renderer::{CmdRenderer, HtmlHandlebars, RenderContext, Renderer};
 use utils;
 
@@ -34,8 +42,12 @@ pub struct MDBook {
     pub root: PathBuf,
     /// The configuration used to tweak now a book is built.
     pub config: Config,
-    /// A representation of the book's contents in memory.
+    /// A representation of the book's contents in memory. For multilingual
+    /// books, this is the default language.
     pub book: Book,
+    /// The other languages a multilingual book has been translated into,
+    /// keyed by language code.
+    pub translations: BTreeMap<String, Book>,
     renderers: Vec<Box<Renderer>>,
 
     /// List of pre-processors to be run on the book
@@ -80,8 +92,12 @@ impl MDBook {
     pub fn load_with_config<P: Into<PathBuf>>(book_root: P, config: Config) -> Result<MDBook> {
         let root = book_root.into();
 
-        let src_dir = root.join(&config.book.src);
-        let book = book::load_book(&src_dir, &config.build)?;
+        let (book, translations) = if config.book.multilingual {
+            load_translations(&root, &config)?
+        } else {
+            let src_dir = root.join(&config.book.src);
+            (book::load_book(&src_dir, &config.build)?, BTreeMap::new())
+        };
 
         let renderers = determine_renderers(&config);
         let preprocessors = determine_preprocessors(&config)?;
@@ -90,6 +106,7 @@ impl MDBook {
             root,
             config,
             book,
+            translations,
             renderers,
             preprocessors,
         })
@@ -110,6 +127,7 @@ impl MDBook {
     ///     match *item {
     ///         BookItem::Chapter(ref chapter) => {},
     ///         BookItem::Separator => {},
+    ///         BookItem::PartTitle(ref title) => {},
     ///     }
     /// }
     ///
@@ -152,38 +170,62 @@ impl MDBook {
         info!("Book building has started");
 
         for renderer in &self.renderers {
-            self.execute_build_process(&**renderer)?;
+            // The default language goes in the root of the build directory
+            // and each translation gets its own sub-directory. That means the
+            // default language needs to go first, otherwise clearing its
+            // build directory would remove the translations.
+            let build_dir = self.build_dir_for(renderer.name());
+            let config = self.default_language_config()?;
+            self.execute_build_process(&**renderer, &self.book, &config, build_dir.clone())?;
+
+            for (code, translation) in &self.translations {
+                info!("Building the \"{}\" translation", code);
+                let config = self.config.for_language(code)?;
+                let build_dir = build_dir.join(code);
+                self.execute_build_process(&**renderer, translation, &config, build_dir)?;
+            }
         }
 
         Ok(())
     }
 
     /// Run the entire build process for a particular `Renderer`.
-    fn execute_build_process(&self, renderer: &Renderer) -> Result<()> {
-        let mut preprocessed_book = self.book.clone();
+    fn execute_build_process(
+        &self,
+        renderer: &Renderer,
+        book: &Book,
+        config: &Config,
+        build_dir: PathBuf,
+    ) -> Result<()> {
+        let mut preprocessed_book = book.clone();
         let preprocess_ctx = PreprocessorContext::new(
             self.root.clone(),
-            self.config.clone(),
+            config.clone(),
             renderer.name().to_string(),
         );
 
         for preprocessor in &self.preprocessors {
-            if preprocessor_should_run(&**preprocessor, renderer, &self.config) {
+            if preprocessor_should_run(&**preprocessor, renderer, config) {
                 debug!("Running the {} preprocessor.", preprocessor.name());
                 preprocessed_book = preprocessor.run(&preprocess_ctx, preprocessed_book)?;
             }
         }
 
         info!("Running the {} backend", renderer.name());
-        self.render(&preprocessed_book, renderer)?;
+        self.render(&preprocessed_book, renderer, config, build_dir)?;
 
         Ok(())
     }
 
-    fn render(&self, preprocessed_book: &Book, renderer: &Renderer) -> Result<()> {
+    fn render(
+        &self,
+        preprocessed_book: &Book,
+        renderer: &Renderer,
+        config: &Config,
+        build_dir: PathBuf,
+    ) -> Result<()> {
         let name = renderer.name();
-        let build_dir = self.build_dir_for(name);
-        if build_dir.exists() {
+        if build_dir.exists() && !renderer.supports_incremental_builds() {
             debug!(
                 "Cleaning build dir for the \"{}\" renderer ({})",
                 name,
@@ -197,7 +239,7 @@ impl MDBook {
         let render_context = RenderContext::new(
             self.root.clone(),
             preprocessed_book.clone(),
-            self.config.clone(),
+            config.clone(),
             build_dir,
         );
 
@@ -221,51 +263,86 @@ impl MDBook {
     }
 
     /// Run `rustdoc` tests on the book, linking against the provided libraries.
+    ///
+    /// The book is preprocessed first, so the code which gets tested is what
+    /// readers see. Each chapter's results are logged, naming the line of
+    /// every code block which failed, unless a preprocessor added the block.
     pub fn test(&mut self, library_paths: Vec<&str>) -> Result<()> {
-        let library_args: Vec<&str> = (0..library_paths.len())
-            .map(|_| "-L")
-            .zip(library_paths.into_iter())
-            .flat_map(|x| vec![x.0, x.1])
-            .collect();
-
-        let temp_dir = TempFileBuilder::new().prefix("mdbook-").tempdir()?;
-
         // FIXME: Is "test" the proper renderer name to use here?
+        let config = self.default_language_config()?;
         let preprocess_context =
-            PreprocessorContext::new(self.root.clone(), self.config.clone(), "test".to_string());
-
-        let book = LinkPreprocessor::new().run(&preprocess_context, self.book.clone())?;
-        // Index Preprocessor is disabled so that chapter paths continue to point to the
-        // actual markdown files.
-
-        for item in book.iter() {
-            if let BookItem::Chapter(ref ch) = *item {
-                if !ch.path.as_os_str().is_empty() {
-                    let path = self.source_dir().join(&ch.path);
-                    let content = utils::fs::file_to_string(&path)?;
-                    info!("Testing file: {:?}", path);
-
-                    // write preprocessed file to tempdir
-                    let path = temp_dir.path().join(&ch.path);
-                    let mut tmpf = utils::fs::create_file(&path)?;
-                    tmpf.write_all(content.as_bytes())?;
-
-                    let output = Command::new("rustdoc")
-                        .arg(&path)
-                        .arg("--test")
-                        .args(&library_args)
-                        .output()?;
-
-                    if !output.status.success() {
-                        bail!(ErrorKind::Subprocess(
-                            "Rustdoc returned an error".to_string(),
-                            output
-                        ));
-                    }
+            PreprocessorContext::new(self.root.clone(), config.clone(), "test".to_string());
+
+        let mut book = self.book.clone();
+        for preprocessor in &self.preprocessors {
+            // Index Preprocessor is disabled so that chapter paths continue to point to the
+            // actual markdown files.
+            if preprocessor.name() == IndexPreprocessor::NAME {
+                continue;
+            }
+
+            if preprocessor_should_run_for(&**preprocessor, "test", &config) {
+                debug!("Running the {} preprocessor.", preprocessor.name());
+                book = preprocessor.run(&preprocess_context, book)?;
+            }
+        }
+
+        let results = test_book(&book, &self.book, &self.root, &config, &library_paths)?;
+        let mut failed = 0;
+
+        for chapter in results.iter().filter(|chapter| chapter.total() > 0) {
+            info!(
+                "Tested \"{}\" ({}): {} passed, {} failed, {} ignored",
+                chapter.name,
+                chapter.path.display(),
+                chapter.passed,
+                chapter.failures.len(),
+                chapter.ignored
+            );
+
+            for failure in &chapter.failures {
+                match failure.line {
+                    Some(line) => error!(
+                        "{}:{}: a code block in \"{}\" failed\n{}",
+                        chapter.path.display(),
+                        line,
+                        chapter.name,
+                        failure.output
+                    ),
+                    None => error!(
+                        "{}: a code block added to \"{}\" by a preprocessor failed\n{}",
+                        chapter.path.display(),
+                        chapter.name,
+                        failure.output
+                    ),
                 }
             }
+            failed += chapter.failures.len();
+        }
+
+        match failed {
+            0 => Ok(()),
+            1 => bail!("1 code block failed"),
+            n => bail!("{} code blocks failed", n),
+        }
+    }
+
+    /// Check every chapter for relative links to chapters or `#fragment`s
+    /// which don't exist, logging each one and returning an error if any
+    /// were found.
+    pub fn check(&self) -> Result<()> {
+        let html_config = self.config.html_config().unwrap_or_default();
+        let broken_links = check_links(&self.book, &html_config);
+
+        for link in &broken_links {
+            error!("{}", link);
+        }
+
+        match broken_links.len() {
+            0 => Ok(()),
+            1 => bail!("Found 1 broken link"),
+            n => bail!("Found {} broken links", n),
         }
-        Ok(())
     }
 
     /// The logic for determining where a backend should put its build
@@ -302,6 +379,15 @@ impl MDBook {
         }
     }
 
+    /// Get the configuration used when building the book's default language.
+    fn default_language_config(&self) -> Result<Config> {
+        if self.config.book.multilingual {
+            self.config.for_language(&self.config.default_language()?)
+        } else {
+            Ok(self.config.clone())
+        }
+    }
+
     /// Get the directory containing this book's source files.
     pub fn source_dir(&self) -> PathBuf {
         self.root.join(&self.config.book.src)
@@ -316,12 +402,45 @@ impl MDBook {
     }
 }
 
+/// Load each language of a multilingual book from its own sub-directory of
+/// `src/`, returning the default language and a map of its translations.
+fn load_translations(root: &Path, config: &Config) -> Result<(Book, BTreeMap<String, Book>)> {
+    let languages = config.languages()?;
+    if languages.is_empty() {
+        bail!("A multilingual book needs at least one `[language.<code>]` table in book.toml");
+    }
+
+    let default = config.default_language()?;
+    let default_src = root.join(&config.for_language(&default)?.book.src);
+    let book = book::load_book(&default_src, &config.build)?;
+
+    let mut translations = BTreeMap::new();
+    for code in languages.keys().filter(|&code| *code != default) {
+        debug!("Loading the \"{}\" translation", code);
+        let src_dir = root.join(&config.for_language(code)?.book.src);
+        let translation = book::load_translation(&src_dir, &default_src, &config.build)
+            .chain_err(|| format!("Unable to load the \"{}\" translation", code))?;
+        translations.insert(code.clone(), translation);
+    }
+
+    Ok((book, translations))
+}
+
 /// Look at the `Config` and try to figure out what renderers to use.
 fn determine_renderers(config: &Config) -> Vec<Box<Renderer>> {
     let mut renderers: Vec<Box<Renderer>> = Vec::new();
 
     if let Some(output_table) = config.get("output").and_then(|o| o.as_table()) {
         for (key, table) in output_table.iter() {
+            // so does the "epub" backend, when it's been compiled in
+            #[cfg(feature = "epub")]
+            {
+                if key == "epub" {
+                    renderers.push(Box::new(EpubRenderer::new()));
+                    continue;
+                }
+            }
+
             // the "html" backend has its own Renderer
             if key == "html" {
                 renderers.push(Box::new(HtmlHandlebars::new()));
@@ -365,6 +484,8 @@ fn determine_preprocessors(config: &Config) -> Result<Vec<Box<Preprocessor>>> {
             match key.as_ref() {
                 "links" => preprocessors.push(Box::new(LinkPreprocessor::new())),
                 "index" => preprocessors.push(Box::new(IndexPreprocessor::new())),
+                "diagrams" => preprocessors.push(Box::new(DiagramPreprocessor::new())),
+                "glossary" => preprocessors.push(Box::new(GlossaryPreprocessor::new())),
                 name => preprocessors.push(interpret_custom_preprocessor(
                     name,
                     &preprocessor_table[name],
@@ -406,13 +527,22 @@ fn interpret_custom_renderer(key: &str, table: &Value) -> Box<CmdRenderer> {
 /// The `build.use-default-preprocessors` config option can be used to ensure
 /// default preprocessors always run if they support the renderer.
 fn preprocessor_should_run(preprocessor: &Preprocessor, renderer: &Renderer, cfg: &Config) -> bool {
+    preprocessor_should_run_for(preprocessor, renderer.name(), cfg)
+}
+
+/// Like `preprocessor_should_run()`, for things like `mdbook test` which
+/// don't have a `Renderer`.
+fn preprocessor_should_run_for(
+    preprocessor: &Preprocessor,
+    renderer_name: &str,
+    cfg: &Config,
+) -> bool {
     // default preprocessors should be run by default (if supported)
     if cfg.build.use_default_preprocessors && is_default_preprocessor(preprocessor) {
-        return preprocessor.supports_renderer(renderer.name());
+        return preprocessor.supports_renderer(renderer_name);
     }
 
     let key = format!("preprocessor.{}.renderers", preprocessor.name());
-    let renderer_name = renderer.name();
 
     if let Some(Value::Array(ref explicit_renderers)) = cfg.get(&key) {
         return explicit_renderers
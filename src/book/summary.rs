@@ -37,6 +37,13 @@ use std::path::{Path, PathBuf};
 /// You can either use - or * to indicate a numbered chapter, the parser doesn't
 /// care but you'll probably want to stay consistent.
 ///
+/// **Part Title:** Headers (`# Part I`) between the numbered chapters split
+/// them up into parts, each with its own title.
+///
+/// ```markdown
+/// # Title of the part
+/// ```
+///
 /// **Suffix Chapter:** After the numbered chapters you can add a couple of
 /// non-numbered chapters. They are the same as prefix chapters but come after
 /// the numbered chapters instead of before.
@@ -88,6 +95,12 @@ impl Link {
             nested_items: Vec::new(),
         }
     }
+
+    /// Is this a draft chapter (e.g. `- [Future chapter]()`) which doesn't
+    /// have a source file yet?
+    pub fn is_draft(&self) -> bool {
+        self.location.as_os_str().is_empty()
+    }
 }
 
 impl Default for Link {
@@ -101,13 +114,17 @@ impl Default for Link {
     }
 }
 
-/// An item in `SUMMARY.md` which could be either a separator or a `Link`.
+/// An item in `SUMMARY.md` which could be either a separator, a part title or
+/// a `Link`.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SummaryItem {
     /// A link to a chapter.
     Link(Link),
     /// A separator (`---`).
     Separator,
+    /// The title of a part of the book (`# Part I`), which groups together the
+    /// numbered chapters after it.
+    PartTitle(String),
 }
 
 impl SummaryItem {
@@ -139,7 +156,9 @@ impl From<Link> for SummaryItem {
 ///                     | EPSILON
 /// prefix_chapters   ::= item*
 /// suffix_chapters   ::= item*
-/// numbered_chapters ::= dotted_item+
+/// numbered_chapters ::= part_title? dotted_item+
+///                     | numbered_chapters separator? part_title? dotted_item+
+/// part_title        ::= "# " TEXT
 /// dotted_item       ::= INDENT* DOT_POINT item
 /// item              ::= link
 ///                     | separator
@@ -154,6 +173,8 @@ impl From<Link> for SummaryItem {
 struct SummaryParser<'a> {
     src: &'a str,
     stream: pulldown_cmark::Parser<'a>,
+    /// An event which was read too early and needs to be handled again.
+    back: Option<Event<'a>>,
 }
 
 /// Reads `Events` from the provided stream until the corresponding
@@ -201,6 +222,7 @@ impl<'a> SummaryParser<'a> {
         SummaryParser {
             src: text,
             stream: pulldown_parser,
+            back: None,
         }
     }
 
@@ -250,6 +272,12 @@ impl<'a> SummaryParser<'a> {
 
         loop {
             match self.next_event() {
+                Some(Event::Start(Tag::Header(1))) if is_prefix => {
+                    // the numbered chapters start with a part title, so let
+                    // the numbered chapter parser handle it
+                    self.back = Some(Event::Start(Tag::Header(1)));
+                    break;
+                }
                 Some(Event::Start(Tag::List(..))) => {
                     if is_prefix {
                         // we've finished prefix chapters and are at the start
@@ -276,48 +304,56 @@ impl<'a> SummaryParser<'a> {
         let link_content = collect_events!(self.stream, end Tag::Link(..));
         let name = stringify_events(link_content);
 
-        if href.is_empty() {
-            Err(self.parse_error("You can't have an empty link."))
-        } else {
-            Ok(Link {
-                name,
-                location: PathBuf::from(href.to_string()),
-                number: None,
-                nested_items: Vec::new(),
-            })
-        }
+        // an empty link location marks a draft chapter
+        Ok(Link {
+            name,
+            location: PathBuf::from(href),
+            number: None,
+            nested_items: Vec::new(),
+        })
     }
 
     /// Parse the numbered chapters. This assumes the opening list tag has
-    /// already been consumed by a previous parser.
+    /// already been consumed by a previous parser, unless the numbered
+    /// chapters start with a part title.
     fn parse_numbered(&mut self) -> Result<Vec<SummaryItem>> {
         let mut items = Vec::new();
         let mut root_items = 0;
         let root_number = SectionNumber::default();
+        let mut in_list = self.back.is_none();
 
         // we need to do this funny loop-match-if-let dance because a rule will
         // close off any currently running list. Therefore we try to read the
         // list items before the rule, then if we encounter a rule we'll add a
         // separator and try to resume parsing numbered chapters if we start a
-        // list immediately afterwards.
+        // list immediately afterwards. Part titles work the same way.
         //
         // If you can think of a better way to do this then please make a PR :)
 
         loop {
-            let mut bunch_of_items = self.parse_nested_numbered(&root_number)?;
-
-            // if we've resumed after something like a rule the root sections
-            // will be numbered from 1. We need to manually go back and update
-            // them
-            update_section_numbers(&mut bunch_of_items, 0, root_items);
-            root_items += bunch_of_items.len() as u32;
-            items.extend(bunch_of_items);
+            if in_list {
+                let mut bunch_of_items = self.parse_nested_numbered(&root_number)?;
+
+                // if we've resumed after something like a rule the root
+                // sections will be numbered from 1. We need to manually go
+                // back and update them
+                update_section_numbers(&mut bunch_of_items, 0, root_items);
+                root_items += bunch_of_items.len() as u32;
+                items.extend(bunch_of_items);
+            }
 
             match self.next_event() {
                 Some(Event::Start(Tag::Paragraph)) => {
                     // we're starting the suffix chapters
                     break;
                 }
+                Some(Event::Start(Tag::Header(1))) => {
+                    let tags = collect_events!(self.stream, end Tag::Header(1));
+                    let title = stringify_events(tags);
+                    trace!("Found part title: {}", title);
+
+                    items.push(SummaryItem::PartTitle(title));
+                }
                 Some(Event::Start(other_tag)) => {
                     if other_tag == Tag::Rule {
                         items.push(SummaryItem::Separator);
@@ -330,15 +366,10 @@ impl<'a> SummaryParser<'a> {
                             break;
                         }
                     }
-
-                    if let Some(Event::Start(Tag::List(..))) = self.next_event() {
-                        continue;
-                    } else {
-                        break;
-                    }
                 }
                 Some(_) => {
                     // something else... ignore
+                    in_list = true;
                     continue;
                 }
                 None => {
@@ -346,13 +377,23 @@ impl<'a> SummaryParser<'a> {
                     break;
                 }
             }
+
+            match self.next_event() {
+                Some(Event::Start(Tag::List(..))) => in_list = true,
+                Some(event @ Event::Start(Tag::Header(1))) => {
+                    // a part title straight after a rule or another part title
+                    self.back = Some(event);
+                    in_list = false;
+                }
+                _ => break,
+            }
         }
 
         Ok(items)
     }
 
     fn next_event(&mut self) -> Option<Event<'a>> {
-        let next = self.stream.next();
+        let next = self.back.take().or_else(|| self.stream.next());
         trace!("Next event: {:?}", next);
 
         next
@@ -442,6 +483,35 @@ impl<'a> SummaryParser<'a> {
     }
 }
 
+/// Number the chapters in each part of the book from 1, instead of carrying on
+/// from the previous part.
+pub fn restart_numbering_per_part(items: &mut [SummaryItem]) {
+    let mut root_items = 0;
+
+    for item in items {
+        match *item {
+            SummaryItem::PartTitle(_) => root_items = 0,
+            SummaryItem::Link(ref mut link) => {
+                root_items += 1;
+                set_root_number(link, root_items);
+            }
+            SummaryItem::Separator => {}
+        }
+    }
+}
+
+fn set_root_number(link: &mut Link, root_number: u32) {
+    if let Some(ref mut number) = link.number {
+        number.0[0] = root_number;
+    }
+
+    for item in &mut link.nested_items {
+        if let SummaryItem::Link(ref mut nested) = *item {
+            set_root_number(nested, root_number);
+        }
+    }
+}
+
 fn update_section_numbers(sections: &mut [SummaryItem], level: usize, by: u32) {
     for section in sections {
         if let SummaryItem::Link(ref mut link) = *section {
@@ -718,13 +788,36 @@ mod tests {
     }
 
     #[test]
-    fn an_empty_link_location_is_an_error() {
-        let src = "- [Empty]()\n";
+    fn an_empty_link_location_is_a_draft_chapter() {
+        let src = "- [First](./first.md)\n- [Future chapter]()\n    - [Nested draft]()\n";
+        let should_be = vec![
+            SummaryItem::Link(Link {
+                name: String::from("First"),
+                location: PathBuf::from("./first.md"),
+                number: Some(SectionNumber(vec![1])),
+                nested_items: Vec::new(),
+            }),
+            SummaryItem::Link(Link {
+                name: String::from("Future chapter"),
+                location: PathBuf::new(),
+                number: Some(SectionNumber(vec![2])),
+                nested_items: vec![SummaryItem::Link(Link {
+                    name: String::from("Nested draft"),
+                    location: PathBuf::new(),
+                    number: Some(SectionNumber(vec![2, 1])),
+                    nested_items: Vec::new(),
+                })],
+            }),
+        ];
+
         let mut parser = SummaryParser::new(src);
         parser.stream.next();
 
-        let got = parser.parse_numbered();
-        assert!(got.is_err());
+        let mut got = parser.parse_numbered().unwrap();
+
+        assert_eq!(got, should_be);
+        assert!(!got[0].maybe_link_mut().unwrap().is_draft());
+        assert!(got[1].maybe_link_mut().unwrap().is_draft());
     }
 
     /// Regression test for https://github.com/rust-lang-nursery/mdBook/issues/779
@@ -763,4 +856,66 @@ mod tests {
 
         assert_eq!(got, should_be);
     }
+
+    fn numbered_link(name: &str, location: &str, number: Vec<u32>) -> SummaryItem {
+        SummaryItem::Link(Link {
+            name: String::from(name),
+            location: PathBuf::from(location),
+            number: Some(SectionNumber(number)),
+            nested_items: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn parse_part_titles_in_the_numbered_chapters() {
+        let src = "# Summary\n\n[Intro](./intro.md)\n\n# Part I\n\n- [First](./first.md)\n\
+                   \n---\n\n# Part II\n\n- [Second](./second.md)\n\n# Part III\n\
+                   \n# Part IV\n\n- [Third](./third.md)\n\n[Conclusion](./end.md)\n";
+        let should_be = vec![
+            SummaryItem::PartTitle(String::from("Part I")),
+            numbered_link("First", "./first.md", vec![1]),
+            SummaryItem::Separator,
+            SummaryItem::PartTitle(String::from("Part II")),
+            numbered_link("Second", "./second.md", vec![2]),
+            SummaryItem::PartTitle(String::from("Part III")),
+            SummaryItem::PartTitle(String::from("Part IV")),
+            numbered_link("Third", "./third.md", vec![3]),
+        ];
+
+        let got = parse_summary(src).unwrap();
+
+        assert_eq!(got.title, Some(String::from("Summary")));
+        assert_eq!(got.prefix_chapters.len(), 1);
+        assert_eq!(got.numbered_chapters, should_be);
+        assert_eq!(got.suffix_chapters.len(), 1);
+    }
+
+    #[test]
+    fn restart_the_numbering_for_each_part() {
+        let mut second = Link::new("Second", "./second.md");
+        second.number = Some(SectionNumber(vec![2]));
+        second.nested_items = vec![numbered_link("Nested", "./nested.md", vec![2, 1])];
+
+        let mut items = vec![
+            numbered_link("First", "./first.md", vec![1]),
+            SummaryItem::PartTitle(String::from("Part II")),
+            SummaryItem::Link(second),
+            SummaryItem::Separator,
+            numbered_link("Third", "./third.md", vec![3]),
+        ];
+
+        restart_numbering_per_part(&mut items);
+
+        let mut second = Link::new("Second", "./second.md");
+        second.number = Some(SectionNumber(vec![1]));
+        second.nested_items = vec![numbered_link("Nested", "./nested.md", vec![1, 1])];
+        let should_be = vec![
+            numbered_link("First", "./first.md", vec![1]),
+            SummaryItem::PartTitle(String::from("Part II")),
+            SummaryItem::Link(second),
+            SummaryItem::Separator,
+            numbered_link("Third", "./third.md", vec![2]),
+        ];
+        assert_eq!(items, should_be);
+    }
 }
@@ -1,5 +1,5 @@
 use clap::{App, ArgMatches, SubCommand};
-use mdbook::errors::Result;
+use mdbook::errors::{Result, ResultExt};
 use mdbook::MDBook;
 use {get_book_dir, open};
 
@@ -15,6 +15,10 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             "[dir] 'Root directory for the book{n}\
              (Defaults to the Current Directory when omitted)'",
         ).arg_from_usage("-o, --open 'Opens the compiled book in a web browser'")
+        .arg_from_usage(
+            "-j, --jobs=[jobs] 'The number of chapters to render in parallel{n}\
+             If omitted, mdBook uses build.jobs from book.toml or the number of CPUs.'",
+        )
 }
 
 // Build command implementation
@@ -26,6 +30,13 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
         book.config.build.build_dir = dest_dir.into();
     }
 
+    if let Some(jobs) = args.value_of("jobs") {
+        let jobs = jobs
+            .parse()
+            .chain_err(|| format!("\"{}\" isn't a valid number of jobs", jobs))?;
+        book.config.build.jobs = Some(jobs);
+    }
+
     book.build()?;
 
     if args.is_present("open") {
@@ -1,6 +1,7 @@
 //! Subcommand modules for the `mdbook` binary.
 
 pub mod build;
+pub mod check;
 pub mod clean;
 pub mod init;
 #[cfg(feature = "serve")]
@@ -2,6 +2,7 @@ extern crate iron;
 extern crate staticfile;
 extern crate ws;
 
+use self::iron::headers::ContentType;
 use self::iron::{
     status, AfterMiddleware, Chain, Iron, IronError, IronResult, Request, Response, Set,
 };
@@ -12,9 +13,13 @@ use mdbook::errors::*;
 use mdbook::utils;
 use mdbook::MDBook;
 use std;
+use std::fs;
+use std::path::PathBuf;
 use {get_book_dir, open};
 
-struct ErrorRecover;
+struct ErrorRecover {
+    page_404: PathBuf,
+}
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -85,6 +90,9 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     let livereload_url = format!("ws://{}:{}", public_address, ws_port);
     book.config
         .set("output.html.livereload-url", &livereload_url)?;
+    // the book is served from the root of the server, which is where the
+    // 404 page needs to find the book's assets
+    book.config.set("output.html.site-url", "/")?;
 
     if let Some(dest_dir) = args.value_of("dest-dir") {
         book.config.build.build_dir = dest_dir.into();
@@ -92,8 +100,11 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
 
     book.build()?;
 
-    let mut chain = Chain::new(staticfile::Static::new(book.build_dir_for("html")));
-    chain.link_after(ErrorRecover);
+    let build_dir = book.build_dir_for("html");
+    let mut chain = Chain::new(staticfile::Static::new(&build_dir));
+    chain.link_after(ErrorRecover {
+        page_404: build_dir.join("404.html"),
+    });
     let _iron = Iron::new(chain)
         .http(&*address)
         .chain_err(|| "Unable to launch the server")?;
@@ -125,6 +136,7 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
             .and_then(|mut b| {
                 b.config
                     .set("output.html.livereload-url", &livereload_url)?;
+                b.config.set("output.html.site-url", "/")?;
                 Ok(b)
             }).and_then(|b| b.build());
 
@@ -142,8 +154,16 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
 impl AfterMiddleware for ErrorRecover {
     fn catch(&self, _: &mut Request, err: IronError) -> IronResult<Response> {
         match err.response.status {
-            // each error will result in 404 response
-            Some(_) => Ok(err.response.set(status::NotFound)),
+            // each error will result in 404 response, showing the book's 404
+            // page if it has one
+            Some(_) => {
+                let mut response = err.response.set(status::NotFound);
+                if let Ok(page) = fs::read(&self.page_404) {
+                    response = response.set(page);
+                    response.headers.set(ContentType::html());
+                }
+                Ok(response)
+            }
             _ => Err(err),
         }
     }
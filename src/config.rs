@@ -2,8 +2,9 @@
 //!
 //! The main entrypoint of the `config` module is the `Config` struct. This acts
 //! essentially as a bag of configuration information, with a couple
-//! pre-determined tables (`BookConfig` and `BuildConfig`) as well as support
-//! for arbitrary data which is exposed to plugins and alternate backends.
+//! pre-determined tables (`BookConfig`, `BuildConfig` and `RustConfig`) as well
+//! as support for arbitrary data which is exposed to plugins and alternate
+//! backends.
 //!
 //!
 //! # Examples
@@ -52,7 +53,9 @@
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::{self, Display, Formatter};
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -72,6 +75,8 @@ pub struct Config {
     pub book: BookConfig,
     /// Information about the build environment.
     pub build: BuildConfig,
+    /// Information about Rust language support.
+    pub rust: RustConfig,
     rest: Value,
 }
 
@@ -173,6 +178,80 @@ impl Config {
         self.get_deserialized("output.html").ok()
     }
 
+    /// Get the configuration used by `mdbook test` (its `[test]` table).
+    pub fn test_config(&self) -> Result<TestConfig> {
+        match self.get("test") {
+            Some(_) => self
+                .get_deserialized("test")
+                .chain_err(|| "Invalid configuration in the [test] table"),
+            None => Ok(TestConfig::default()),
+        }
+    }
+
+    /// Get the languages a multilingual book is available in (i.e. its
+    /// `[language.<code>]` tables), keyed by language code.
+    pub fn languages(&self) -> Result<BTreeMap<String, LanguageConfig>> {
+        match self.get("language") {
+            Some(_) => self
+                .get_deserialized("language")
+                .chain_err(|| "Invalid configuration in the [language] tables"),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// The code for the language a book is written in. For multilingual books,
+    /// this is the language untranslated chapters will fall back to.
+    pub fn default_language(&self) -> Result<String> {
+        let languages = self.languages()?;
+
+        Ok(languages
+            .iter()
+            .find(|&(_, lang)| lang.default)
+            .map(|(code, _)| code.clone())
+            .or_else(|| self.book.language.clone())
+            .or_else(|| languages.keys().next().cloned())
+            .unwrap_or_else(|| String::from("en")))
+    }
+
+    /// Get the configuration used when building a multilingual book's `code`
+    /// translation.
+    ///
+    /// The translation's sources are expected to live in a sub-directory of
+    /// `book.src` named after the language, and any title or description in
+    /// its `[language.<code>]` table will override the book's. Translations are
+    /// published in a sub-directory named after the language too, so that's
+    /// added to `output.html.site-url`.
+    pub fn for_language(&self, code: &str) -> Result<Config> {
+        let default = self.default_language()?;
+        let lang = self.languages()?.remove(code).unwrap_or_default();
+        let mut cfg = self.clone();
+
+        cfg.book.src = self.book.src.join(code);
+        cfg.book.language = Some(code.to_string());
+        if lang.title.is_some() {
+            cfg.book.title = lang.title;
+        }
+        if lang.description.is_some() {
+            cfg.book.description = lang.description;
+        }
+        let site_url = match self.get("output.html.site-url") {
+            Some(Value::String(site_url)) if code != default => Some(site_url),
+            _ => None,
+        };
+        if let Some(site_url) = site_url {
+            let site_url = format!("{}/{}/", site_url.trim_end_matches('/'), code);
+            cfg.set("output.html.site-url", site_url)
+                .expect("A string is always representable as TOML");
+        }
+
+        // remember which language is the default, now `book.language` has
+        // been overwritten
+        cfg.set(format!("language.{}.default", default), true)
+            .expect("A bool is always representable as TOML");
+
+        Ok(cfg)
+    }
+
     /// Convenience function to fetch a value from the config and deserialize it
     /// into some arbitrary type.
     pub fn get_deserialized<'de, T: Deserialize<'de>, S: AsRef<str>>(&self, name: S) -> Result<T> {
@@ -202,6 +281,8 @@ impl Config {
             self.book.update_value(&index[5..], value);
         } else if index.starts_with("build.") {
             self.build.update_value(&index[6..], value);
+        } else if index.starts_with("rust.") {
+            self.rust.update_value(&index[5..], value);
         } else {
             self.rest.insert(index, value)?;
         }
@@ -260,6 +341,7 @@ impl Default for Config {
         Config {
             book: BookConfig::default(),
             build: BuildConfig::default(),
+            rust: RustConfig::default(),
             rest: Value::Table(Table::default()),
         }
     }
@@ -300,9 +382,20 @@ impl<'de> Deserialize<'de> for Config {
             .and_then(|value| value.try_into().ok())
             .unwrap_or_default();
 
+        // unlike the other tables, a mistake here would silently change how
+        // code is compiled, so it's an error
+        let rust: RustConfig = match table.remove("rust") {
+            Some(value) => value.try_into().map_err(|e| {
+                use serde::de::Error;
+                D::Error::custom(format!("Invalid configuration in the [rust] table: {}", e))
+            })?,
+            None => RustConfig::default(),
+        };
+
         Ok(Config {
             book,
             build,
+            rust,
             rest: Value::Table(table),
         })
     }
@@ -321,7 +414,15 @@ impl Serialize for Config {
             }
         };
 
+        let rust_config = match Value::try_from(self.rust.clone()) {
+            Ok(cfg) => cfg,
+            Err(_) => {
+                return Err(S::Error::custom("Unable to serialize the RustConfig"));
+            }
+        };
+
         table.insert("book", book_config).expect("unreachable");
+        table.insert("rust", rust_config).expect("unreachable");
         table.serialize(s)
     }
 }
@@ -371,6 +472,8 @@ pub struct BookConfig {
     pub src: PathBuf,
     /// Does this book support more than one language?
     pub multilingual: bool,
+    /// The language the book is written in (e.g. `en`). Defaults to English.
+    pub language: Option<String>,
 }
 
 impl Default for BookConfig {
@@ -381,10 +484,27 @@ impl Default for BookConfig {
             description: None,
             src: PathBuf::from("src"),
             multilingual: false,
+            language: None,
         }
     }
 }
 
+/// Configuration for one of the languages a multilingual book is written in,
+/// from its `[language.<code>]` table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LanguageConfig {
+    /// The language's name, as shown in the language switcher (e.g.
+    /// `English` or `日本語`). Defaults to the language code.
+    pub name: Option<String>,
+    /// Is this the language untranslated chapters fall back to?
+    pub default: bool,
+    /// The book's title in this language.
+    pub title: Option<String>,
+    /// The book's description in this language.
+    pub description: Option<String>,
+}
+
 /// Configuration for the build procedure.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -397,6 +517,14 @@ pub struct BuildConfig {
     /// Should the default preprocessors always be used when they are
     /// compatible with the renderer?
     pub use_default_preprocessors: bool,
+    /// How many chapters to render at once. Defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Should links which can't be expanded (e.g. an `{{#include}}` of a
+    /// missing file) fail the build instead of being left in the page?
+    pub strict: bool,
+    /// Should each part of the book (started by a `# Part title` in
+    /// `SUMMARY.md`) number its chapters from 1?
+    pub restart_numbering_per_part: bool,
 }
 
 impl Default for BuildConfig {
@@ -405,10 +533,56 @@ impl Default for BuildConfig {
             build_dir: PathBuf::from("book"),
             create_missing: true,
             use_default_preprocessors: true,
+            jobs: None,
+            strict: false,
+            restart_numbering_per_part: false,
+        }
+    }
+}
+
+/// Configuration for the Rust language support.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RustConfig {
+    /// The edition code examples are written in. Rust itself defaults to
+    /// 2015 if this isn't set.
+    pub edition: Option<RustEdition>,
+}
+
+/// A Rust edition.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RustEdition {
+    /// The 2015 edition of Rust.
+    #[serde(rename = "2015")]
+    E2015,
+    /// The 2018 edition of Rust.
+    #[serde(rename = "2018")]
+    E2018,
+}
+
+impl Display for RustEdition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            RustEdition::E2015 => write!(f, "2015"),
+            RustEdition::E2018 => write!(f, "2018"),
         }
     }
 }
 
+/// Configuration for `mdbook test`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TestConfig {
+    /// Crates code examples can use, passed to `rustdoc` as
+    /// `--extern name=path`. Relative paths are relative to the book's root
+    /// directory.
+    pub externs: BTreeMap<String, PathBuf>,
+    /// Extra arguments to pass to `rustdoc`.
+    pub rustdoc_flags: Vec<String>,
+    /// Environment variables to set when running `rustdoc`.
+    pub env: BTreeMap<String, String>,
+}
+
 /// Configuration for the HTML renderer.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -421,6 +595,9 @@ pub struct HtmlConfig {
     pub curly_quotes: bool,
     /// Should mathjax be enabled?
     pub mathjax_support: bool,
+    /// Highlight code blocks while building the book, instead of loading
+    /// highlight.js in the reader's browser.
+    pub build_time_highlighting: bool,
     /// An optional google analytics code.
     pub google_analytics: Option<String>,
     /// Additional CSS stylesheets to include in the rendered page's `<head>`.
@@ -447,6 +624,25 @@ pub struct HtmlConfig {
     /// FontAwesome icon class to use for the Git repository link.
     /// Defaults to `fa-github` if `None`.
     pub git_repository_icon: Option<String>,
+    /// The absolute URL the book is published at (e.g.
+    /// `https://example.com/book/`), used for links to the book from
+    /// elsewhere and to generate a `sitemap.xml`.
+    pub site_url: Option<String>,
+    /// Generate a `robots.txt` pointing search engines at the sitemap? Only
+    /// used if `site_url` is set.
+    pub robots_txt: bool,
+    /// The image shown in social media previews of the book's pages, either
+    /// an absolute URL or a path relative to `site_url`.
+    pub social_image: Option<String>,
+    /// Pages which redirect readers to where a chapter has moved, mapping the
+    /// old page (e.g. `/old/page.html`) to its new location.
+    pub redirect: BTreeMap<String, String>,
+    /// Atom feed settings. If `None`, no feed is generated.
+    pub feed: Option<Feed>,
+    /// Print page settings.
+    pub print: Print,
+    /// Markdown extensions, which are also used by the search index.
+    pub markdown: Markdown,
 }
 
 impl HtmlConfig {
@@ -460,6 +656,29 @@ impl HtmlConfig {
     }
 }
 
+/// Markdown extensions for the HTML renderer, which are all off by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Markdown {
+    /// Strike through text between `~~`s.
+    pub strikethrough: bool,
+    /// Show a checkbox for list items starting with `[ ]` or `[x]`.
+    pub tasklists: bool,
+    /// Turn a line followed by lines starting with `: ` into a term and its
+    /// definitions.
+    pub definition_lists: bool,
+    /// Set a heading's ID and classes with `{#id .class}` at its end.
+    pub heading_attributes: bool,
+    /// Use curly quotes, en and em dashes for `--` and `---`, and an ellipsis
+    /// for `...`.
+    pub smart_punctuation: bool,
+    /// Show `> **Note**` block quotes and `::: warning` blocks as callouts.
+    pub admonitions: bool,
+    /// Render equations between `$`s, `\(` and `\)`, `$$`s or `\[` and `\]`
+    /// to MathML.
+    pub math: bool,
+}
+
 /// Configuration for tweaking how the the HTML renderer handles the playpen.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -531,6 +750,135 @@ impl Default for Search {
     }
 }
 
+/// Configuration of the HTML renderer's print page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Print {
+    /// Generate the print page (and show the print button)? Default: `true`.
+    pub enable: bool,
+    /// How to split the book between several print pages. Default: every
+    /// chapter is on one page.
+    pub split: PrintSplit,
+    /// The number of top-level chapters on each print page when splitting by
+    /// size. Default: `10`.
+    pub page_size: usize,
+}
+
+impl Default for Print {
+    fn default() -> Print {
+        Print {
+            enable: true,
+            split: PrintSplit::None,
+            page_size: 10,
+        }
+    }
+}
+
+/// How to split the book between several print pages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrintSplit {
+    /// Every chapter is on one page.
+    None,
+    /// Each part of the book (see `SUMMARY.md`) is on its own page.
+    Part,
+    /// Every `page-size` top-level chapters are on their own page.
+    Size,
+}
+
+/// Configuration of the HTML renderer's Atom feed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Feed {
+    /// The chapter whose sub-chapters are the feed's entries (e.g.
+    /// `releases/README.md`). If `None`, every chapter with a `date` in its
+    /// front matter is an entry.
+    pub section: Option<PathBuf>,
+    /// The feed's title. Defaults to the book's title.
+    pub title: Option<String>,
+    /// Maximum number of entries, newest first. Default: `20`.
+    pub limit: usize,
+}
+
+impl Default for Feed {
+    fn default() -> Feed {
+        Feed {
+            section: None,
+            title: None,
+            limit: 20,
+        }
+    }
+}
+
+/// Configuration for the built-in EPUB renderer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct EpubConfig {
+    /// The name of the generated file (without the `.epub` extension).
+    /// Defaults to the book's title, or `book` if there isn't one.
+    pub filename: Option<String>,
+    /// A unique identifier for this publication (e.g. an ISBN or a
+    /// `urn:uuid:...`). Defaults to one derived from the book's title.
+    pub identifier: Option<String>,
+    /// An image in the source directory to use as the book's cover.
+    pub cover_image: Option<PathBuf>,
+    /// Additional CSS stylesheets to embed and apply to every chapter.
+    pub additional_css: Vec<PathBuf>,
+    /// Use "smart quotes" instead of the usual `"` character.
+    pub curly_quotes: bool,
+}
+
+/// Configuration for the built-in `diagrams` preprocessor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DiagramsConfig {
+    /// The command which renders each diagram language to SVG, keyed by the
+    /// language's name. The diagram is written to the command's `stdin`, and
+    /// it should print the SVG to `stdout`. Default: `dot = "dot -Tsvg"`.
+    pub languages: BTreeMap<String, String>,
+}
+
+impl Default for DiagramsConfig {
+    fn default() -> DiagramsConfig {
+        let mut languages = BTreeMap::new();
+        languages.insert(String::from("dot"), String::from("dot -Tsvg"));
+
+        DiagramsConfig { languages }
+    }
+}
+
+/// Configuration for the built-in `glossary` preprocessor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GlossaryConfig {
+    /// A TOML file defining the terms, relative to the book's root directory.
+    /// If this isn't set, the terms are read from the glossary chapter.
+    pub file: Option<PathBuf>,
+    /// The glossary chapter, relative to the source directory. When the terms
+    /// are read from `file`, they're added to this chapter, which is created
+    /// at the end of the book if it doesn't exist. Default: `glossary.md`.
+    pub chapter: PathBuf,
+    /// The title of a generated glossary chapter. Default: `Glossary`.
+    pub title: String,
+    /// Link the first use of each term in a chapter to its definition.
+    /// Default: `true`.
+    pub links: bool,
+    /// Show a term's definition when the mouse is over it. Default: `true`.
+    pub tooltips: bool,
+}
+
+impl Default for GlossaryConfig {
+    fn default() -> GlossaryConfig {
+        GlossaryConfig {
+            file: None,
+            chapter: PathBuf::from("glossary.md"),
+            title: String::from("Glossary"),
+            links: true,
+            tooltips: true,
+        }
+    }
+}
+
 /// Allows you to "update" any arbitrary field in a struct by round-tripping via
 /// a `toml::Value`.
 ///
@@ -607,6 +955,9 @@ mod tests {
             build_dir: PathBuf::from("outputs"),
             create_missing: false,
             use_default_preprocessors: true,
+            jobs: None,
+            strict: false,
+            restart_numbering_per_part: false,
         };
         let playpen_should_be = Playpen {
             editable: true,
@@ -712,6 +1063,9 @@ mod tests {
             build_dir: PathBuf::from("my-book"),
             create_missing: true,
             use_default_preprocessors: true,
+            jobs: None,
+            strict: false,
+            restart_numbering_per_part: false,
         };
 
         let html_should_be = HtmlConfig {
@@ -814,4 +1168,104 @@ mod tests {
 
         assert_eq!(cfg.book.title, Some(should_be));
     }
+
+    #[test]
+    fn translations_override_the_book_config() {
+        let src = r#"
+        [book]
+        title = "My Book"
+        description = "Just a book"
+        multilingual = true
+
+        [language.en]
+        name = "English"
+        default = true
+
+        [language.ja]
+        name = "日本語"
+        title = "私の本"
+
+        [output.html]
+        site-url = "https://example.com/book"
+        "#;
+
+        let cfg = Config::from_str(src).unwrap();
+        assert_eq!(cfg.default_language().unwrap(), "en");
+        assert_eq!(cfg.languages().unwrap().len(), 2);
+
+        let ja = cfg.for_language("ja").unwrap();
+        assert_eq!(ja.book.src, PathBuf::from("src/ja"));
+        assert_eq!(ja.book.language, Some(String::from("ja")));
+        assert_eq!(ja.book.title, Some(String::from("私の本")));
+        assert_eq!(ja.book.description, Some(String::from("Just a book")));
+        assert_eq!(ja.default_language().unwrap(), "en");
+        assert_eq!(
+            ja.html_config().unwrap().site_url,
+            Some(String::from("https://example.com/book/ja/"))
+        );
+
+        let en = cfg.for_language("en").unwrap();
+        assert_eq!(
+            en.html_config().unwrap().site_url,
+            Some(String::from("https://example.com/book"))
+        );
+    }
+
+    #[test]
+    fn the_default_language_is_english() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.default_language().unwrap(), "en");
+
+        cfg.book.language = Some(String::from("fr"));
+        assert_eq!(cfg.default_language().unwrap(), "fr");
+    }
+
+    #[test]
+    fn load_rust_and_test_config() {
+        let src = r#"
+        [rust]
+        edition = "2018"
+
+        [test]
+        rustdoc-flags = ["-L", "target/debug/deps"]
+
+        [test.externs]
+        my_crate = "target/debug/libmy_crate.rlib"
+
+        [test.env]
+        RUST_BACKTRACE = "1"
+        "#;
+
+        let mut cfg = Config::from_str(src).unwrap();
+        assert_eq!(cfg.rust.edition, Some(RustEdition::E2018));
+        assert_eq!(cfg.get("rust"), None);
+
+        let test = cfg.test_config().unwrap();
+        assert_eq!(test.rustdoc_flags, vec!["-L", "target/debug/deps"]);
+        assert_eq!(
+            test.externs["my_crate"],
+            PathBuf::from("target/debug/libmy_crate.rlib")
+        );
+        assert_eq!(test.env["RUST_BACKTRACE"], "1");
+
+        cfg.set("rust.edition", "2015").unwrap();
+        assert_eq!(cfg.rust.edition, Some(RustEdition::E2015));
+    }
+
+    #[test]
+    fn invalid_rust_and_test_config_is_an_error() {
+        assert!(Config::from_str("[rust]\nedition = \"2019\"").is_err());
+
+        let cfg = Config::from_str("[test]\nexterns = [\"my_crate\"]").unwrap();
+        assert!(cfg.test_config().is_err());
+    }
+
+    #[test]
+    fn invalid_language_tables_are_an_error() {
+        let cfg = Config::from_str("[language.fr]\nname = 5").unwrap();
+
+        assert!(cfg.languages().is_err());
+        assert!(cfg.default_language().is_err());
+        assert!(cfg.for_language("fr").is_err());
+    }
 }
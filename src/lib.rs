@@ -82,9 +82,13 @@
 
 #![deny(missing_docs)]
 
+extern crate chrono;
 #[macro_use]
 extern crate error_chain;
 extern crate handlebars;
+#[cfg(feature = "epub")]
+#[macro_use]
+extern crate html5ever;
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
@@ -92,16 +96,20 @@ extern crate lazy_static;
 extern crate log;
 extern crate memchr;
 extern crate pulldown_cmark;
+extern crate rayon;
 extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate serde_yaml;
 extern crate shlex;
 extern crate tempfile;
 extern crate toml;
 extern crate toml_query;
+#[cfg(feature = "epub")]
+extern crate zip;
 
 #[cfg(test)]
 #[macro_use]
@@ -160,6 +168,13 @@ pub mod errors {
                 description("Reserved Filename")
                 display("{} is reserved for internal use", filename.display())
             }
+
+            /// Some `{{#include}}` or `{{#playpen}}` links couldn't be expanded
+            /// and `build.strict` is enabled.
+            IncludeFailed(errors: Vec<::preprocess::IncludeError>) {
+                description("Unable to expand some links")
+                display("{}", errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))
+            }
         }
     }
 
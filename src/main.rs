@@ -40,6 +40,7 @@ fn main() {
         .subcommand(cmd::init::make_subcommand())
         .subcommand(cmd::build::make_subcommand())
         .subcommand(cmd::test::make_subcommand())
+        .subcommand(cmd::check::make_subcommand())
         .subcommand(cmd::clean::make_subcommand());
 
     #[cfg(feature = "watch")]
@@ -57,6 +58,7 @@ fn main() {
         #[cfg(feature = "serve")]
         ("serve", Some(sub_matches)) => cmd::serve::execute(sub_matches),
         ("test", Some(sub_matches)) => cmd::test::execute(sub_matches),
+        ("check", Some(sub_matches)) => cmd::check::execute(sub_matches),
         (_, _) => unreachable!(),
     };
 
@@ -0,0 +1,410 @@
+use shlex::Shlex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use config::DiagramsConfig;
+use errors::*;
+use utils::fs::{file_to_string, resolve_relative_path, write_file, CACHE_DIR};
+
+use super::links::linked_files;
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+
+/// A preprocessor which renders diagrams to SVG while building the book.
+///
+/// Code blocks in one of the languages in the `[preprocessor.diagrams]`
+/// table (e.g. ```` ```dot ````) are piped through that language's command,
+/// and replaced by the SVG it prints. Rendered diagrams are cached in the
+/// book's cache directory, so a diagram is only rendered again when it
+/// changes.
+#[derive(Default)]
+pub struct DiagramPreprocessor;
+
+impl DiagramPreprocessor {
+    pub(crate) const NAME: &'static str = "diagrams";
+
+    /// Create a new `DiagramPreprocessor`.
+    pub fn new() -> Self {
+        DiagramPreprocessor
+    }
+}
+
+impl Preprocessor for DiagramPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let key = format!("preprocessor.{}", Self::NAME);
+        let cfg: DiagramsConfig = match ctx.config.get(&key) {
+            Some(_) => ctx
+                .config
+                .get_deserialized(&key)
+                .chain_err(|| "Invalid configuration for the diagrams preprocessor")?,
+            None => DiagramsConfig::default(),
+        };
+        let renderer = DiagramRenderer {
+            root: &ctx.root,
+            src_dir: ctx.root.join(&ctx.config.book.src),
+            cache_dir: ctx.root.join(CACHE_DIR).join(Self::NAME),
+            languages: &cfg.languages,
+        };
+
+        let mut result = Ok(());
+        book.for_each_mut(|section: &mut BookItem| {
+            if result.is_err() {
+                return;
+            }
+
+            if let BookItem::Chapter(ref mut ch) = *section {
+                if ch.is_draft() {
+                    return;
+                }
+
+                match render_diagrams(&ch.content, &ch.path, &renderer) {
+                    Ok(content) => ch.content = content,
+                    Err(e) => result = Err(e),
+                }
+            }
+        });
+
+        result.map(|_| book)
+    }
+}
+
+struct DiagramRenderer<'a> {
+    root: &'a Path,
+    src_dir: PathBuf,
+    cache_dir: PathBuf,
+    languages: &'a BTreeMap<String, String>,
+}
+
+impl<'a> DiagramRenderer<'a> {
+    /// Render a diagram, or get it from the cache if it's been rendered by the
+    /// same command before.
+    fn render(&self, command: &str, source: &str) -> Result<String> {
+        // DefaultHasher isn't guaranteed to be stable across versions of Rust,
+        // but a different hash only means the diagram is rendered again
+        let mut hasher = DefaultHasher::new();
+        (command, source).hash(&mut hasher);
+        let filename = format!("{:016x}.svg", hasher.finish());
+
+        if let Ok(svg) = file_to_string(self.cache_dir.join(&filename)) {
+            return Ok(svg);
+        }
+
+        let svg = run_command(command, source, self.root)?;
+        if let Err(e) = write_file(&self.cache_dir, &filename, svg.as_bytes()) {
+            warn!("Unable to cache a rendered diagram: {}", e);
+        }
+
+        Ok(svg)
+    }
+}
+
+/// Replace the diagrams in a chapter with their SVG.
+fn render_diagrams(content: &str, path: &Path, renderer: &DiagramRenderer) -> Result<String> {
+    let blocks = find_code_blocks(content);
+    let mut rendered = String::with_capacity(content.len());
+    let mut previous_end = 0;
+
+    for (n, block) in blocks.iter().enumerate() {
+        let command = match renderer.languages.get(block.language) {
+            Some(command) => command,
+            None => continue,
+        };
+
+        let svg = renderer.render(command, &block.source).chain_err(|| {
+            format!(
+                "Unable to render the {} diagram {}",
+                block.language,
+                locate_block(n, &blocks, path, &renderer.src_dir)
+            )
+        })?;
+
+        rendered.push_str(&content[previous_end..block.start]);
+        push_svg(&mut rendered, &svg, block.indent);
+        previous_end = block.end;
+    }
+
+    rendered.push_str(&content[previous_end..]);
+    Ok(rendered)
+}
+
+/// Describe where the `n`th of a preprocessed chapter's code `blocks` was
+/// written, for error messages.
+///
+/// When preprocessing kept the same blocks in the same order (e.g. it only
+/// filled one in with an `{{#include}}`), they match up one to one with the
+/// chapter's file. Otherwise the same block is looked for in the chapter's
+/// file and then in the files it includes, and failing that all that's known
+/// is where the block is in the preprocessed chapter.
+fn locate_block(n: usize, blocks: &[CodeBlock], chapter: &Path, src_dir: &Path) -> String {
+    let block = &blocks[n];
+
+    if let Ok(written) = file_to_string(src_dir.join(chapter)) {
+        let written_blocks = find_code_blocks(&written);
+        let same_blocks = written_blocks.len() == blocks.len()
+            && written_blocks
+                .iter()
+                .zip(blocks)
+                .all(|(a, b)| a.language == b.language);
+        if same_blocks {
+            return format!("on line {} of {}", written_blocks[n].line, chapter.display());
+        }
+        if let Some(line) = find_same_block(block, &written_blocks) {
+            return format!("on line {} of {}", line, chapter.display());
+        }
+
+        let dir = chapter.parent().unwrap_or_else(|| Path::new(""));
+        for linked in linked_files(&written) {
+            let linked = resolve_relative_path(dir.join(linked));
+            if let Ok(content) = file_to_string(src_dir.join(&linked)) {
+                if let Some(line) = find_same_block(block, &find_code_blocks(&content)) {
+                    return format!("on line {} of {}", line, linked.display());
+                }
+            }
+        }
+    }
+
+    format!(
+        "at byte {} of the preprocessed chapter {}",
+        block.start,
+        chapter.display()
+    )
+}
+
+/// The line a block with the same language and source starts on, if there's
+/// exactly one.
+fn find_same_block(block: &CodeBlock, blocks: &[CodeBlock]) -> Option<usize> {
+    let mut same = blocks
+        .iter()
+        .filter(|other| other.language == block.language && other.source == block.source);
+    match (same.next(), same.next()) {
+        (Some(other), None) => Some(other.line),
+        _ => None,
+    }
+}
+
+/// Add an SVG image as an HTML block. Blank lines would end the HTML block,
+/// so they're left out.
+fn push_svg(rendered: &mut String, svg: &str, indent: &str) {
+    rendered.push_str(indent);
+    rendered.push_str("<div class=\"diagram\">\n");
+    for line in svg.lines().filter(|line| !line.trim().is_empty()) {
+        rendered.push_str(indent);
+        rendered.push_str(line);
+        rendered.push('\n');
+    }
+    rendered.push_str(indent);
+    rendered.push_str("</div>\n\n");
+}
+
+/// Pipe a diagram through a command, returning the SVG it prints (without
+/// any XML declaration or doctype before the `<svg>` element).
+fn run_command(command: &str, source: &str, root: &Path) -> Result<String> {
+    let mut words = Shlex::new(command);
+    let executable = match words.next() {
+        Some(e) => e,
+        None => bail!("Command string was empty"),
+    };
+
+    let mut child = Command::new(&executable)
+        .args(words)
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .chain_err(|| format!("Unable to start \"{}\". Is it installed?", executable))?;
+
+    {
+        let mut stdin = child.stdin.take().expect("Child has stdin");
+        if let Err(e) = stdin.write_all(source.as_bytes()) {
+            // the command may have exited early, which is reported below
+            warn!("Error writing the diagram to \"{}\", {}", command, e);
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .chain_err(|| format!("Error waiting for \"{}\" to complete", command))?;
+    ensure!(
+        output.status.success(),
+        "\"{}\" exited unsuccessfully: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let svg = String::from_utf8(output.stdout)?;
+    match svg.find("<svg") {
+        Some(start) => Ok(svg[start..].to_string()),
+        None => bail!("\"{}\" didn't print an SVG image", command),
+    }
+}
+
+/// A fenced code block in a chapter.
+#[derive(Debug, Clone, PartialEq)]
+struct CodeBlock<'a> {
+    /// The first word of the block's info string.
+    language: &'a str,
+    source: String,
+    /// The line the opening fence is on (starting from 1).
+    line: usize,
+    /// The indentation of the opening fence.
+    indent: &'a str,
+    /// The byte range of the block, from the start of the opening fence to
+    /// the end of the closing one.
+    start: usize,
+    end: usize,
+}
+
+/// A line starting a fenced code block, or ending one.
+struct Fence<'a> {
+    indent: usize,
+    character: char,
+    length: usize,
+    info: &'a str,
+}
+
+impl<'a> Fence<'a> {
+    fn parse(line: &'a str) -> Option<Fence<'a>> {
+        let line = line.trim_end_matches('\r');
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let character = trimmed.chars().next()?;
+        if indent > 3 || (character != '`' && character != '~') {
+            return None;
+        }
+
+        let length = trimmed.len() - trimmed.trim_start_matches(character).len();
+        let info = trimmed[length..].trim();
+        if length < 3 || (character == '`' && info.contains('`')) {
+            return None;
+        }
+
+        Some(Fence {
+            indent,
+            character,
+            length,
+            info,
+        })
+    }
+
+    fn is_closed_by(&self, other: &Fence) -> bool {
+        other.character == self.character && other.length >= self.length && other.info.is_empty()
+    }
+}
+
+/// Find the fenced code blocks in some markdown. A block which isn't closed
+/// goes on to the end of the document.
+fn find_code_blocks<'a>(content: &'a str) -> Vec<CodeBlock<'a>> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(Fence, CodeBlock)> = None;
+    let mut offset = 0;
+
+    for (number, line) in content.split('\n').enumerate() {
+        let line_end = ::std::cmp::min(offset + line.len() + 1, content.len());
+        let fence = Fence::parse(line);
+
+        match open.take() {
+            Some((opening, mut block)) => {
+                let closes = match fence {
+                    Some(ref fence) => opening.is_closed_by(fence),
+                    None => false,
+                };
+                if closes {
+                    block.end = line_end;
+                    blocks.push(block);
+                } else {
+                    // the block's content is unindented by as much as its fence
+                    let indent = line.len() - line.trim_start_matches(' ').len();
+                    let line = &line[::std::cmp::min(indent, opening.indent)..];
+                    block.source.push_str(line.trim_end_matches('\r'));
+                    block.source.push('\n');
+                    open = Some((opening, block));
+                }
+            }
+            None => {
+                if let Some(fence) = fence {
+                    let block = CodeBlock {
+                        language: fence.info.split_whitespace().next().unwrap_or_default(),
+                        source: String::new(),
+                        line: number + 1,
+                        indent: &line[..fence.indent],
+                        start: offset,
+                        end: offset,
+                    };
+                    open = Some((fence, block));
+                }
+            }
+        }
+
+        offset = line_end;
+    }
+
+    if let Some((_, mut block)) = open {
+        block.end = content.len();
+        blocks.push(block);
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_the_code_blocks() {
+        let content = "# Diagrams\n\n```dot\ndigraph { a -> b }\n```\n\n\
+                       ````markdown\n```dot\nnot a diagram\n```\n````\n\n  ~~~ dot {.wide}\n  \
+                       graph { c }\n  ~~~\n";
+        let got: Vec<_> = find_code_blocks(content)
+            .into_iter()
+            .map(|block| (block.language, block.source, block.line, block.indent))
+            .collect();
+
+        assert_eq!(
+            got,
+            vec![
+                ("dot", String::from("digraph { a -> b }\n"), 3, ""),
+                ("markdown", String::from("```dot\nnot a diagram\n```\n"), 7, ""),
+                ("dot", String::from("graph { c }\n"), 13, "  "),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_span_the_whole_fence() {
+        let content = "Text\n```dot\na\n```\nMore text";
+        let blocks = find_code_blocks(content);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(&content[blocks[0].start..blocks[0].end], "```dot\na\n```\n");
+    }
+
+    #[test]
+    fn unclosed_code_blocks_go_to_the_end() {
+        let content = "```dot\na\n\nb";
+        let blocks = find_code_blocks(content);
+
+        assert_eq!(blocks[0].source, "a\n\nb\n");
+        assert_eq!(blocks[0].end, content.len());
+    }
+
+    #[test]
+    fn svg_is_inlined_without_blank_lines() {
+        let mut rendered = String::new();
+        push_svg(&mut rendered, "<svg>\n\n  <g/>\n</svg>\n", " ");
+
+        assert_eq!(
+            rendered,
+            " <div class=\"diagram\">\n <svg>\n   <g/>\n </svg>\n </div>\n\n"
+        );
+    }
+}
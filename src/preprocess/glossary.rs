@@ -0,0 +1,544 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use regex::{self, Regex};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use toml;
+
+use config::GlossaryConfig;
+use errors::*;
+use utils;
+use utils::fs::{file_to_string, path_to_root};
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem, Chapter};
+
+/// A preprocessor which links terms to their definitions in a glossary.
+///
+/// The terms are read from a TOML file, and added to a generated glossary
+/// chapter, or from an existing glossary chapter where each heading (below
+/// the chapter's title) is a term. The first use of each term in every other
+/// chapter links to its definition, with the definition as a tooltip.
+/// Headings, code, and text which is already a link are left alone.
+#[derive(Default)]
+pub struct GlossaryPreprocessor;
+
+impl GlossaryPreprocessor {
+    pub(crate) const NAME: &'static str = "glossary";
+
+    /// Create a new `GlossaryPreprocessor`.
+    pub fn new() -> Self {
+        GlossaryPreprocessor
+    }
+}
+
+impl Preprocessor for GlossaryPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let key = format!("preprocessor.{}", Self::NAME);
+        let cfg: GlossaryConfig = match ctx.config.get(&key) {
+            Some(_) => ctx
+                .config
+                .get_deserialized(&key)
+                .chain_err(|| "Invalid configuration for the glossary preprocessor")?,
+            None => GlossaryConfig::default(),
+        };
+
+        let terms = match cfg.file {
+            Some(ref file) => {
+                let path = ctx.root.join(file);
+                let content = file_to_string(&path)
+                    .chain_err(|| format!("Unable to read the glossary ({})", path.display()))?;
+                let terms = parse_glossary_file(&content)
+                    .chain_err(|| format!("Unable to parse the glossary ({})", path.display()))?;
+                add_glossary_chapter(&mut book, &cfg, &terms);
+                terms
+            }
+            None => match find_chapter(&book, &cfg.chapter) {
+                Some(ch) => terms_from_chapter(&ch.content),
+                None => bail!(
+                    "The glossary preprocessor needs either a `file` of terms or a {} chapter",
+                    cfg.chapter.display()
+                ),
+            },
+        };
+
+        if terms.is_empty() || !(cfg.links || cfg.tooltips) {
+            return Ok(book);
+        }
+
+        let linker = TermLinker::new(&terms, &cfg);
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                if !ch.is_draft() && ch.path != cfg.chapter {
+                    ch.content = linker.link(&ch.content, &ch.path);
+                }
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// A term defined in the glossary.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    name: String,
+    /// Other ways of writing the term, e.g. its plural.
+    aliases: Vec<String>,
+    /// The definition, as markdown.
+    definition: String,
+    /// The ID of the term's heading in the glossary chapter.
+    id: String,
+}
+
+impl Term {
+    fn new(name: String, aliases: Vec<String>, definition: String) -> Term {
+        let id = utils::id_from_content(&name);
+        Term {
+            name,
+            aliases,
+            definition,
+            id,
+        }
+    }
+}
+
+/// An entry in a glossary file, which is either just the definition or a
+/// table with the definition and any aliases.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Definition(String),
+    Table {
+        definition: String,
+        #[serde(default)]
+        aliases: Vec<String>,
+    },
+}
+
+/// Parse a glossary file, where each key is a term, sorting the terms
+/// alphabetically.
+fn parse_glossary_file(content: &str) -> Result<Vec<Term>> {
+    let entries: BTreeMap<String, Entry> =
+        toml::from_str(content).chain_err(|| "Invalid glossary file")?;
+
+    let mut terms: Vec<_> = entries
+        .into_iter()
+        .map(|(name, entry)| match entry {
+            Entry::Definition(definition) => Term::new(name, Vec::new(), definition),
+            Entry::Table {
+                definition,
+                aliases,
+            } => Term::new(name, aliases, definition),
+        }).collect();
+    terms.sort_by_key(|term| term.name.to_lowercase());
+
+    Ok(terms)
+}
+
+/// Read the terms from a glossary chapter, where each heading below the
+/// chapter's title is a term, and the text up to the next heading is its
+/// definition.
+fn terms_from_chapter(content: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut current: Option<(String, String)> = None;
+
+    for event in Parser::new_ext(content, markdown_options()) {
+        match event {
+            Event::Start(Tag::Header(level)) if level > 1 => {
+                if let Some((name, definition)) = current.take() {
+                    terms.push(Term::new(name, Vec::new(), definition));
+                }
+                heading = Some(String::new());
+            }
+            Event::End(Tag::Header(level)) if level > 1 => {
+                current = heading.take().map(|name| (name, String::new()));
+            }
+            Event::Start(Tag::Header(_)) => {
+                if let Some((name, definition)) = current.take() {
+                    terms.push(Term::new(name, Vec::new(), definition));
+                }
+            }
+            Event::Text(text) => match (&mut heading, &mut current) {
+                (&mut Some(ref mut name), _) => name.push_str(&text),
+                (&mut None, &mut Some((_, ref mut definition))) => definition.push_str(&text),
+                _ => {}
+            },
+            Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph) => {
+                if let Some((_, ref mut definition)) = current {
+                    definition.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, definition)) = current {
+        terms.push(Term::new(name, Vec::new(), definition));
+    }
+
+    for term in &mut terms {
+        term.name = term.name.trim().to_string();
+        term.definition = utils::collapse_whitespace(term.definition.trim()).into_owned();
+    }
+    terms.retain(|term| !term.name.is_empty());
+    terms
+}
+
+fn find_chapter<'a>(book: &'a Book, path: &Path) -> Option<&'a Chapter> {
+    book.iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => Some(ch),
+            _ => None,
+        }).find(|ch| ch.path == path)
+}
+
+/// Add the terms to the glossary chapter, creating it at the end of the book
+/// if it doesn't exist.
+fn add_glossary_chapter(book: &mut Book, cfg: &GlossaryConfig, terms: &[Term]) {
+    let mut definitions = String::new();
+    for term in terms {
+        definitions.push_str(&format!("## {}\n\n{}\n\n", term.name, term.definition.trim()));
+    }
+
+    let mut found = false;
+    book.for_each_mut(|section: &mut BookItem| {
+        if let BookItem::Chapter(ref mut ch) = *section {
+            if ch.path == cfg.chapter {
+                ch.content = format!("{}\n\n{}", ch.content.trim_end(), definitions);
+                found = true;
+            }
+        }
+    });
+
+    if !found {
+        let content = format!("# {}\n\n{}", cfg.title, definitions);
+        let chapter = Chapter::new(&cfg.title, content, &cfg.chapter, Vec::new());
+        book.push_item(chapter);
+    }
+}
+
+fn markdown_options() -> Options {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    opts
+}
+
+/// Links the first use of each term in a chapter to the glossary.
+struct TermLinker<'a> {
+    terms: &'a [Term],
+    cfg: &'a GlossaryConfig,
+    /// Matches any of the terms or their aliases, longest first, with a
+    /// capture group for each.
+    pattern: Regex,
+    /// The index of the term each of the pattern's groups belongs to.
+    groups: Vec<usize>,
+    /// The definitions as plain text, for tooltips.
+    tooltips: Vec<String>,
+}
+
+impl<'a> TermLinker<'a> {
+    fn new(terms: &'a [Term], cfg: &'a GlossaryConfig) -> TermLinker<'a> {
+        let mut names = HashMap::new();
+        for (i, term) in terms.iter().enumerate() {
+            for name in Some(&term.name).into_iter().chain(&term.aliases) {
+                names.entry(name.to_lowercase()).or_insert(i);
+            }
+        }
+
+        // the regex's case insensitivity folds more characters together than
+        // `to_lowercase` does (e.g. "µ" and "μ"), so which term was found is
+        // told by the group which matched rather than by looking it up
+        let mut names: Vec<_> = names.into_iter().collect();
+        names.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)));
+        let alternatives: Vec<_> = names
+            .iter()
+            .map(|(name, _)| format!("({})", regex::escape(name)))
+            .collect();
+        let groups = names.into_iter().map(|(_, term)| term).collect();
+        let pattern = Regex::new(&format!("(?i){}", alternatives.join("|")))
+            .expect("Escaped terms are always a valid regex");
+
+        let tooltips = terms
+            .iter()
+            .map(|term| plain_text(&term.definition))
+            .collect();
+
+        TermLinker {
+            terms,
+            cfg,
+            pattern,
+            groups,
+            tooltips,
+        }
+    }
+
+    fn link(&self, content: &str, chapter: &Path) -> String {
+        let mut parser = Parser::new_ext(content, markdown_options());
+        let mut replacements = Vec::new();
+        let mut linked = HashSet::new();
+        // headings, code, links and images
+        let mut skipping = 0;
+        let mut in_html_link = false;
+
+        loop {
+            let start = parser.get_offset();
+            let event = match parser.next() {
+                Some(event) => event,
+                None => break,
+            };
+            let end = parser.get_offset().min(content.len());
+
+            match event {
+                Event::Start(Tag::Header(_))
+                | Event::Start(Tag::CodeBlock(_))
+                | Event::Start(Tag::Code)
+                | Event::Start(Tag::Link(..))
+                | Event::Start(Tag::Image(..)) => skipping += 1,
+                Event::End(Tag::Header(_))
+                | Event::End(Tag::CodeBlock(_))
+                | Event::End(Tag::Code)
+                | Event::End(Tag::Link(..))
+                | Event::End(Tag::Image(..)) => skipping -= 1,
+                Event::InlineHtml(ref html) => {
+                    let html = html.to_lowercase();
+                    if html.starts_with("<a ") || html.starts_with("<a>") {
+                        in_html_link = true;
+                    } else if html.starts_with("</a") {
+                        in_html_link = false;
+                    }
+                }
+                // only text which is written exactly as it's shown can be
+                // turned into a link without changing what it says
+                Event::Text(ref text) if skipping == 0 && !in_html_link => {
+                    if content.get(start..end) != Some(&**text) {
+                        continue;
+                    }
+
+                    for captures in self.pattern.captures_iter(text) {
+                        let found = captures.get(0).expect("Group 0 is the whole match");
+                        if !is_whole_word(text, found.start(), found.end()) {
+                            continue;
+                        }
+                        let group = (1..captures.len()).find(|&i| captures.get(i).is_some());
+                        let term = match group {
+                            Some(group) => self.groups[group - 1],
+                            None => continue,
+                        };
+                        if linked.insert(term) {
+                            replacements.push((start + found.start(), start + found.end(), term));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut linked_content = String::with_capacity(content.len());
+        let mut previous_end = 0;
+        for (start, end, term) in replacements {
+            linked_content.push_str(&content[previous_end..start]);
+            linked_content.push_str(&self.term_link(&content[start..end], term, chapter));
+            previous_end = end;
+        }
+        linked_content.push_str(&content[previous_end..]);
+
+        linked_content
+    }
+
+    /// The markdown for a use of a term, as it was written.
+    fn term_link(&self, text: &str, term: usize, chapter: &Path) -> String {
+        let tooltip = &self.tooltips[term];
+
+        if !self.cfg.links {
+            return format!("<abbr title=\"{}\">{}</abbr>", escape_html(tooltip), text);
+        }
+
+        let glossary = self.cfg.chapter.to_string_lossy().replace('\\', "/");
+        let destination = format!(
+            "{}{}#{}",
+            path_to_root(chapter),
+            glossary,
+            self.terms[term].id
+        );
+
+        if self.cfg.tooltips && !tooltip.is_empty() {
+            let title = tooltip.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("[{}]({} \"{}\")", text, destination, title)
+        } else {
+            format!("[{}]({})", text, destination)
+        }
+    }
+}
+
+/// Is the match at `start..end` a whole word, rather than part of one?
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let first = text[start..end].chars().next();
+    let last = text[start..end].chars().next_back();
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+
+    let joined_before = match (before, first) {
+        (Some(before), Some(first)) => is_word(before) && is_word(first),
+        _ => false,
+    };
+    let joined_after = match (last, after) {
+        (Some(last), Some(after)) => is_word(last) && is_word(after),
+        _ => false,
+    };
+
+    !joined_before && !joined_after
+}
+
+/// The text of some markdown, without any formatting.
+fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Text(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    utils::collapse_whitespace(text.trim()).into_owned()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn terms() -> Vec<Term> {
+        parse_glossary_file(
+            r#"
+            Crate = "A *compilation* unit."
+
+            ["Build script"]
+            definition = "Runs before a \"crate\" is built."
+            aliases = ["build scripts"]
+            "#,
+        ).unwrap()
+    }
+
+    #[test]
+    fn parse_a_glossary_file() {
+        let got = terms();
+
+        assert_eq!(
+            got,
+            vec![
+                Term {
+                    name: String::from("Build script"),
+                    aliases: vec![String::from("build scripts")],
+                    definition: String::from("Runs before a \"crate\" is built."),
+                    id: String::from("build-script"),
+                },
+                Term {
+                    name: String::from("Crate"),
+                    aliases: Vec::new(),
+                    definition: String::from("A *compilation* unit."),
+                    id: String::from("crate"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_the_terms_from_a_chapter() {
+        let content = "# Glossary\n\nSome intro.\n\n## Crate\n\nA compilation\nunit.\n\n\
+                       Another paragraph.\n\n## `cargo`\n\nThe package manager.\n";
+        let got: Vec<_> = terms_from_chapter(content)
+            .into_iter()
+            .map(|term| (term.name, term.definition))
+            .collect();
+
+        assert_eq!(
+            got,
+            vec![
+                (
+                    String::from("Crate"),
+                    String::from("A compilation unit. Another paragraph.")
+                ),
+                (String::from("cargo"), String::from("The package manager.")),
+            ]
+        );
+    }
+
+    #[test]
+    fn link_the_first_use_of_each_term() {
+        let terms = terms();
+        let cfg = GlossaryConfig::default();
+        let linker = TermLinker::new(&terms, &cfg);
+        let content = "# Crate basics\n\nEvery crate, and crates, use [a crate](crate.md).\n\n\
+                       `crate`\n\n```\ncrate\n```\n\nA Crate's build scripts and another crate.\n";
+
+        let got = linker.link(content, Path::new("guide/basics.md"));
+
+        assert_eq!(
+            got,
+            "# Crate basics\n\nEvery [crate](../glossary.md#crate \"A compilation unit.\"), \
+             and crates, use [a crate](crate.md).\n\n`crate`\n\n```\ncrate\n```\n\n\
+             A Crate's [build scripts](../glossary.md#build-script \
+             \"Runs before a \\\"crate\\\" is built.\") and another crate.\n"
+        );
+    }
+
+    #[test]
+    fn tooltips_without_links() {
+        let terms = terms();
+        let cfg = GlossaryConfig {
+            links: false,
+            ..Default::default()
+        };
+        let linker = TermLinker::new(&terms, &cfg);
+
+        let got = linker.link("A crate.", Path::new("intro.md"));
+
+        assert_eq!(got, "A <abbr title=\"A compilation unit.\">crate</abbr>.");
+    }
+
+    #[test]
+    fn terms_matched_by_case_folding_are_linked() {
+        let terms = parse_glossary_file("\"μs\" = \"A microsecond.\"").unwrap();
+        let cfg = GlossaryConfig {
+            links: false,
+            ..Default::default()
+        };
+        let linker = TermLinker::new(&terms, &cfg);
+
+        // "µ" is the micro sign, which folds to the Greek "μ" in the term
+        let got = linker.link("It takes 5 \u{b5}s.", Path::new("intro.md"));
+
+        assert_eq!(got, "It takes 5 <abbr title=\"A microsecond.\">\u{b5}s</abbr>.");
+    }
+
+    #[test]
+    fn the_glossary_chapter_is_generated() {
+        let mut book = Book::new();
+        let cfg = GlossaryConfig::default();
+        add_glossary_chapter(&mut book, &cfg, &terms());
+
+        let chapter = find_chapter(&book, &PathBuf::from("glossary.md")).unwrap();
+        assert_eq!(chapter.name, "Glossary");
+        assert_eq!(
+            chapter.content,
+            "# Glossary\n\n## Build script\n\nRuns before a \"crate\" is built.\n\n\
+             ## Crate\n\nA *compilation* unit.\n\n"
+        );
+    }
+}
@@ -1,9 +1,14 @@
 use errors::*;
+use itertools::Itertools;
 use regex::{CaptureMatches, Captures, Regex};
+use std::fmt::{self, Display, Formatter};
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use std::path::{Path, PathBuf};
-use utils::fs::file_to_string;
-use utils::take_lines;
+use utils::fs::{file_to_string, resolve_relative_path};
+use utils::{
+    line_and_column, strip_anchors, take_anchored_lines, take_lines,
+    take_rustdoc_include_anchored_lines, take_rustdoc_include_lines, RangeArgument,
+};
 
 use super::{Preprocessor, PreprocessorContext};
 use book::{Book, BookItem};
@@ -11,8 +16,14 @@ use book::{Book, BookItem};
 const ESCAPE_CHAR: char = '\\';
 const MAX_LINK_NESTED_DEPTH: usize = 10;
 
-/// A preprocessor for expanding the `{{# playpen}}` and `{{# include}}`
-/// helpers in a chapter.
+/// A preprocessor for expanding the `{{# playpen}}`, `{{# include}}` and
+/// `{{# rustdoc_include}}` helpers in a chapter.
+///
+/// Links which can't be expanded are left as-is and logged, unless
+/// `build.strict` is set, in which case the preprocessor fails with an
+/// [`ErrorKind::IncludeFailed`] listing every broken link.
+///
+/// [`ErrorKind::IncludeFailed`]: ../errors/enum.ErrorKind.html
 pub struct LinkPreprocessor;
 
 impl LinkPreprocessor {
@@ -31,71 +42,225 @@ impl Preprocessor for LinkPreprocessor {
 
     fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
         let src_dir = ctx.root.join(&ctx.config.book.src);
+        let mut errors = Vec::new();
 
         book.for_each_mut(|section: &mut BookItem| {
             if let BookItem::Chapter(ref mut ch) = *section {
+                if ch.is_draft() {
+                    return;
+                }
+
                 let base = ch
                     .path
                     .parent()
                     .map(|dir| src_dir.join(dir))
                     .expect("All book items have a parent");
 
-                let content = replace_all(&ch.content, base, &ch.path, 0);
+                let content = replace_all(&ch.content, base, &ch.path, &mut errors);
                 ch.content = content;
             }
         });
 
+        if ctx.config.build.strict && !errors.is_empty() {
+            bail!(ErrorKind::IncludeFailed(errors));
+        }
+
+        for error in &errors {
+            error!("{}", error);
+        }
+
         Ok(book)
     }
 }
 
-fn replace_all<P1, P2>(s: &str, path: P1, source: P2, depth: usize) -> String
+/// A `{{#include}}` or `{{#playpen}}` link which couldn't be expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludeError {
+    /// The chapter containing the link, relative to the book's `src`
+    /// directory.
+    pub chapter: PathBuf,
+    /// The line the link is on (starting from 1).
+    pub line: usize,
+    /// The column the link starts at (starting from 1).
+    pub column: usize,
+    /// The link, exactly as it was written in the chapter.
+    pub link: String,
+    /// Why the link couldn't be expanded. If the problem is in a file which
+    /// was included by `link`, this refers to that file.
+    pub reason: IncludeErrorReason,
+}
+
+/// The different ways expanding a link can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncludeErrorReason {
+    /// The file couldn't be read, along with the underlying error message.
+    Unreadable(PathBuf, String),
+    /// The file (directly or indirectly) includes itself.
+    Cyclic(PathBuf),
+    /// The file is nested more than `MAX_LINK_NESTED_DEPTH` includes deep.
+    TooDeeplyNested(PathBuf),
+    /// The requested lines aren't all in the file, which has the given number
+    /// of lines.
+    OutOfRange(PathBuf, usize),
+    /// The file doesn't contain an `ANCHOR` with this name.
+    AnchorNotFound(PathBuf, String),
+}
+
+impl Display for IncludeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: unable to expand \"{}\", ",
+            self.chapter.display(),
+            self.line,
+            self.column,
+            self.link
+        )?;
+
+        match self.reason {
+            IncludeErrorReason::Unreadable(ref path, ref message) => {
+                write!(f, "{} could not be read ({})", path.display(), message)
+            }
+            IncludeErrorReason::Cyclic(ref path) => write!(f, "{} includes itself", path.display()),
+            IncludeErrorReason::TooDeeplyNested(ref path) => write!(
+                f,
+                "{} is nested more than {} includes deep",
+                path.display(),
+                MAX_LINK_NESTED_DEPTH
+            ),
+            IncludeErrorReason::OutOfRange(ref path, lines) => write!(
+                f,
+                "the requested lines are outside {} (which has {} lines)",
+                path.display(),
+                lines
+            ),
+            IncludeErrorReason::AnchorNotFound(ref path, ref anchor) => {
+                write!(f, "{} has no anchor named \"{}\"", path.display(), anchor)
+            }
+        }
+    }
+}
+
+/// Expand every link in `s`, recording any which couldn't be expanded in
+/// `errors`.
+fn replace_all<P1, P2>(s: &str, path: P1, source: P2, errors: &mut Vec<IncludeError>) -> String
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
 {
+    let path = path.as_ref();
+    let source = source.as_ref();
+
+    // Remember the chapter itself, so it can't include itself either
+    let mut stack: Vec<PathBuf> = source
+        .file_name()
+        .map(|name| identify_file(&path.join(name)))
+        .into_iter()
+        .collect();
+
+    let (replaced, failed) = replace_links(s, path, &mut stack);
+
+    errors.extend(failed.into_iter().map(|(span, reason)| {
+        let (line, column) = line_and_column(s, span.start);
+        IncludeError {
+            chapter: source.to_path_buf(),
+            line,
+            column,
+            link: s[span].to_string(),
+            reason,
+        }
+    }));
+
+    replaced
+}
+
+/// Expand every link in `s`, returning the new content along with the span
+/// of each link which couldn't be fully expanded (and why).
+///
+/// `stack` contains every file currently being included, so cycles can be
+/// detected.
+fn replace_links(
+    s: &str,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> (String, Vec<(Range<usize>, IncludeErrorReason)>) {
     // When replacing one thing in a string by something with a different length,
     // the indices after that will not correspond,
     // we therefore have to store the difference to correct this
-    let path = path.as_ref();
-    let source = source.as_ref();
     let mut previous_end_index = 0;
     let mut replaced = String::new();
+    let mut failed = Vec::new();
 
-    for playpen in find_links(s) {
-        replaced.push_str(&s[previous_end_index..playpen.start_index]);
+    for link in find_links(s) {
+        replaced.push_str(&s[previous_end_index..link.start_index]);
 
-        match playpen.render_with_path(&path) {
-            Ok(new_content) => {
-                if depth < MAX_LINK_NESTED_DEPTH {
-                    if let Some(rel_path) = playpen.link.relative_path(path) {
-                        replaced.push_str(&replace_all(&new_content, rel_path, source, depth + 1));
-                    } else {
-                        replaced.push_str(&new_content);
-                    }
-                } else {
-                    error!(
-                        "Stack depth exceeded in {}. Check for cyclic includes",
-                        source.display()
-                    );
-                }
-                previous_end_index = playpen.end_index;
+        let mut reasons = Vec::new();
+        match expand_link(&link, path, stack, &mut reasons) {
+            Some(new_content) => {
+                replaced.push_str(&new_content);
+                previous_end_index = link.end_index;
             }
-            Err(e) => {
-                error!("Error updating \"{}\", {}", playpen.link_text, e);
-                for cause in e.iter().skip(1) {
-                    warn!("Caused By: {}", cause);
-                }
-
+            None => {
                 // This should make sure we include the raw `{{# ... }}` snippet
                 // in the page content if there are any errors.
-                previous_end_index = playpen.start_index;
+                previous_end_index = link.start_index;
             }
         }
+
+        let span = link.start_index..link.end_index;
+        failed.extend(reasons.into_iter().map(|reason| (span.clone(), reason)));
     }
 
     replaced.push_str(&s[previous_end_index..]);
-    replaced
+    (replaced, failed)
+}
+
+/// Expand a single link, including any links in the file it refers to.
+/// Returns `None` if the link couldn't be expanded at all.
+fn expand_link(
+    link: &Link,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<IncludeErrorReason>,
+) -> Option<String> {
+    let target = match link.link.path() {
+        Some(target) => path.join(target),
+        None => return link.render_with_path(path, errors),
+    };
+
+    let id = identify_file(&target);
+    if stack.contains(&id) {
+        errors.push(IncludeErrorReason::Cyclic(target));
+        return None;
+    }
+    if stack.len() > MAX_LINK_NESTED_DEPTH {
+        errors.push(IncludeErrorReason::TooDeeplyNested(target));
+        return None;
+    }
+
+    let new_content = link.render_with_path(path, errors)?;
+    let nested_path = target.parent().expect("Included file should not be /");
+
+    stack.push(id);
+    let (replaced, failed) = replace_links(&new_content, nested_path, stack);
+    stack.pop();
+
+    errors.extend(failed.into_iter().map(|(_, reason)| reason));
+    Some(replaced)
+}
+
+/// The files some markdown links to with `{{#include}}` and friends, relative
+/// to the markdown's directory.
+pub(crate) fn linked_files(s: &str) -> Vec<PathBuf> {
+    find_links(s)
+        .filter_map(|link| link.link.path().map(Path::to_path_buf))
+        .collect()
+}
+
+/// Get something which uniquely identifies a file, so different paths to the
+/// same file are recognised when looking for cycles.
+fn identify_file(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| resolve_relative_path(path))
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -105,36 +270,50 @@ enum LinkType<'a> {
     IncludeRangeFrom(PathBuf, RangeFrom<usize>),
     IncludeRangeTo(PathBuf, RangeTo<usize>),
     IncludeRangeFull(PathBuf, RangeFull),
+    IncludeAnchor(PathBuf, String),
     Playpen(PathBuf, Vec<&'a str>),
+    RustdocInclude(PathBuf, RangeOrAnchor),
+}
+
+/// The part of a file shown to the reader by `{{#rustdoc_include}}`.
+#[derive(PartialEq, Debug, Clone)]
+enum RangeOrAnchor {
+    /// The first line to show (starting from 0) and, optionally, the line
+    /// after the last one.
+    Range(usize, Option<usize>),
+    Anchor(String),
 }
 
 impl<'a> LinkType<'a> {
-    fn relative_path<P: AsRef<Path>>(self, base: P) -> Option<PathBuf> {
-        let base = base.as_ref();
-        match self {
+    fn path(&self) -> Option<&Path> {
+        match *self {
             LinkType::Escaped => None,
-            LinkType::IncludeRange(p, _) => Some(return_relative_path(base, &p)),
-            LinkType::IncludeRangeFrom(p, _) => Some(return_relative_path(base, &p)),
-            LinkType::IncludeRangeTo(p, _) => Some(return_relative_path(base, &p)),
-            LinkType::IncludeRangeFull(p, _) => Some(return_relative_path(base, &p)),
-            LinkType::Playpen(p, _) => Some(return_relative_path(base, &p)),
+            LinkType::IncludeRange(ref p, _) => Some(p),
+            LinkType::IncludeRangeFrom(ref p, _) => Some(p),
+            LinkType::IncludeRangeTo(ref p, _) => Some(p),
+            LinkType::IncludeRangeFull(ref p, _) => Some(p),
+            LinkType::IncludeAnchor(ref p, _) => Some(p),
+            LinkType::Playpen(ref p, _) => Some(p),
+            LinkType::RustdocInclude(ref p, _) => Some(p),
         }
     }
 }
-fn return_relative_path<P: AsRef<Path>>(base: P, relative: P) -> PathBuf {
-    base.as_ref()
-        .join(relative)
-        .parent()
-        .expect("Included file should not be /")
-        .to_path_buf()
-}
 
 fn parse_include_path(path: &str) -> LinkType<'static> {
     let mut parts = path.split(':');
     let path = parts.next().unwrap().into();
+    let first = parts.next();
+
+    // anything other than a line number is the name of an anchor
+    match first {
+        Some(anchor) if !anchor.is_empty() && anchor.parse::<usize>().is_err() => {
+            return LinkType::IncludeAnchor(path, anchor.to_string());
+        }
+        _ => {}
+    }
+
     // subtract 1 since line numbers usually begin with 1
-    let start = parts
-        .next()
+    let start = first
         .and_then(|s| s.parse::<usize>().ok())
         .map(|val| val.saturating_sub(1));
     let end = parts.next();
@@ -162,6 +341,21 @@ fn parse_include_path(path: &str) -> LinkType<'static> {
     }
 }
 
+fn parse_rustdoc_include_path(path: &str) -> LinkType<'static> {
+    let (path, range_or_anchor) = match parse_include_path(path) {
+        LinkType::IncludeRange(path, range) => {
+            (path, RangeOrAnchor::Range(range.start, Some(range.end)))
+        }
+        LinkType::IncludeRangeFrom(path, range) => (path, RangeOrAnchor::Range(range.start, None)),
+        LinkType::IncludeRangeTo(path, range) => (path, RangeOrAnchor::Range(0, Some(range.end))),
+        LinkType::IncludeRangeFull(path, _) => (path, RangeOrAnchor::Range(0, None)),
+        LinkType::IncludeAnchor(path, anchor) => (path, RangeOrAnchor::Anchor(anchor)),
+        other => unreachable!("{:?} isn't an include", other),
+    };
+
+    LinkType::RustdocInclude(path, range_or_anchor)
+}
+
 #[derive(PartialEq, Debug, Clone)]
 struct Link<'a> {
     start_index: usize,
@@ -180,6 +374,7 @@ impl<'a> Link<'a> {
 
                 match (typ.as_str(), file_arg) {
                     ("include", Some(pth)) => Some(parse_include_path(pth)),
+                    ("rustdoc_include", Some(pth)) => Some(parse_rustdoc_include_path(pth)),
                     ("playpen", Some(pth)) => Some(LinkType::Playpen(pth.into(), props)),
                     _ => None,
                 }
@@ -200,83 +395,127 @@ impl<'a> Link<'a> {
         })
     }
 
-    fn render_with_path<P: AsRef<Path>>(&self, base: P) -> Result<String> {
+    fn render_with_path<P: AsRef<Path>>(
+        &self,
+        base: P,
+        errors: &mut Vec<IncludeErrorReason>,
+    ) -> Option<String> {
         let base = base.as_ref();
         match self.link {
             // omit the escape char
-            LinkType::Escaped => Ok((&self.link_text[1..]).to_owned()),
+            LinkType::Escaped => Some((&self.link_text[1..]).to_owned()),
             LinkType::IncludeRange(ref pat, ref range) => {
                 let target = base.join(pat);
 
-                file_to_string(&target)
-                    .map(|s| take_lines(&s, range.clone()))
-                    .chain_err(|| {
-                        format!(
-                            "Could not read file for link {} ({})",
-                            self.link_text,
-                            target.display(),
-                        )
-                    })
+                read_target(&target, errors)
+                    .map(|s| take_lines_in_range(&s, range.clone(), &target, errors))
             }
             LinkType::IncludeRangeFrom(ref pat, ref range) => {
                 let target = base.join(pat);
 
-                file_to_string(&target)
-                    .map(|s| take_lines(&s, range.clone()))
-                    .chain_err(|| {
-                        format!(
-                            "Could not read file for link {} ({})",
-                            self.link_text,
-                            target.display(),
-                        )
-                    })
+                read_target(&target, errors)
+                    .map(|s| take_lines_in_range(&s, range.clone(), &target, errors))
             }
             LinkType::IncludeRangeTo(ref pat, ref range) => {
                 let target = base.join(pat);
 
-                file_to_string(&target)
-                    .map(|s| take_lines(&s, *range))
-                    .chain_err(|| {
-                        format!(
-                            "Could not read file for link {} ({})",
-                            self.link_text,
-                            target.display(),
-                        )
-                    })
+                read_target(&target, errors)
+                    .map(|s| take_lines_in_range(&s, *range, &target, errors))
             }
             LinkType::IncludeRangeFull(ref pat, _) => {
                 let target = base.join(pat);
 
-                file_to_string(&target).chain_err(|| {
-                    format!(
-                        "Could not read file for link {} ({})",
-                        self.link_text,
-                        target.display()
-                    )
-                })
+                read_target(&target, errors).map(|s| strip_anchors(&s))
+            }
+            LinkType::IncludeAnchor(ref pat, ref anchor) => {
+                let target = base.join(pat);
+
+                let contents = read_target(&target, errors)?;
+                let lines = take_anchored_lines(&contents, anchor);
+                if lines.is_none() {
+                    errors.push(IncludeErrorReason::AnchorNotFound(target, anchor.clone()));
+                }
+                lines
             }
             LinkType::Playpen(ref pat, ref attrs) => {
                 let target = base.join(pat);
 
-                let contents = file_to_string(&target).chain_err(|| {
-                    format!(
-                        "Could not read file for link {} ({})",
-                        self.link_text,
-                        target.display()
-                    )
-                })?;
+                let contents = strip_anchors(&read_target(&target, errors)?);
                 let ftype = if !attrs.is_empty() { "rust," } else { "rust" };
-                Ok(format!(
+                Some(format!(
                     "```{}{}\n{}\n```\n",
                     ftype,
                     attrs.join(","),
                     contents
                 ))
             }
+            LinkType::RustdocInclude(ref pat, ref range_or_anchor) => {
+                let target = base.join(pat);
+
+                let contents = read_target(&target, errors)?;
+                match *range_or_anchor {
+                    RangeOrAnchor::Range(start, Some(end)) => {
+                        check_range(&contents, &(start..end), &target, errors);
+                        Some(take_rustdoc_include_lines(&contents, start..end))
+                    }
+                    RangeOrAnchor::Range(start, None) => {
+                        check_range(&contents, &(start..), &target, errors);
+                        Some(take_rustdoc_include_lines(&contents, start..))
+                    }
+                    RangeOrAnchor::Anchor(ref anchor) => {
+                        let lines = take_rustdoc_include_anchored_lines(&contents, anchor);
+                        if lines.is_none() {
+                            errors.push(IncludeErrorReason::AnchorNotFound(target, anchor.clone()));
+                        }
+                        lines
+                    }
+                }
+            }
         }
     }
 }
 
+fn read_target(target: &Path, errors: &mut Vec<IncludeErrorReason>) -> Option<String> {
+    match file_to_string(target) {
+        Ok(content) => Some(content),
+        Err(e) => {
+            let message = e.iter().join(": ");
+            errors.push(IncludeErrorReason::Unreadable(target.to_path_buf(), message));
+            None
+        }
+    }
+}
+
+/// Take a range of lines from an included file, making sure every requested
+/// line is actually there.
+fn take_lines_in_range<R: RangeArgument<usize>>(
+    content: &str,
+    range: R,
+    target: &Path,
+    errors: &mut Vec<IncludeErrorReason>,
+) -> String {
+    check_range(content, &range, target, errors);
+    strip_anchors(&take_lines(content, range))
+}
+
+fn check_range<R: RangeArgument<usize>>(
+    content: &str,
+    range: &R,
+    target: &Path,
+    errors: &mut Vec<IncludeErrorReason>,
+) {
+    let lines = content.lines().count();
+    let start = *range.start().unwrap_or(&0);
+
+    let in_range = match range.end() {
+        Some(&end) => start < end && end <= lines,
+        None => start == 0 || start < lines,
+    };
+    if !in_range {
+        errors.push(IncludeErrorReason::OutOfRange(target.to_path_buf(), lines));
+    }
+}
+
 struct LinkIter<'a>(CaptureMatches<'a, 'a>);
 
 impl<'a> Iterator for LinkIter<'a> {
@@ -293,14 +532,14 @@ impl<'a> Iterator for LinkIter<'a> {
 
 fn find_links(contents: &str) -> LinkIter {
     // lazily compute following regex
-    // r"\\\{\{#.*\}\}|\{\{#([a-zA-Z0-9]+)\s*([a-zA-Z0-9_.\-:/\\\s]+)\}\}")?;
+    // r"\\\{\{#.*\}\}|\{\{#([a-zA-Z0-9_]+)\s*([a-zA-Z0-9_.\-:/\\\s]+)\}\}")?;
     lazy_static! {
         static ref RE: Regex = Regex::new(
             r"(?x)                     # insignificant whitespace mode
             \\\{\{\#.*\}\}             # match escaped link
             |                          # or
             \{\{\s*                    # link opening parens and whitespace
-            \#([a-zA-Z0-9]+)           # link type
+            \#([a-zA-Z0-9_]+)          # link type
             \s+                        # separating whitespace
             ([a-zA-Z0-9\s_.\-:/\\]+)   # link target path and space separated properties
             \s*\}\}                    # whitespace and link closing parens"
@@ -312,6 +551,18 @@ fn find_links(contents: &str) -> LinkIter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::{Builder as TempFileBuilder, TempDir};
+
+    fn dir_with_files(files: &[(&str, &str)]) -> TempDir {
+        let temp = TempFileBuilder::new().prefix("links").tempdir().unwrap();
+        for &(name, content) in files {
+            let mut f = File::create(temp.path().join(name)).unwrap();
+            f.write_all(content.as_bytes()).unwrap();
+        }
+        temp
+    }
 
     #[test]
     fn test_replace_all_escaped() {
@@ -325,7 +576,172 @@ mod tests {
         ```hbs
         {{#include file.rs}} << an escaped link!
         ```";
-        assert_eq!(replace_all(start, "", "", 0), end);
+        assert_eq!(replace_all(start, "", "", &mut Vec::new()), end);
+    }
+
+    #[test]
+    fn missing_files_are_reported_with_their_location() {
+        let temp = dir_with_files(&[]);
+        let content = "# Chapter\n\nSome code: {{#include missing.rs}}\n";
+        let mut errors = Vec::new();
+
+        let got = replace_all(content, temp.path(), "nested/chapter.md", &mut errors);
+
+        assert_eq!(got, content);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].chapter, PathBuf::from("nested/chapter.md"));
+        assert_eq!((errors[0].line, errors[0].column), (3, 12));
+        assert_eq!(errors[0].link, "{{#include missing.rs}}");
+        match errors[0].reason {
+            IncludeErrorReason::Unreadable(ref path, _) => {
+                assert_eq!(path, &temp.path().join("missing.rs"))
+            }
+            ref other => panic!("Unexpected reason: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cyclic_includes_are_detected() {
+        let temp = dir_with_files(&[
+            ("chapter.md", "{{#include chapter.md}}"),
+            ("a.md", "A\n{{#include b.md}}"),
+            ("b.md", "B\n{{#include a.md}}"),
+        ]);
+        let mut errors = Vec::new();
+
+        let got = replace_all("{{#include a.md}}", temp.path(), "chapter.md", &mut errors);
+
+        assert_eq!(got, "A\nB\n{{#include a.md}}");
+        assert_eq!(
+            errors,
+            vec![IncludeError {
+                chapter: PathBuf::from("chapter.md"),
+                line: 1,
+                column: 1,
+                link: String::from("{{#include a.md}}"),
+                reason: IncludeErrorReason::Cyclic(temp.path().join("a.md")),
+            }]
+        );
+
+        let mut errors = Vec::new();
+        replace_all("{{#include chapter.md}}", temp.path(), "chapter.md", &mut errors);
+        assert_eq!(
+            errors[0].reason,
+            IncludeErrorReason::Cyclic(temp.path().join("chapter.md"))
+        );
+    }
+
+    #[test]
+    fn deeply_nested_includes_are_cut_off() {
+        let files: Vec<(String, String)> = (0..12)
+            .map(|i| (format!("{}.md", i), format!("{}\n{{{{#include {}.md}}}}", i, i + 1)))
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(n, c)| (&**n, &**c)).collect();
+        let temp = dir_with_files(&files);
+        let mut errors = Vec::new();
+
+        let got = replace_all("{{#include 0.md}}", temp.path(), "chapter.md", &mut errors);
+
+        assert!(got.ends_with("9\n{{#include 10.md}}"));
+        assert_eq!(
+            errors[0].reason,
+            IncludeErrorReason::TooDeeplyNested(temp.path().join("10.md"))
+        );
+    }
+
+    #[test]
+    fn including_the_same_file_twice_is_not_a_cycle() {
+        let temp = dir_with_files(&[("a.rs", "fn main() {}")]);
+        let mut errors = Vec::new();
+
+        let got = replace_all(
+            "{{#include a.rs}}\n{{#include a.rs}}",
+            temp.path(),
+            "chapter.md",
+            &mut errors,
+        );
+
+        assert_eq!(got, "fn main() {}\nfn main() {}");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn include_the_lines_between_anchors() {
+        let temp = dir_with_files(&[(
+            "main.rs",
+            "// ANCHOR: all\nfn main() {\n    // ANCHOR: body\n    let x = 42;\n    \
+             // ANCHOR_END: body\n}\n// ANCHOR_END: all\n",
+        )]);
+        let content =
+            "{{#include main.rs:body}}\n\n{{#include main.rs}}\n\n{{#include main.rs:2:3}}";
+        let mut errors = Vec::new();
+
+        let got = replace_all(content, temp.path(), "chapter.md", &mut errors);
+
+        assert_eq!(
+            got,
+            "    let x = 42;\n\nfn main() {\n    let x = 42;\n}\n\n\nfn main() {"
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn rustdoc_includes_hide_everything_outside_the_range() {
+        let temp = dir_with_files(&[(
+            "main.rs",
+            "fn main() {\n    // ANCHOR: body\n    let x = 42;\n    // ANCHOR_END: body\n}\n",
+        )]);
+        let content = "{{#rustdoc_include main.rs:body}}\n\n{{#rustdoc_include main.rs:3}}";
+        let mut errors = Vec::new();
+
+        let got = replace_all(content, temp.path(), "chapter.md", &mut errors);
+
+        assert_eq!(
+            got,
+            "# fn main() {\n    let x = 42;\n# }\n\n# fn main() {\n    let x = 42;\n# }"
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn missing_anchors_are_reported() {
+        let temp = dir_with_files(&[("main.rs", "// ANCHOR: body\nfn main() {}\n")]);
+        let mut errors = Vec::new();
+
+        let got = replace_all("{{#include main.rs:nope}}", temp.path(), "", &mut errors);
+
+        assert_eq!(got, "{{#include main.rs:nope}}");
+        assert_eq!(
+            errors.iter().map(|e| e.reason.clone()).collect::<Vec<_>>(),
+            vec![IncludeErrorReason::AnchorNotFound(
+                temp.path().join("main.rs"),
+                String::from("nope")
+            )]
+        );
+    }
+
+    #[test]
+    fn out_of_range_line_spans_are_reported() {
+        let temp = dir_with_files(&[("a.rs", "one\ntwo\nthree\n")]);
+        let in_range = ["a.rs:1", "a.rs:2:3", "a.rs:3:", "a.rs::3", "a.rs"];
+        let out_of_range = ["a.rs:4", "a.rs:2:5", "a.rs:3:2", "a.rs:5:", "a.rs::4"];
+
+        for link in &in_range {
+            let mut errors = Vec::new();
+            replace_all(&format!("{{{{#include {}}}}}", link), temp.path(), "", &mut errors);
+            assert!(errors.is_empty(), "{} should be in range", link);
+        }
+
+        for link in &out_of_range {
+            let mut errors = Vec::new();
+            replace_all(&format!("{{{{#include {}}}}}", link), temp.path(), "", &mut errors);
+            assert_eq!(
+                errors.iter().map(|e| e.reason.clone()).collect::<Vec<_>>(),
+                vec![IncludeErrorReason::OutOfRange(temp.path().join("a.rs"), 3)],
+                "{} should be out of range",
+                link
+            );
+        }
     }
 
     #[test]
@@ -446,6 +862,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_links_with_anchor() {
+        let s = "Some random text with {{#include file.rs:anchor-name}}...";
+        let res = find_links(s).collect::<Vec<_>>();
+        println!("\nOUTPUT: {:?}\n", res);
+        assert_eq!(
+            res,
+            vec![Link {
+                start_index: 22,
+                end_index: 54,
+                link: LinkType::IncludeAnchor(
+                    PathBuf::from("file.rs"),
+                    String::from("anchor-name")
+                ),
+                link_text: "{{#include file.rs:anchor-name}}",
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_rustdoc_includes() {
+        let s = "{{#rustdoc_include file.rs:2:4}} {{#rustdoc_include file.rs:anchor}}";
+        let res = find_links(s).collect::<Vec<_>>();
+        println!("\nOUTPUT: {:?}\n", res);
+        assert_eq!(res.len(), 2);
+        assert_eq!(
+            res[0].link,
+            LinkType::RustdocInclude(PathBuf::from("file.rs"), RangeOrAnchor::Range(1, Some(4)))
+        );
+        assert_eq!(
+            res[1].link,
+            LinkType::RustdocInclude(
+                PathBuf::from("file.rs"),
+                RangeOrAnchor::Anchor(String::from("anchor"))
+            )
+        );
+    }
+
     #[test]
     fn test_find_links_with_full_range() {
         let s = "Some random text with {{#include file.rs::}}...";
@@ -1,10 +1,14 @@
 //! Book preprocessing.
 
 pub use self::cmd::CmdPreprocessor;
+pub use self::diagrams::DiagramPreprocessor;
+pub use self::glossary::GlossaryPreprocessor;
 pub use self::index::IndexPreprocessor;
-pub use self::links::LinkPreprocessor;
+pub use self::links::{IncludeError, IncludeErrorReason, LinkPreprocessor};
 
 mod cmd;
+mod diagrams;
+mod glossary;
 mod index;
 mod links;
 
@@ -0,0 +1,618 @@
+//! The built-in EPUB renderer.
+//!
+//! Each chapter is rendered to its own XHTML document and the whole lot is
+//! packaged up into a single EPUB 3 file, alongside a table of contents (both
+//! the EPUB 3 navigation document and a legacy NCX file for older readers) and
+//! any images the chapters reference.
+
+use std::fs::{self, File};
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use handlebars::html_escape;
+use regex::Regex;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use book::{Book, BookItem, Chapter};
+use config::EpubConfig;
+use errors::*;
+use renderer::html_handlebars::build_header_links;
+use renderer::{RenderContext, Renderer};
+use utils;
+use utils::fs::{normalize_path, path_to_root, resolve_relative_path};
+
+use self::xhtml::html_to_xhtml;
+
+mod xhtml;
+
+static STYLESHEET: &'static [u8] = include_bytes!("style.css");
+
+const CONTAINER: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A renderer which packages the book up as a single EPUB file.
+#[derive(Debug, Default)]
+pub struct EpubRenderer;
+
+impl EpubRenderer {
+    /// Create a new `EpubRenderer`.
+    pub fn new() -> Self {
+        EpubRenderer
+    }
+}
+
+impl Renderer for EpubRenderer {
+    fn name(&self) -> &str {
+        "epub"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let cfg: EpubConfig = match ctx.config.get("output.epub") {
+            Some(_) => ctx
+                .config
+                .get_deserialized("output.epub")
+                .chain_err(|| "Invalid configuration for the EPUB renderer")?,
+            None => EpubConfig::default(),
+        };
+        let title = ctx.config.book.title.clone().unwrap_or_default();
+
+        let mut package = Package::new(ctx, &cfg)?;
+
+        for item in ctx.book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if !ch.is_draft() {
+                    package.add_chapter(ch)?;
+                }
+            }
+        }
+
+        let filename = match cfg.filename {
+            Some(ref filename) => filename.clone(),
+            None if !title.is_empty() => utils::normalize_id(&title),
+            None => String::from("book"),
+        };
+
+        fs::create_dir_all(&ctx.destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+        let destination = ctx.destination.join(filename).with_extension("epub");
+        debug!("Writing the EPUB to {}", destination.display());
+
+        let f = File::create(&destination)
+            .chain_err(|| format!("Unable to create {}", destination.display()))?;
+        package.write(f, &ctx.book, &title)
+    }
+}
+
+/// A single file in the EPUB's `OEBPS` directory.
+#[derive(Debug, Clone, PartialEq)]
+struct Resource {
+    id: String,
+    /// The file's location, relative to `OEBPS` and using forward slashes.
+    href: String,
+    media_type: &'static str,
+    properties: Option<&'static str>,
+    data: Vec<u8>,
+}
+
+/// Everything which will eventually end up in the EPUB.
+struct Package<'a> {
+    ctx: &'a RenderContext,
+    cfg: &'a EpubConfig,
+    identifier: String,
+    /// The language the book is written in, defaulting to English.
+    language: String,
+    stylesheets: Vec<String>,
+    chapters: Vec<Resource>,
+    resources: Vec<Resource>,
+}
+
+impl<'a> Package<'a> {
+    fn new(ctx: &'a RenderContext, cfg: &'a EpubConfig) -> Result<Package<'a>> {
+        let identifier = match cfg.identifier {
+            Some(ref id) => id.clone(),
+            None => {
+                let title = ctx.config.book.title.clone().unwrap_or_default();
+                format!("urn:mdbook:{}", utils::normalize_id(&title))
+            }
+        };
+
+        let language = match ctx.config.book.language {
+            Some(ref language) => language.clone(),
+            None => ctx.config.default_language()?,
+        };
+
+        let mut package = Package {
+            ctx,
+            cfg,
+            identifier,
+            language,
+            stylesheets: vec![String::from("style.css")],
+            chapters: Vec::new(),
+            resources: vec![Resource {
+                id: String::from("style"),
+                href: String::from("style.css"),
+                media_type: "text/css",
+                properties: None,
+                data: STYLESHEET.to_vec(),
+            }],
+        };
+
+        for (i, css) in cfg.additional_css.iter().enumerate() {
+            let data = read_file(&ctx.root.join(css))?;
+            let href = href_for(&resolve_relative_path(css));
+
+            package.stylesheets.push(href.clone());
+            package.resources.push(Resource {
+                id: format!("css-{}", i),
+                href,
+                media_type: "text/css",
+                properties: None,
+                data,
+            });
+        }
+
+        if let Some(ref cover) = cfg.cover_image {
+            let href = href_for(&resolve_relative_path(cover));
+            let media_type = match media_type(&href) {
+                Some(media_type) => media_type,
+                None => bail!("The cover image ({}) isn't a supported format", cover.display()),
+            };
+            let data = read_file(&ctx.source_dir().join(cover))?;
+
+            package.resources.push(Resource {
+                id: String::from("cover-image"),
+                href,
+                media_type,
+                properties: Some("cover-image"),
+                data,
+            });
+        }
+
+        Ok(package)
+    }
+
+    fn add_chapter(&mut self, ch: &Chapter) -> Result<()> {
+        let href = href_for(&ch.path.with_extension("html"));
+        let id = format!("chapter-{}", self.chapters.len());
+        debug!("Rendering {} as {}", ch.path.display(), href);
+
+        let content = utils::render_markdown(&ch.content, self.cfg.curly_quotes);
+        let content = build_header_links(&content);
+        self.add_images(ch, &content);
+        let content = html_to_xhtml(&content);
+
+        let root = path_to_root(&ch.path);
+        let stylesheets: Vec<String> = self
+            .stylesheets
+            .iter()
+            .map(|href| format!("{}{}", root, href))
+            .collect();
+        let document = xhtml_document(&ch.name, &self.language, &stylesheets, &content);
+
+        self.chapters.push(Resource {
+            id,
+            href,
+            media_type: "application/xhtml+xml",
+            properties: None,
+            data: document.into_bytes(),
+        });
+
+        Ok(())
+    }
+
+    /// Embed any local images a chapter refers to.
+    fn add_images(&mut self, ch: &Chapter, content: &str) {
+        lazy_static! {
+            static ref IMG_SRC: Regex = Regex::new(r#"<img\s[^>]*?src="([^"]+)""#).unwrap();
+        }
+
+        let base = ch.path.parent().unwrap_or_else(|| Path::new(""));
+
+        for caps in IMG_SRC.captures_iter(content) {
+            let src = &caps[1];
+            if src.starts_with('/') || src.contains("://") || src.starts_with("data:") {
+                continue;
+            }
+
+            let href = href_for(&resolve_relative_path(base.join(src)));
+            if self.resources.iter().any(|r| r.href == href) {
+                continue;
+            }
+
+            let media_type = match media_type(&href) {
+                Some(media_type) => media_type,
+                None => {
+                    warn!(
+                        "Unable to embed \"{}\" (from {}), unsupported image format",
+                        src,
+                        ch.path.display()
+                    );
+                    continue;
+                }
+            };
+
+            let path = self.ctx.source_dir().join(&href);
+            match read_file(&path) {
+                Ok(data) => self.resources.push(Resource {
+                    id: format!("image-{}", self.resources.len()),
+                    href,
+                    media_type,
+                    properties: None,
+                    data,
+                }),
+                Err(e) => {
+                    warn!("Unable to embed \"{}\" (from {})", src, ch.path.display());
+                    warn!("\tCaused By: {}", e);
+                }
+            }
+        }
+    }
+
+    fn write<W: Write + Seek>(&self, writer: W, book: &Book, title: &str) -> Result<()> {
+        let toc = toc_entries(&book.sections);
+        let mut zip = ZipWriter::new(writer);
+
+        // The mimetype must come first and can't be compressed, that way
+        // readers can identify the file just by looking at its first few bytes
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored)
+            .chain_err(|| "Unable to write the mimetype")?;
+        zip.write_all(b"application/epub+zip")?;
+
+        let mut files = vec![
+            (String::from("META-INF/container.xml"), CONTAINER.as_bytes().to_vec()),
+            (String::from("OEBPS/content.opf"), self.opf(title).into_bytes()),
+            (String::from("OEBPS/toc.ncx"), self.ncx(&toc, title).into_bytes()),
+            (String::from("OEBPS/nav.xhtml"), self.nav(&toc, title).into_bytes()),
+        ];
+        files.extend(
+            self.chapters
+                .iter()
+                .chain(&self.resources)
+                .map(|r| (format!("OEBPS/{}", r.href), r.data.clone())),
+        );
+
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in files {
+            zip.start_file(name.as_str(), deflated)
+                .chain_err(|| format!("Unable to add {} to the EPUB", name))?;
+            zip.write_all(&data)?;
+        }
+
+        zip.finish().chain_err(|| "Unable to finish writing the EPUB")?;
+        Ok(())
+    }
+
+    /// The package document, which lists every file in the EPUB and the order
+    /// chapters should be read in.
+    fn opf(&self, title: &str) -> String {
+        let book = &self.ctx.config.book;
+        let mut metadata = Vec::new();
+
+        metadata.push(format!(
+            r#"<dc:identifier id="book-id">{}</dc:identifier>"#,
+            html_escape(&self.identifier)
+        ));
+        metadata.push(format!("<dc:title>{}</dc:title>", html_escape(title)));
+        metadata.push(format!(
+            "<dc:language>{}</dc:language>",
+            html_escape(&self.language)
+        ));
+        for author in &book.authors {
+            metadata.push(format!("<dc:creator>{}</dc:creator>", html_escape(author)));
+        }
+        if let Some(ref description) = book.description {
+            metadata.push(format!(
+                "<dc:description>{}</dc:description>",
+                html_escape(description)
+            ));
+        }
+        metadata.push(format!(
+            r#"<meta property="dcterms:modified">{}</meta>"#,
+            Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        ));
+        if self.cfg.cover_image.is_some() {
+            metadata.push(String::from(r#"<meta name="cover" content="cover-image"/>"#));
+        }
+
+        let mut manifest = vec![
+            String::from(
+                r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+            ),
+            String::from(r#"<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#),
+        ];
+        for resource in self.chapters.iter().chain(&self.resources) {
+            let properties = match resource.properties {
+                Some(properties) => format!(r#" properties="{}""#, properties),
+                None => String::new(),
+            };
+            manifest.push(format!(
+                r#"<item id="{}" href="{}" media-type="{}"{}/>"#,
+                resource.id,
+                html_escape(&resource.href),
+                resource.media_type,
+                properties
+            ));
+        }
+
+        let spine: Vec<String> = self
+            .chapters
+            .iter()
+            .map(|ch| format!(r#"<itemref idref="{}"/>"#, ch.id))
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    {}
+  </metadata>
+  <manifest>
+    {}
+  </manifest>
+  <spine toc="ncx">
+    {}
+  </spine>
+</package>
+"#,
+            metadata.join("\n    "),
+            manifest.join("\n    "),
+            spine.join("\n    ")
+        )
+    }
+
+    /// The EPUB 2 table of contents, for the benefit of older readers.
+    fn ncx(&self, toc: &[TocEntry], title: &str) -> String {
+        fn nav_points(entries: &[TocEntry], play_order: &mut usize, out: &mut String) {
+            for entry in entries {
+                *play_order += 1;
+                out.push_str(&format!(
+                    r#"<navPoint id="navpoint-{0}" playOrder="{0}"><navLabel><text>{1}</text></navLabel><content src="{2}"/>"#,
+                    play_order,
+                    html_escape(&entry.label),
+                    html_escape(&entry.href)
+                ));
+                nav_points(&entry.children, play_order, out);
+                out.push_str("</navPoint>\n");
+            }
+        }
+
+        let mut nav_map = String::new();
+        nav_points(toc, &mut 0, &mut nav_map);
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head>
+  <meta name="dtb:uid" content="{}"/>
+  <meta name="dtb:depth" content="{}"/>
+  <meta name="dtb:totalPageCount" content="0"/>
+  <meta name="dtb:maxPageNumber" content="0"/>
+</head>
+<docTitle><text>{}</text></docTitle>
+<navMap>
+{}</navMap>
+</ncx>
+"#,
+            html_escape(&self.identifier),
+            depth(toc),
+            html_escape(title),
+            nav_map
+        )
+    }
+
+    /// The EPUB 3 navigation document.
+    fn nav(&self, toc: &[TocEntry], title: &str) -> String {
+        fn list(entries: &[TocEntry], out: &mut String) {
+            out.push_str("<ol>\n");
+            for entry in entries {
+                out.push_str(&format!(
+                    r#"<li><a href="{}">{}</a>"#,
+                    html_escape(&entry.href),
+                    html_escape(&entry.label)
+                ));
+                if !entry.children.is_empty() {
+                    list(&entry.children, out);
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ol>\n");
+        }
+
+        let mut content = format!(
+            r#"<nav epub:type="toc" id="toc"><h1>{}</h1>"#,
+            html_escape(title)
+        );
+        list(toc, &mut content);
+        content.push_str("</nav>");
+
+        xhtml_document(title, &self.language, &self.stylesheets, &content)
+    }
+}
+
+/// An entry in the table of contents.
+#[derive(Debug, Clone, PartialEq)]
+struct TocEntry {
+    label: String,
+    href: String,
+    children: Vec<TocEntry>,
+}
+
+fn toc_entries(items: &[BookItem]) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+
+    for item in items {
+        if let BookItem::Chapter(ref ch) = *item {
+            let children = toc_entries(&ch.sub_items);
+
+            if ch.is_draft() {
+                // there's nothing to link to, so hoist the nested chapters up
+                entries.extend(children);
+            } else {
+                entries.push(TocEntry {
+                    label: ch.to_string(),
+                    href: href_for(&ch.path.with_extension("html")),
+                    children,
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+fn depth(entries: &[TocEntry]) -> usize {
+    entries
+        .iter()
+        .map(|entry| 1 + depth(&entry.children))
+        .max()
+        .unwrap_or(0)
+}
+
+fn xhtml_document(title: &str, language: &str, stylesheets: &[String], body: &str) -> String {
+    let links: Vec<String> = stylesheets
+        .iter()
+        .map(|href| {
+            format!(
+                r#"<link rel="stylesheet" type="text/css" href="{}"/>"#,
+                html_escape(href)
+            )
+        }).collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="{lang}" xml:lang="{lang}">
+<head>
+  <meta charset="UTF-8"/>
+  <title>{}</title>
+  {}
+</head>
+<body>
+{}
+</body>
+</html>
+"#,
+        html_escape(title),
+        links.join("\n  "),
+        body,
+        lang = html_escape(language)
+    )
+}
+
+/// Get a file's location inside the EPUB, using forward slashes regardless of
+/// platform.
+fn href_for(path: &Path) -> String {
+    normalize_path(&path.to_string_lossy())
+}
+
+fn media_type(href: &str) -> Option<&'static str> {
+    let extension = Path::new(href)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    match &*extension {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        "css" => Some("text/css"),
+        _ => None,
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).chain_err(|| format!("Unable to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::SectionNumber;
+
+    fn chapter(name: &str, path: &str, number: Vec<u32>, sub_items: Vec<BookItem>) -> BookItem {
+        let mut ch = Chapter::new(name, String::new(), path, Vec::new());
+        ch.number = Some(SectionNumber(number));
+        ch.sub_items = sub_items;
+        BookItem::Chapter(ch)
+    }
+
+    #[test]
+    fn toc_follows_the_book_hierarchy() {
+        let items = vec![
+            chapter(
+                "First",
+                "first.md",
+                vec![1],
+                vec![chapter("Nested", "first/nested.md", vec![1, 1], Vec::new())],
+            ),
+            BookItem::Separator,
+            chapter("Second", "second.md", vec![2], Vec::new()),
+        ];
+
+        let got = toc_entries(&items);
+
+        assert_eq!(
+            got,
+            vec![
+                TocEntry {
+                    label: String::from("1. First"),
+                    href: String::from("first.html"),
+                    children: vec![TocEntry {
+                        label: String::from("1.1. Nested"),
+                        href: String::from("first/nested.html"),
+                        children: Vec::new(),
+                    }],
+                },
+                TocEntry {
+                    label: String::from("2. Second"),
+                    href: String::from("second.html"),
+                    children: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(depth(&got), 2);
+    }
+
+    #[test]
+    fn chapters_without_a_file_are_left_out_of_the_toc() {
+        let items = vec![chapter(
+            "Placeholder",
+            "",
+            vec![1],
+            vec![chapter("Nested", "nested.md", vec![1, 1], Vec::new())],
+        )];
+
+        let got = toc_entries(&items);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].href, "nested.html");
+    }
+
+    #[test]
+    fn recognise_image_media_types() {
+        assert_eq!(media_type("images/cover.PNG"), Some("image/png"));
+        assert_eq!(media_type("diagram.svg"), Some("image/svg+xml"));
+        assert_eq!(media_type("photo.jpeg"), Some("image/jpeg"));
+        assert_eq!(media_type("archive.zip"), None);
+        assert_eq!(media_type("no-extension"), None);
+    }
+
+    #[test]
+    fn documents_are_in_the_books_language() {
+        let got = xhtml_document("Title", "fr", &[String::from("style.css")], "<p>Bonjour</p>");
+
+        assert!(got.contains(r#"lang="fr" xml:lang="fr""#));
+        assert!(got.contains(r#"href="style.css""#));
+    }
+}
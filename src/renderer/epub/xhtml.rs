@@ -0,0 +1,101 @@
+//! Turning the HTML rendered from a chapter into XHTML.
+
+use handlebars::html_escape;
+use html5ever::rcdom::{Handle, NodeData, RcDom};
+use html5ever::tendril::TendrilSink;
+use html5ever::{parse_fragment, Namespace, ParseOpts, QualName};
+
+/// Elements which can't have any content, so are written self-closed.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Parse some HTML the way a browser would and write it back out as
+/// well-formed XML, so raw HTML in a chapter (e.g. a `<br>`, a `&nbsp;` or an
+/// unclosed `<p>`) doesn't make the EPUB invalid.
+///
+/// Comments are dropped, since they can't always be written as XML.
+pub fn html_to_xhtml(html: &str) -> String {
+    let dom = parse_fragment(
+        RcDom::default(),
+        ParseOpts::default(),
+        QualName::new(None, ns!(html), local_name!("body")),
+        Vec::new(),
+    ).one(html);
+
+    let mut xhtml = String::new();
+    // the fragment is parsed into an `<html>` element
+    for root in dom.document.children.borrow().iter() {
+        for node in root.children.borrow().iter() {
+            write_node(node, &ns!(html), &mut xhtml);
+        }
+    }
+    xhtml
+}
+
+fn write_node(node: &Handle, parent_ns: &Namespace, xhtml: &mut String) {
+    match node.data {
+        NodeData::Text { ref contents } => xhtml.push_str(&html_escape(&contents.borrow())),
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => {
+            xhtml.push('<');
+            xhtml.push_str(&name.local);
+            // e.g. `<svg>` and `<math>` are in their own namespaces
+            if name.ns != *parent_ns {
+                xhtml.push_str(&format!(" xmlns=\"{}\"", html_escape(&name.ns)));
+            }
+
+            let attrs = attrs.borrow();
+            if attrs.iter().any(|attr| attr.name.ns == ns!(xlink)) {
+                xhtml.push_str(" xmlns:xlink=\"http://www.w3.org/1999/xlink\"");
+            }
+            for attr in attrs.iter().filter(|attr| attr.name.ns != ns!(xmlns)) {
+                xhtml.push(' ');
+                if let Some(ref prefix) = attr.name.prefix {
+                    xhtml.push_str(prefix);
+                    xhtml.push(':');
+                }
+                xhtml.push_str(&attr.name.local);
+                xhtml.push_str(&format!("=\"{}\"", html_escape(&attr.value)));
+            }
+
+            let children = node.children.borrow();
+            if children.is_empty() && VOID_ELEMENTS.contains(&&*name.local) {
+                xhtml.push_str("/>");
+                return;
+            }
+
+            xhtml.push('>');
+            for child in children.iter() {
+                write_node(child, &name.ns, xhtml);
+            }
+            xhtml.push_str(&format!("</{}>", name.local));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_html_becomes_well_formed_xml() {
+        let html = "<p>One<br>two&nbsp;&amp; <img src=\"a.png\" alt='\"A\"'></p>\n\
+                    <p>Unclosed\n<svg><use xlink:href=\"#icon\"></use></svg><!-- gone -->";
+
+        let got = html_to_xhtml(html);
+
+        assert_eq!(
+            got,
+            "<p>One<br/>two\u{a0}&amp; <img src=\"a.png\" alt=\"&quot;A&quot;\"/></p>\n\
+             <p>Unclosed\n<svg xmlns=\"http://www.w3.org/2000/svg\">\
+             <use xmlns:xlink=\"http://www.w3.org/1999/xlink\" xlink:href=\"#icon\"></use>\
+             </svg></p>"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Remembering what went into the previous build, so pages which haven't
+//! changed don't need to be rendered again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use errors::*;
+use utils;
+use utils::fs::CACHE_DIR;
+
+/// Get the file the cache for a build into `destination` is saved to. It's
+/// kept in the book's cache directory rather than the destination, so it
+/// isn't published along with the book.
+pub fn cache_file(root: &Path, destination: &Path) -> PathBuf {
+    root.join(CACHE_DIR)
+        .join("html")
+        .join(format!("{:016x}.json", hash(destination)))
+}
+
+/// Hashes of everything which went into a build.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// The version of `mdbook` which did the build.
+    version: String,
+    /// A hash of everything each page shares (configuration, theme, the table
+    /// of contents, etc). If this changes, every page needs to be rendered.
+    shared: u64,
+    /// A hash of each chapter's (preprocessed) content, keyed by its path.
+    chapters: BTreeMap<PathBuf, u64>,
+    /// The files copied from the source directory, relative to it.
+    files: BTreeSet<PathBuf>,
+}
+
+impl BuildCache {
+    /// Create an empty cache for a build whose pages share `shared`.
+    pub fn new(shared: u64) -> BuildCache {
+        BuildCache {
+            version: ::MDBOOK_VERSION.to_string(),
+            shared,
+            chapters: BTreeMap::new(),
+            files: BTreeSet::new(),
+        }
+    }
+
+    /// Load the cache left behind by the last build, if there was one and it
+    /// was generated by this version of `mdbook`.
+    pub fn load(path: &Path) -> Option<BuildCache> {
+        let content = utils::fs::file_to_string(path).ok()?;
+
+        match serde_json::from_str::<BuildCache>(&content) {
+            Ok(ref cache) if cache.version != ::MDBOOK_VERSION => None,
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("Ignoring the invalid build cache in {}", path.display());
+                warn!("\tCaused By: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Save the cache so the next build can use it.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string(self).chain_err(|| "Unable to serialize the cache")?;
+        utils::fs::create_file(path)?
+            .write_all(content.as_bytes())
+            .chain_err(|| format!("Unable to write the build cache to {}", path.display()))
+    }
+
+    /// Can pages rendered during the previous build be reused for this one?
+    pub fn is_compatible_with(&self, previous: &BuildCache) -> bool {
+        self.version == previous.version && self.shared == previous.shared
+    }
+
+    /// Record a chapter's content hash, returning `true` if it is the same as
+    /// in the previous build.
+    pub fn insert_chapter(
+        &mut self,
+        path: &Path,
+        content_hash: u64,
+        previous: Option<&BuildCache>,
+    ) -> bool {
+        self.chapters.insert(path.to_path_buf(), content_hash);
+
+        previous.and_then(|prev| prev.chapters.get(path)) == Some(&content_hash)
+    }
+
+    /// Record a file which was copied from the source directory.
+    pub fn insert_file(&mut self, path: &Path) {
+        self.files.insert(path.to_path_buf());
+    }
+
+    /// The outputs of the previous build which this build no longer has,
+    /// i.e. the pages of removed chapters and files removed from the source
+    /// directory, relative to the destination.
+    pub fn stale_outputs(&self, previous: &BuildCache) -> Vec<PathBuf> {
+        let pages = previous
+            .chapters
+            .keys()
+            .filter(|path| !self.chapters.contains_key(*path))
+            .map(|path| path.with_extension("html"));
+        let files = previous.files.difference(&self.files).cloned();
+
+        pages.chain(files).collect()
+    }
+}
+
+/// Calculate a hash of some arbitrary data.
+///
+/// This isn't guaranteed to be stable across versions of Rust, but because a
+/// different hash only causes a page to be rendered again that's not a problem.
+pub fn hash<H: Hash + ?Sized>(value: &H) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder as TempFileBuilder;
+
+    #[test]
+    fn round_trip_the_cache_through_the_cache_directory() {
+        let temp = TempFileBuilder::new().prefix("cache").tempdir().unwrap();
+        let path = cache_file(temp.path(), &temp.path().join("book"));
+        let mut cache = BuildCache::new(hash("shared"));
+        cache.insert_chapter(Path::new("first.md"), hash("# First"), None);
+        cache.insert_file(Path::new("images/logo.png"));
+
+        cache.save(&path).unwrap();
+        let got = BuildCache::load(&path).unwrap();
+
+        assert_eq!(got, cache);
+    }
+
+    #[test]
+    fn missing_or_outdated_caches_are_ignored() {
+        let temp = TempFileBuilder::new().prefix("cache").tempdir().unwrap();
+        let path = cache_file(temp.path(), &temp.path().join("book"));
+        assert!(BuildCache::load(&path).is_none());
+
+        let mut cache = BuildCache::new(hash("shared"));
+        cache.version = String::from("0.0.1");
+        cache.save(&path).unwrap();
+
+        assert!(BuildCache::load(&path).is_none());
+    }
+
+    #[test]
+    fn only_unchanged_chapters_are_reused() {
+        let mut previous = BuildCache::new(hash("shared"));
+        previous.insert_chapter(Path::new("first.md"), hash("# First"), None);
+        previous.insert_chapter(Path::new("second.md"), hash("# Second"), None);
+
+        let mut cache = BuildCache::new(hash("shared"));
+        assert!(cache.is_compatible_with(&previous));
+        assert!(cache.insert_chapter(Path::new("first.md"), hash("# First"), Some(&previous)));
+        assert!(!cache.insert_chapter(
+            Path::new("second.md"),
+            hash("# Second (edited)"),
+            Some(&previous)
+        ));
+        assert!(!cache.insert_chapter(Path::new("third.md"), hash("# Third"), Some(&previous)));
+
+        let other = BuildCache::new(hash("a different theme"));
+        assert!(!other.is_compatible_with(&previous));
+    }
+
+    #[test]
+    fn removed_chapters_and_files_are_stale() {
+        let mut previous = BuildCache::new(hash("shared"));
+        previous.insert_chapter(Path::new("first.md"), hash("# First"), None);
+        previous.insert_chapter(Path::new("old.md"), hash("# Old"), None);
+        previous.insert_file(Path::new("images/kept.png"));
+        previous.insert_file(Path::new("images/removed.png"));
+
+        let mut cache = BuildCache::new(hash("shared"));
+        cache.insert_chapter(Path::new("first.md"), hash("# First"), Some(&previous));
+        cache.insert_file(Path::new("images/kept.png"));
+
+        assert_eq!(
+            cache.stale_outputs(&previous),
+            vec![PathBuf::from("old.html"), PathBuf::from("images/removed.png")]
+        );
+    }
+}
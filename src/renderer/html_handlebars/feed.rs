@@ -0,0 +1,342 @@
+//! An Atom feed of the book's newest chapters.
+
+use std::cmp::Reverse;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde_json;
+
+use super::hbs_renderer::absolute_url;
+use super::sitemap::{escape_xml, last_modified};
+use book::{Book, BookItem, Chapter};
+use config::{Config, Feed, HtmlConfig};
+use errors::*;
+use utils;
+
+struct Entry<'a> {
+    chapter: &'a Chapter,
+    updated: DateTime<Utc>,
+}
+
+/// Write `feed.xml` to the destination directory.
+pub fn create_files(
+    feed: &Feed,
+    book: &Book,
+    config: &Config,
+    html_config: &HtmlConfig,
+    src_dir: &Path,
+    destination: &Path,
+    site_url: &str,
+) -> Result<()> {
+    let entries = find_entries(feed, book, src_dir)?;
+    let xml = render_feed(feed, config, html_config, &entries, site_url, Utc::now());
+
+    utils::fs::write_file(destination, "feed.xml", xml.as_bytes())?;
+    debug!("Creating feed.xml ✓");
+    Ok(())
+}
+
+/// The chapters in the feed, newest first.
+fn find_entries<'a>(feed: &Feed, book: &'a Book, src_dir: &Path) -> Result<Vec<Entry<'a>>> {
+    let mut entries = Vec::new();
+
+    match feed.section {
+        Some(ref section) => {
+            let parent = book
+                .iter()
+                .filter_map(|item| match *item {
+                    BookItem::Chapter(ref ch) => Some(ch),
+                    _ => None,
+                }).find(|ch| is_section(ch, section));
+            let parent = match parent {
+                Some(parent) => parent,
+                None => bail!("The feed's section ({}) isn't in the book", section.display()),
+            };
+
+            let mut chapters = Vec::new();
+            collect_chapters(&parent.sub_items, &mut chapters);
+
+            // chapters without a date use when they were last changed
+            for ch in chapters {
+                let updated = match chapter_date(ch)? {
+                    Some(date) => Some(date),
+                    None => last_modified(&src_dir.join(&ch.path)),
+                };
+                if let Some(updated) = updated {
+                    entries.push(Entry { chapter: ch, updated });
+                }
+            }
+        }
+        None => {
+            for item in book.iter() {
+                if let BookItem::Chapter(ref ch) = *item {
+                    if ch.is_draft() {
+                        continue;
+                    }
+                    if let Some(updated) = chapter_date(ch)? {
+                        entries.push(Entry { chapter: ch, updated });
+                    }
+                }
+            }
+        }
+    }
+
+    entries.sort_by_key(|entry| Reverse(entry.updated));
+    entries.truncate(feed.limit);
+    Ok(entries)
+}
+
+/// Is this the chapter at `section`? The `index` preprocessor renames
+/// `README.md` chapters to `index.md`, so either name is accepted.
+fn is_section(ch: &Chapter, section: &Path) -> bool {
+    if ch.path == section {
+        return true;
+    }
+
+    let is_readme = section
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_lowercase)
+        == Some(String::from("readme.md"));
+
+    is_readme && ch.path == section.with_file_name("index.md")
+}
+
+fn collect_chapters<'a>(items: &'a [BookItem], chapters: &mut Vec<&'a Chapter>) {
+    for item in items {
+        if let BookItem::Chapter(ref ch) = *item {
+            if !ch.is_draft() {
+                chapters.push(ch);
+            }
+            collect_chapters(&ch.sub_items, chapters);
+        }
+    }
+}
+
+/// The `date` from a chapter's front matter, if it has one.
+fn chapter_date(ch: &Chapter) -> Result<Option<DateTime<Utc>>> {
+    let date = match ch.metadata.get("date").and_then(serde_json::Value::as_str) {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+
+    match parse_date(date) {
+        Some(date) => Ok(Some(date)),
+        None => bail!(
+            "Unable to parse the date of \"{}\" ({}), expected something like \"2018-09-30\"",
+            ch.name,
+            date
+        ),
+    }
+}
+
+/// Parse an RFC 3339 date and time, or just a date. Times without a timezone
+/// are treated as UTC.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+        .ok()?;
+    Some(DateTime::from_utc(naive, Utc))
+}
+
+fn render_feed(
+    feed: &Feed,
+    config: &Config,
+    html_config: &HtmlConfig,
+    entries: &[Entry],
+    site_url: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let book_url = absolute_url(site_url, "");
+    let title = feed
+        .title
+        .as_ref()
+        .or(config.book.title.as_ref())
+        .map(String::as_str)
+        .unwrap_or("");
+    let updated = entries.first().map(|entry| entry.updated).unwrap_or(now);
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+    );
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("  <id>{}</id>\n", escape_xml(&book_url)));
+    xml.push_str(&format!("  <link href=\"{}\"/>\n", escape_xml(&book_url)));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}\"/>\n",
+        escape_xml(&absolute_url(site_url, "feed.xml"))
+    ));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    for author in &config.book.authors {
+        xml.push_str(&format!(
+            "  <author><name>{}</name></author>\n",
+            escape_xml(author)
+        ));
+    }
+
+    for entry in entries {
+        xml.push_str(&render_entry(entry, site_url, html_config));
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn render_entry(entry: &Entry, site_url: &str, html_config: &HtmlConfig) -> String {
+    let ch = entry.chapter;
+    let metadata_str = |key: &str| ch.metadata.get(key).and_then(serde_json::Value::as_str);
+
+    let page = ch.path.with_extension("html");
+    let url = escape_xml(&absolute_url(site_url, &page.to_string_lossy()));
+    let title = metadata_str("title").unwrap_or(&ch.name);
+
+    let mut xml = String::from("  <entry>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("    <id>{}</id>\n", url));
+    xml.push_str(&format!("    <link href=\"{}\"/>\n", url));
+    xml.push_str(&format!("    <updated>{}</updated>\n", entry.updated.to_rfc3339()));
+    if let Some(author) = metadata_str("author") {
+        xml.push_str(&format!(
+            "    <author><name>{}</name></author>\n",
+            escape_xml(author)
+        ));
+    }
+
+    let summary = match metadata_str("description") {
+        Some(description) => Some(escape_xml(description)),
+        None => first_paragraph(&utils::render_markdown_with_config(&ch.content, html_config)),
+    };
+    if let Some(summary) = summary {
+        // relative links in the summary are relative to the chapter's page
+        xml.push_str(&format!(
+            "    <summary type=\"html\" xml:base=\"{}\">{}</summary>\n",
+            url,
+            escape_xml(&summary)
+        ));
+    }
+
+    xml.push_str("  </entry>\n");
+    xml
+}
+
+/// The first `<p>` element of a chapter's rendered HTML.
+fn first_paragraph(html: &str) -> Option<String> {
+    let start = html.find("<p>")?;
+    let end = start + html[start..].find("</p>")? + "</p>".len();
+    Some(html[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn dated_chapter(name: &str, path: &str, date: &str) -> Chapter {
+        let content = format!("# {}\n\nWhat's new, see [the notes](notes.md).\n", name);
+        let mut ch = Chapter::new(name, content, path, Vec::new());
+        ch.metadata.insert("date".to_string(), json!(date));
+        ch
+    }
+
+    #[test]
+    fn parse_dates_with_and_without_times() {
+        let inputs = vec![
+            ("2018-09-30", "2018-09-30T00:00:00+00:00"),
+            ("2018-09-30T12:30:00Z", "2018-09-30T12:30:00+00:00"),
+            ("2018-09-30T12:30:00+02:00", "2018-09-30T10:30:00+00:00"),
+            ("2018-09-30T12:30:00", "2018-09-30T12:30:00+00:00"),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(parse_date(src).unwrap().to_rfc3339(), should_be);
+        }
+        assert!(parse_date("last tuesday").is_none());
+    }
+
+    #[test]
+    fn the_newest_dated_chapters_are_in_the_feed() {
+        let temp = TempFileBuilder::new().prefix("feed").tempdir().unwrap();
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()));
+        book.push_item(dated_chapter("Old", "old.md", "2018-01-01"));
+        book.push_item(dated_chapter("New", "new.md", "2018-03-01"));
+        book.push_item(dated_chapter("Newer", "newer.md", "2018-06-01"));
+        let feed = Feed {
+            limit: 2,
+            ..Default::default()
+        };
+
+        let entries = find_entries(&feed, &book, temp.path()).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.chapter.name.as_str()).collect();
+
+        assert_eq!(names, vec!["Newer", "New"]);
+    }
+
+    #[test]
+    fn a_sections_sub_chapters_are_in_the_feed() {
+        let temp = TempFileBuilder::new().prefix("feed").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "releases/undated.md", b"# Undated").unwrap();
+
+        let mut releases = Chapter::new("Releases", String::new(), "releases/index.md", Vec::new());
+        releases.sub_items = vec![
+            BookItem::Chapter(dated_chapter("v1.0", "releases/v1.0.md", "2018-01-01")),
+            BookItem::Chapter(Chapter::new_draft("v2.0", Vec::new())),
+            BookItem::Chapter(Chapter::new(
+                "Undated",
+                String::new(),
+                "releases/undated.md",
+                Vec::new(),
+            )),
+        ];
+        let mut book = Book::new();
+        book.push_item(dated_chapter("Elsewhere", "elsewhere.md", "2018-01-01"));
+        book.push_item(releases);
+        let feed = Feed {
+            section: Some("releases/README.md".into()),
+            ..Default::default()
+        };
+
+        let entries = find_entries(&feed, &book, temp.path()).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.chapter.name.as_str()).collect();
+
+        assert_eq!(names, vec!["Undated", "v1.0"]);
+    }
+
+    #[test]
+    fn render_an_atom_feed() {
+        let ch = dated_chapter("v1.0 & more", "releases/v1.0.md", "2018-01-01");
+        let entries = vec![Entry {
+            chapter: &ch,
+            updated: parse_date("2018-01-01").unwrap(),
+        }];
+        let mut config = Config::default();
+        config.book.title = Some(String::from("Release notes"));
+        config.book.authors = vec![String::from("Someone")];
+
+        let got = render_feed(
+            &Feed::default(),
+            &config,
+            &HtmlConfig::default(),
+            &entries,
+            "https://example.com/book",
+            Utc::now(),
+        );
+
+        assert!(got.contains("<title>Release notes</title>"));
+        assert!(got.contains("<link rel=\"self\" href=\"https://example.com/book/feed.xml\"/>"));
+        assert!(got.contains("  <updated>2018-01-01T00:00:00+00:00</updated>"));
+        assert!(got.contains("<author><name>Someone</name></author>"));
+        assert!(got.contains("<title>v1.0 &amp; more</title>"));
+        assert!(got.contains("<id>https://example.com/book/releases/v1.0.html</id>"));
+        assert!(got.contains(
+            "<summary type=\"html\" xml:base=\"https://example.com/book/releases/v1.0.html\">\
+             &lt;p&gt;What&apos;s new, see &lt;a href=&quot;notes.html&quot;&gt;"
+        ));
+    }
+}
@@ -1,20 +1,30 @@
-use book::{Book, BookItem};
-use config::{Config, HtmlConfig, Playpen};
+use book::{Book, BookItem, Chapter};
+use config::{Config, HtmlConfig, Playpen, RustEdition};
 use errors::*;
+use renderer::html_handlebars::cache::{self, BuildCache};
 use renderer::html_handlebars::helpers;
 use renderer::{RenderContext, Renderer};
 use theme::{self, playpen_editor, Theme};
 use utils;
 
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
 use handlebars::Handlebars;
+use pulldown_cmark::{Event, Parser, Tag};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use regex::{Captures, Regex};
 use serde_json;
 
+/// What the 404 page says if the book doesn't have a `404.md`.
+const DEFAULT_404: &str = "# Document not found (404)\n\n\
+                           This URL is invalid, sorry. Please use the navigation bar or search \
+                           to continue.\n";
+
 #[derive(Default)]
 pub struct HtmlHandlebars;
 
@@ -23,86 +33,124 @@ impl HtmlHandlebars {
         HtmlHandlebars
     }
 
-    fn render_item(
-        &self,
-        item: &BookItem,
-        mut ctx: RenderItemContext,
-        print_content: &mut String,
-    ) -> Result<()> {
+    /// Render a chapter to disk, returning its contents for the print page.
+    fn render_item(&self, ch: &Chapter, mut ctx: RenderItemContext) -> Result<String> {
         // FIXME: This should be made DRY-er and rely less on mutable state
-        if let BookItem::Chapter(ref ch) = *item {
-            let content = ch.content.clone();
-            let content = utils::render_markdown(&content, ctx.html_config.curly_quotes);
-
-            let string_path = ch.path.parent().unwrap().display().to_string();
+        let content = ch.content.clone();
+        let content = utils::render_markdown_with_config(&content, &ctx.html_config);
+
+        // Update the context with data for this file
+        let path = ch
+            .path
+            .to_str()
+            .chain_err(|| "Could not convert path to str")?;
+        let filepath = Path::new(&ch.path).with_extension("html");
+
+        // "print.html" is used for the print page.
+        if ch.path == Path::new("print.md") {
+            bail!(ErrorKind::ReservedFilenameError(ch.path.clone()));
+        };
 
-            let fixed_content = utils::render_markdown_with_base(&ch.content, ctx.html_config.curly_quotes, &string_path);
-            print_content.push_str(&fixed_content);
+        if ctx.unchanged {
+            debug!("{} hasn't changed since the last build", path);
+            return Ok(content);
+        }
 
-            // Update the context with data for this file
-            let path = ch
-                .path
-                .to_str()
-                .chain_err(|| "Could not convert path to str")?;
-            let filepath = Path::new(&ch.path).with_extension("html");
+        // The front matter can override the page's title and description
+        let metadata_str = |key: &str| ch.metadata.get(key).and_then(serde_json::Value::as_str);
 
-            // "print.html" is used for the print page.
-            if ch.path == Path::new("print.md") {
-                bail!(ErrorKind::ReservedFilenameError(ch.path.clone()));
-            };
+        // Non-lexical lifetimes needed :'(
+        let title: String;
+        {
+            let book_title = ctx
+                .data
+                .get("book_title")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("");
+            title = metadata_str("title").unwrap_or(&ch.name).to_string() + " - " + book_title;
+        }
 
-            // Non-lexical lifetimes needed :'(
-            let title: String;
-            {
-                let book_title = ctx
-                    .data
-                    .get("book_title")
-                    .and_then(serde_json::Value::as_str)
-                    .unwrap_or("");
-                title = ch.name.clone() + " - " + book_title;
-            }
+        let description = metadata_str("description")
+            .map(String::from)
+            .or_else(|| chapter_description(&ch.content));
+        if let Some(description) = description {
+            ctx.data.insert("description".to_owned(), json!(description));
+        }
+        if let Some(ref site_url) = ctx.html_config.site_url {
+            let url = absolute_url(site_url, filepath.to_str().unwrap_or_default());
+            ctx.data.insert("page_url".to_owned(), json!(url));
+        }
+        let site_url = &ctx.html_config.site_url;
+        if let Some(image) = metadata_str("image").and_then(|i| social_image_url(i, site_url)) {
+            ctx.data.insert("social_image".to_owned(), json!(image));
+        }
+        if let Some(author) = metadata_str("author") {
+            ctx.data.insert("author".to_owned(), json!(author));
+        }
 
-            ctx.data.insert("path".to_owned(), json!(path));
-            ctx.data.insert("content".to_owned(), json!(content));
-            ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
-            ctx.data.insert("title".to_owned(), json!(title));
-            ctx.data.insert(
-                "path_to_root".to_owned(),
-                json!(utils::fs::path_to_root(&ch.path)),
-            );
+        ctx.data.insert("path".to_owned(), json!(path));
+        ctx.data.insert("metadata".to_owned(), json!(ch.metadata));
+        ctx.data.insert("content".to_owned(), json!(&content));
+        ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
+        ctx.data.insert("title".to_owned(), json!(title));
+        ctx.data.insert(
+            "path_to_root".to_owned(),
+            json!(utils::fs::path_to_root(&ch.path)),
+        );
 
-            // Render the handlebars template with the data
-            debug!("Render template");
-            let rendered = ctx.handlebars.render("index", &ctx.data)?;
+        // Render the handlebars template with the data
+        debug!("Render template");
+        let rendered = ctx.handlebars.render("index", &ctx.data)?;
 
-            let rendered = self.post_process(rendered, &ctx.html_config.playpen);
+        let rendered = self.post_process(rendered, &ctx.html_config.playpen, ctx.edition);
 
-            // Write to file
-            debug!("Creating {}", filepath.display());
-            utils::fs::write_file(&ctx.destination, &filepath, rendered.as_bytes())?;
+        // Write to file
+        debug!("Creating {}", filepath.display());
+        utils::fs::write_file(&ctx.destination, &filepath, rendered.as_bytes())?;
 
-            if ctx.is_index {
-                ctx.data.insert("path".to_owned(), json!("index.html"));
-                ctx.data.insert("path_to_root".to_owned(), json!(""));
-                let rendered_index = ctx.handlebars.render("index", &ctx.data)?;
-                let rendered_index = self.post_process(rendered_index, &ctx.html_config.playpen);
-                debug!("Creating index.html from {}", path);
-                utils::fs::write_file(&ctx.destination, "index.html", rendered_index.as_bytes())?;
+        if ctx.is_index {
+            ctx.data.insert("path".to_owned(), json!("index.html"));
+            ctx.data.insert("path_to_root".to_owned(), json!(""));
+            if let Some(ref site_url) = ctx.html_config.site_url {
+                let url = absolute_url(site_url, "");
+                ctx.data.insert("page_url".to_owned(), json!(url));
             }
+            let rendered_index = ctx.handlebars.render("index", &ctx.data)?;
+            let rendered_index =
+                self.post_process(rendered_index, &ctx.html_config.playpen, ctx.edition);
+            debug!("Creating index.html from {}", path);
+            utils::fs::write_file(&ctx.destination, "index.html", rendered_index.as_bytes())?;
         }
 
-        Ok(())
+        Ok(content)
     }
 
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
-    fn post_process(&self, rendered: String, playpen_config: &Playpen) -> String {
+    fn post_process(
+        &self,
+        rendered: String,
+        playpen_config: &Playpen,
+        edition: Option<RustEdition>,
+    ) -> String {
         let rendered = build_header_links(&rendered);
         let rendered = fix_code_blocks(&rendered);
-        let rendered = add_playpen_pre(&rendered, playpen_config);
+        let rendered = add_playpen_pre(&rendered, playpen_config, edition);
 
         rendered
     }
 
+    /// Like `post_process()`, but the print page's headers already have links
+    /// (with IDs which are unique across every chapter).
+    fn post_process_print(
+        &self,
+        rendered: String,
+        playpen_config: &Playpen,
+        edition: Option<RustEdition>,
+    ) -> String {
+        let rendered = fix_code_blocks(&rendered);
+        add_playpen_pre(&rendered, playpen_config, edition)
+    }
+
     fn copy_static_files(
         &self,
         destination: &Path,
@@ -126,7 +174,9 @@ impl HtmlHandlebars {
         write_file(destination, "highlight.css", &theme.highlight_css)?;
         write_file(destination, "tomorrow-night.css", &theme.tomorrow_night_css)?;
         write_file(destination, "ayu-highlight.css", &theme.ayu_highlight_css)?;
-        write_file(destination, "highlight.js", &theme.highlight_js)?;
+        if !html_config.build_time_highlighting {
+            write_file(destination, "highlight.js", &theme.highlight_js)?;
+        }
         write_file(destination, "clipboard.min.js", &theme.clipboard_js)?;
         write_file(
             destination,
@@ -183,22 +233,102 @@ impl HtmlHandlebars {
         Ok(())
     }
 
-    /// Update the context with data for this file
+    /// Update the context with data for one of the print pages
     fn configure_print_version(
         &self,
         data: &mut serde_json::Map<String, serde_json::Value>,
+        file: &str,
         print_content: &str,
     ) {
+        let path = Path::new(file).with_extension("md");
+
         // Make sure that the Print chapter does not display the title from
         // the last rendered chapter by removing it from its context
         data.remove("title");
-        data.insert("is_print".to_owned(), json!(true));
-        data.insert("path".to_owned(), json!("print.md"));
+        data.entry("is_print").or_insert_with(|| json!(true));
+        data.insert("path".to_owned(), json!(path));
         data.insert("content".to_owned(), json!(print_content));
         data.insert(
             "path_to_root".to_owned(),
-            json!(utils::fs::path_to_root(Path::new("print.md"))),
+            json!(utils::fs::path_to_root(&path)),
         );
+
+        let page_url = data
+            .get("site_url")
+            .and_then(serde_json::Value::as_str)
+            .map(|site_url| absolute_url(site_url, file));
+        if let Some(page_url) = page_url {
+            data.insert("page_url".to_owned(), json!(page_url));
+        }
+    }
+
+    fn render_print_page(
+        &self,
+        ctx: &RenderContext,
+        handlebars: &Handlebars,
+        html_config: &HtmlConfig,
+        mut data: serde_json::Map<String, serde_json::Value>,
+        file: &str,
+        print_content: &str,
+    ) -> Result<()> {
+        self.configure_print_version(&mut data, file, print_content);
+        if let Some(ref title) = ctx.config.book.title {
+            data.insert("title".to_owned(), json!(title));
+        }
+
+        // Render the handlebars template with the data
+        debug!("Render template");
+        let rendered = handlebars.render("index", &data)?;
+
+        let rendered =
+            self.post_process_print(rendered, &html_config.playpen, ctx.config.rust.edition);
+
+        utils::fs::write_file(&ctx.destination, file, rendered.as_bytes())?;
+        debug!("Creating {} ✓", file);
+        Ok(())
+    }
+
+    /// Render `404.html` from `404.md` in the source directory, or a default
+    /// message if there isn't one.
+    fn render_404(
+        &self,
+        ctx: &RenderContext,
+        html_config: &HtmlConfig,
+        src_dir: &Path,
+        handlebars: &Handlebars,
+        mut data: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let content_404 = src_dir.join("404.md");
+        let content = if content_404.exists() {
+            utils::fs::file_to_string(&content_404)?
+        } else {
+            String::from(DEFAULT_404)
+        };
+        let content = utils::render_markdown_with_config(&content, html_config);
+
+        // The page is shown in place of any missing URL, so its links can't
+        // be relative to where it is
+        let path_to_root = match html_config.site_url {
+            Some(ref site_url) => absolute_url(site_url, ""),
+            None => String::from("/"),
+        };
+        let title = match ctx.config.book.title {
+            Some(ref book_title) => format!("Page not found - {}", book_title),
+            None => String::from("Page not found"),
+        };
+
+        data.remove("page_url");
+        data.insert("path".to_owned(), json!("404.md"));
+        data.insert("content".to_owned(), json!(content));
+        data.insert("title".to_owned(), json!(title));
+        data.insert("path_to_root".to_owned(), json!(path_to_root));
+
+        let rendered = handlebars.render("index", &data)?;
+        let rendered = self.post_process(rendered, &html_config.playpen, ctx.config.rust.edition);
+
+        utils::fs::write_file(&ctx.destination, "404.html", rendered.as_bytes())?;
+        debug!("Creating 404.html ✓");
+        Ok(())
     }
 
     fn register_hbs_helpers(&self, handlebars: &mut Handlebars, html_config: &HtmlConfig) {
@@ -310,62 +440,246 @@ impl Renderer for HtmlHandlebars {
         debug!("Register handlebars helpers");
         self.register_hbs_helpers(&mut handlebars, &html_config);
 
-        let mut data = make_data(&ctx.root, &book, &ctx.config, &html_config)?;
+        let data = make_data(&ctx.root, &book, &ctx.config, &html_config)?;
 
-        // Print version
-        let mut print_content = String::new();
+        // the previous build's pages are only there to be reused if the
+        // destination hasn't been removed since
+        let cache_file = cache::cache_file(&ctx.root, destination);
+        let mut cache = BuildCache::new(shared_hash(ctx, &theme, &data, &html_config));
+        let previous = BuildCache::load(&cache_file)
+            .filter(|prev| cache.is_compatible_with(prev) && destination.exists());
+
+        if previous.is_none() && destination.exists() {
+            debug!("Unable to reuse the previous build, rendering everything from scratch");
+            utils::fs::remove_dir_content(destination)
+                .chain_err(|| "Unable to clear output directory")?;
+        }
+
+        super::redirect::create_files(&html_config.redirect, book, destination)
+            .chain_err(|| "Unable to create the redirects")?;
 
         fs::create_dir_all(&destination)
             .chain_err(|| "Unexpected error when constructing destination path")?;
 
+        let mut chapters = Vec::new();
         let mut is_index = true;
+        let mut changed = previous.is_none();
         for item in book.iter() {
-            let ctx = RenderItemContext {
-                handlebars: &handlebars,
-                destination: destination.to_path_buf(),
-                data: data.clone(),
-                is_index,
-                html_config: html_config.clone(),
+            let unchanged = match *item {
+                // drafts don't have a page (or a place in the print page)
+                BookItem::Chapter(ref ch) if ch.is_draft() => continue,
+                BookItem::Chapter(ref ch) => {
+                    let metadata = serde_json::to_string(&ch.metadata)?;
+                    let content_hash = cache::hash(&(&ch.content, metadata));
+                    let same_content =
+                        cache.insert_chapter(&ch.path, content_hash, previous.as_ref());
+                    let outputs_exist = destination.join(ch.path.with_extension("html")).exists()
+                        && (!is_index || destination.join("index.html").exists());
+
+                    same_content && outputs_exist
+                }
+                BookItem::Separator | BookItem::PartTitle(_) => true,
             };
-            self.render_item(item, ctx, &mut print_content)?;
+            changed |= !unchanged;
+
+            if let BookItem::Chapter(ref ch) = *item {
+                chapters.push((ch, is_index, unchanged));
+            }
             is_index = false;
         }
 
-        // Print version
-        self.configure_print_version(&mut data, &print_content);
-        if let Some(ref title) = ctx.config.book.title {
-            data.insert("title".to_owned(), json!(title));
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(ctx.config.build.jobs.unwrap_or(0))
+            .build()
+            .chain_err(|| "Unable to start the rendering threads")?;
+
+        // Chapters are rendered in parallel, but the results are kept in book
+        // order so the print page (and which error gets reported) is always
+        // the same
+        let rendered: Vec<Result<String>> = pool.install(|| {
+            chapters
+                .par_iter()
+                .map(|&(ch, is_index, unchanged)| {
+                    let ctx = RenderItemContext {
+                        handlebars: &handlebars,
+                        destination: destination.to_path_buf(),
+                        data: data.clone(),
+                        is_index,
+                        unchanged,
+                        html_config: html_config.clone(),
+                        edition: ctx.config.rust.edition,
+                    };
+
+                    self.render_item(ch, ctx).chain_err(|| {
+                        format!("Unable to render \"{}\" ({})", ch.name, ch.path.display())
+                    })
+                }).collect()
+        });
+
+        let mut print_chapters = Vec::new();
+        for (&(ch, _, _), content) in chapters.iter().zip(rendered) {
+            print_chapters.push((ch, content?));
         }
 
-        // Render the handlebars template with the data
-        debug!("Render template");
-        let rendered = handlebars.render("index", &data)?;
-
-        let rendered = self.post_process(rendered, &html_config.playpen);
+        self.render_404(ctx, &html_config, &src_dir, &handlebars, data.clone())
+            .chain_err(|| "Unable to render the 404 page")?;
 
-        utils::fs::write_file(&destination, "print.html", rendered.as_bytes())?;
-        debug!("Creating print.html ✓");
+        // Print version
+        if changed && html_config.print.enable {
+            let groups = super::print::group_chapters(book, print_chapters, &html_config.print);
+            let pages = super::print::render_print_pages(&ctx.config.book, &groups);
+            let hbs = &handlebars;
+
+            if pages.len() > 1 {
+                // the contents page isn't printed itself
+                let mut data = data.clone();
+                data.insert("is_print".to_owned(), json!(false));
+                let contents = super::print::render_contents_page(&ctx.config.book, &groups);
+                self.render_print_page(ctx, hbs, &html_config, data, "print.html", &contents)?;
+            }
+            for (index, content) in pages.iter().enumerate() {
+                let file = super::print::print_file(index, pages.len());
+                self.render_print_page(ctx, hbs, &html_config, data.clone(), &file, content)?;
+            }
+        } else if !changed {
+            debug!("Nothing has changed since the last build");
+        }
 
-        debug!("Copy static files");
-        self.copy_static_files(&destination, &theme, &html_config)
-            .chain_err(|| "Unable to copy across static files")?;
-        self.copy_additional_css_and_js(&html_config, &ctx.root, &destination)
-            .chain_err(|| "Unable to copy across additional CSS and JS")?;
+        if previous.is_none() {
+            debug!("Copy static files");
+            self.copy_static_files(destination, &theme, &html_config)
+                .chain_err(|| "Unable to copy across static files")?;
+            self.copy_additional_css_and_js(&html_config, &ctx.root, destination)
+                .chain_err(|| "Unable to copy across additional CSS and JS")?;
+        }
 
         // Render search index
         #[cfg(feature = "search")]
         {
-            let search = html_config.search.unwrap_or_default();
-            if search.enable {
-                super::search::create_files(&search, &destination, &book)?;
+            let search = html_config.search.clone().unwrap_or_default();
+            if changed && search.enable {
+                let markdown = &html_config.markdown;
+                super::search::create_files(&search, markdown, destination, book)?;
+            }
+        }
+
+        if let Some(ref site_url) = html_config.site_url {
+            super::sitemap::create_files(
+                book,
+                &src_dir,
+                destination,
+                site_url,
+                html_config.robots_txt,
+            ).chain_err(|| "Unable to create the sitemap")?;
+        }
+
+        if let Some(ref feed) = html_config.feed {
+            match html_config.site_url {
+                Some(ref site_url) => {
+                    super::feed::create_files(
+                        feed,
+                        book,
+                        &ctx.config,
+                        &html_config,
+                        &src_dir,
+                        destination,
+                        site_url,
+                    ).chain_err(|| "Unable to create the feed")?;
+                }
+                None => warn!("Not creating a feed because `output.html.site-url` isn't set"),
             }
         }
 
         // Copy all remaining files
-        utils::fs::copy_files_except_ext(&src_dir, &destination, true, &["md"])?;
+        copy_changed_files(&src_dir, destination, &mut cache)?;
+
+        if let Some(ref previous) = previous {
+            for stale in cache.stale_outputs(previous) {
+                debug!("Removing {}, which is no longer part of the book", stale.display());
+                let path = destination.join(stale);
+                if let Err(e) = fs::remove_file(&path) {
+                    if path.exists() {
+                        warn!("Unable to remove {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
 
-        Ok(())
+        cache.save(&cache_file)
+    }
+
+    fn supports_incremental_builds(&self) -> bool {
+        true
+    }
+}
+
+/// Copy every file in the source directory other than the chapters into the
+/// destination, skipping the ones which haven't changed since they were last
+/// copied, and record them in the cache.
+fn copy_changed_files(src_dir: &Path, destination: &Path, cache: &mut BuildCache) -> Result<()> {
+    if src_dir == destination {
+        return Ok(());
+    }
+
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(src_dir.join(&dir))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let relative = dir.join(entry.file_name());
+
+            if metadata.is_dir() {
+                if entry.path() != destination {
+                    dirs.push(relative);
+                }
+                continue;
+            }
+            if relative.extension().and_then(|ext| ext.to_str()) == Some("md") {
+                continue;
+            }
+
+            cache.insert_file(&relative);
+            let dest = destination.join(&relative);
+            if let (Ok(source), Ok(existing)) = (
+                metadata.modified(),
+                fs::metadata(&dest).and_then(|m| m.modified()),
+            ) {
+                if existing >= source {
+                    debug!("{} is up to date", dest.display());
+                    continue;
+                }
+            }
+
+            debug!("Copying {} to {}", entry.path().display(), dest.display());
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(entry.path(), &dest)?;
+        }
     }
+
+    Ok(())
+}
+
+/// Hash everything which ends up in every page (the configuration, theme,
+/// table of contents, etc) so we can tell whether the previous build's pages
+/// are still usable.
+fn shared_hash(
+    ctx: &RenderContext,
+    theme: &Theme,
+    data: &serde_json::Map<String, serde_json::Value>,
+    html_config: &HtmlConfig,
+) -> u64 {
+    let additional_files: Vec<Vec<u8>> = html_config
+        .additional_css
+        .iter()
+        .chain(html_config.additional_js.iter())
+        .map(|file| fs::read(ctx.root.join(file)).unwrap_or_default())
+        .collect();
+    let config = serde_json::to_string(&ctx.config).unwrap_or_default();
+    let data = serde_json::to_string(data).unwrap_or_default();
+
+    cache::hash(&(theme, config, data, additional_files))
 }
 
 fn make_data(
@@ -378,7 +692,14 @@ fn make_data(
     let html = config.html_config().unwrap_or_default();
 
     let mut data = serde_json::Map::new();
-    data.insert("language".to_owned(), json!("en"));
+    let language = match config.book.language {
+        Some(ref language) => language.clone(),
+        None => config.default_language()?,
+    };
+    if config.book.multilingual {
+        data.insert("languages".to_owned(), json!(languages(config, &language)?));
+    }
+    data.insert("language".to_owned(), json!(language));
     data.insert(
         "book_title".to_owned(),
         json!(config.book.title.clone().unwrap_or_default()),
@@ -388,6 +709,29 @@ fn make_data(
         json!(config.book.description.clone().unwrap_or_default()),
     );
     data.insert("favicon".to_owned(), json!("favicon.png"));
+    data.insert("print_enable".to_owned(), json!(html_config.print.enable));
+    data.insert(
+        "highlight_js".to_owned(),
+        json!(!html_config.build_time_highlighting),
+    );
+    if let Some(ref site_url) = html_config.site_url {
+        data.insert("site_url".to_owned(), json!(site_url));
+        if html_config.feed.is_some() {
+            let feed_url = absolute_url(site_url, "feed.xml");
+            data.insert("feed_url".to_owned(), json!(feed_url));
+        }
+    }
+    if let Some(ref image) = html_config.social_image {
+        match social_image_url(image, &html_config.site_url) {
+            Some(url) => {
+                data.insert("social_image".to_owned(), json!(url));
+            }
+            None => warn!(
+                "Ignoring `output.html.social-image` because it isn't an absolute URL and \
+                 `output.html.site-url` isn't set"
+            ),
+        }
+    }
     if let Some(ref livereload) = html_config.livereload_url {
         data.insert("livereload".to_owned(), json!(livereload));
     }
@@ -488,6 +832,9 @@ fn make_data(
             BookItem::Separator => {
                 chapter.insert("spacer".to_owned(), json!("_spacer_"));
             }
+            BookItem::PartTitle(ref title) => {
+                chapter.insert("part".to_owned(), json!(title));
+            }
         }
 
         chapters.push(chapter);
@@ -499,44 +846,90 @@ fn make_data(
     Ok(data)
 }
 
+/// Generate the entries for the language switcher of a multilingual book.
+///
+/// The default language is rendered into the root of the output directory and
+/// each translation into a sub-directory named after its language code.
+fn languages(config: &Config, current: &str) -> Result<Vec<serde_json::Value>> {
+    let default = config.default_language()?;
+    let mut languages = config.languages()?;
+    languages.entry(default.clone()).or_default();
+
+    let to_default = if current == default { "" } else { "../" };
+
+    let languages = languages
+        .into_iter()
+        .map(|(code, lang)| {
+            let url = if code == default {
+                format!("{}index.html", to_default)
+            } else {
+                format!("{}{}/index.html", to_default, code)
+            };
+
+            json!({
+                "name": lang.name.unwrap_or_else(|| code.clone()),
+                "selected": code == current,
+                "code": code,
+                "url": url,
+            })
+        }).collect();
+
+    Ok(languages)
+}
+
 /// Goes through the rendered HTML, making sure all header tags are wrapped in
 /// an anchor so people can link to sections directly.
-fn build_header_links(html: &str) -> String {
-    let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
+pub(crate) fn build_header_links(html: &str) -> String {
     let mut id_counter = HashMap::new();
 
-    regex
+    HEADER
         .replace_all(html, |caps: &Captures| {
             let level = caps[1]
                 .parse()
                 .expect("Regex should ensure we only ever get numbers here");
+            let id = unique_id(&header_id(caps), &mut id_counter);
+            let classes = caps.get(3).map_or("", |classes| classes.as_str());
 
-            wrap_header_with_link(level, &caps[2], &mut id_counter)
+            header_link(level, &id, classes, &caps[4])
         }).into_owned()
 }
 
-/// Wraps a single header tag with a link, making sure each tag gets its own
-/// unique ID by appending an auto-incremented number (if necessary).
-fn wrap_header_with_link(
-    level: usize,
-    content: &str,
-    id_counter: &mut HashMap<String, usize>,
-) -> String {
-    let raw_id = utils::id_from_content(content);
+/// The ID a header was given in the markdown, or one made from its content.
+/// `caps` are the header's captures from `HEADER`.
+pub(crate) fn header_id(caps: &Captures) -> String {
+    match caps.get(2) {
+        Some(id) => id.as_str().to_string(),
+        None => utils::id_from_content(&caps[4]),
+    }
+}
 
-    let id_count = id_counter.entry(raw_id.clone()).or_insert(0);
+/// Make a header's ID unique by appending an auto-incremented number (if
+/// necessary).
+pub(crate) fn unique_id(raw_id: &str, id_counter: &mut HashMap<String, usize>) -> String {
+    let id_count = id_counter.entry(raw_id.to_string()).or_insert(0);
 
     let id = match *id_count {
-        0 => raw_id,
+        0 => raw_id.to_string(),
         other => format!("{}-{}", raw_id, other),
     };
 
     *id_count += 1;
+    id
+}
+
+/// Wrap a header with a link to itself.
+pub(crate) fn header_link(level: usize, id: &str, classes: &str, content: &str) -> String {
+    let class = if classes.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{}\"", classes)
+    };
 
     format!(
-        r##"<a class="header" href="#{id}" id="{id}"><h{level}>{text}</h{level}></a>"##,
+        r##"<a class="header" href="#{id}" id="{id}"><h{level}{class}>{text}</h{level}></a>"##,
         level = level,
         id = id,
+        class = class,
         text = content
     )
 }
@@ -566,7 +959,7 @@ fn fix_code_blocks(html: &str) -> String {
         }).into_owned()
 }
 
-fn add_playpen_pre(html: &str, playpen_config: &Playpen) -> String {
+fn add_playpen_pre(html: &str, playpen_config: &Playpen, edition: Option<RustEdition>) -> String {
     let regex = Regex::new(r##"((?s)<code[^>]?class="([^"]+)".*?>(.*?)</code>)"##).unwrap();
     regex
         .replace_all(html, |caps: &Captures| {
@@ -579,10 +972,21 @@ fn add_playpen_pre(html: &str, playpen_config: &Playpen) -> String {
                 && !classes.contains("noplaypen"))
                 || classes.contains("mdbook-runnable")
             {
+                // tell the playpen which edition to use, unless the code
+                // block already says
+                let (text, classes) = match edition {
+                    Some(edition) if !classes.contains("edition") => {
+                        let with_edition = format!("{} edition{}", classes, edition);
+                        (text.replacen(classes, &with_edition, 1), with_edition)
+                    }
+                    _ => (text.to_string(), classes.to_string()),
+                };
+
                 // wrap the contents in an external pre block
+                let plain_text = strip_tags(&text);
                 if playpen_config.editable && classes.contains("editable")
-                    || text.contains("fn main")
-                    || text.contains("quick_main!")
+                    || plain_text.contains("fn main")
+                    || plain_text.contains("quick_main!")
                 {
                     format!("<pre class=\"playpen\">{}</pre>", text)
                 } else {
@@ -608,7 +1012,8 @@ fn partition_source(s: &str) -> (String, String) {
     let mut after = String::new();
 
     for line in s.lines() {
-        let trimline = line.trim();
+        let plain_line = strip_tags(line);
+        let trimline = plain_line.trim();
         let header = trimline.chars().all(|c| c.is_whitespace()) || trimline.starts_with("#![");
         if !header || after_header {
             after_header = true;
@@ -623,12 +1028,91 @@ fn partition_source(s: &str) -> (String, String) {
     (before, after)
 }
 
+lazy_static! {
+    /// A header in the rendered HTML, with the ID and classes it was given in
+    /// the markdown, if any.
+    pub(crate) static ref HEADER: Regex =
+        Regex::new(r#"<h(\d)(?: id="([^"]*)")?(?: class="([^"]*)")?>(.*?)</h\d>"#).unwrap();
+}
+
+/// Remove the tags from some HTML, e.g. the `<span>`s around highlighted code.
+fn strip_tags<'a>(html: &'a str) -> Cow<'a, str> {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    }
+    TAG.replace_all(html, "")
+}
+
+/// The absolute URL of a page, given the URL the book is published at.
+pub(crate) fn absolute_url(site_url: &str, path: &str) -> String {
+    let path = path.replace("\\", "/");
+    format!("{}/{}", site_url.trim_end_matches('/'), path.trim_start_matches('/'))
+}
+
+/// Social media previews need an absolute URL for their image, so a relative
+/// one is only usable when the book's URL is known.
+fn social_image_url(image: &str, site_url: &Option<String>) -> Option<String> {
+    if image.starts_with("http://") || image.starts_with("https://") {
+        Some(image.to_string())
+    } else {
+        site_url.as_ref().map(|site_url| absolute_url(site_url, image))
+    }
+}
+
+/// Describe a chapter for search engines and social media previews, using
+/// (the start of) its first paragraph.
+fn chapter_description(content: &str) -> Option<String> {
+    const MAX_LENGTH: usize = 160;
+
+    let mut text = String::new();
+    let mut in_paragraph = false;
+    let mut in_image = false;
+
+    for event in Parser::new(content) {
+        match event {
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(Tag::Paragraph) if text.trim().is_empty() => in_paragraph = false,
+            Event::End(Tag::Paragraph) => break,
+            Event::Start(Tag::Image(..)) => in_image = true,
+            Event::End(Tag::Image(..)) => in_image = false,
+            Event::Text(ref t) if in_paragraph && !in_image => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak if in_paragraph => text.push(' '),
+            _ => {}
+        }
+    }
+
+    let text = utils::collapse_whitespace(text.trim());
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= MAX_LENGTH {
+        return Some(text.into_owned());
+    }
+
+    // cut the description short at the end of a word
+    let mut description = String::new();
+    for word in text.split(' ') {
+        if description.chars().count() + word.chars().count() + 1 > MAX_LENGTH {
+            break;
+        }
+        description.push_str(word);
+        description.push(' ');
+    }
+    description.pop();
+    description.push('…');
+
+    Some(description)
+}
+
 struct RenderItemContext<'a> {
     handlebars: &'a Handlebars,
     destination: PathBuf,
     data: serde_json::Map<String, serde_json::Value>,
     is_index: bool,
+    /// The page can be left as-is from the previous build.
+    unchanged: bool,
     html_config: HtmlConfig,
+    edition: Option<RustEdition>,
 }
 
 #[cfg(test)]
@@ -662,6 +1146,10 @@ mod tests {
                 "<h1>Foo</h1><h3>Foo</h3>",
                 r##"<a class="header" href="#foo" id="foo"><h1>Foo</h1></a><a class="header" href="#foo-1" id="foo-1"><h3>Foo</h3></a>"##,
             ),
+            (
+                r#"<h2 id="custom" class="big">Foo</h2><h2 class="wide">Foo</h2>"#,
+                r##"<a class="header" href="#custom" id="custom"><h2 class="big">Foo</h2></a><a class="header" href="#foo" id="foo"><h2 class="wide">Foo</h2></a>"##,
+            ),
         ];
 
         for (src, should_be) in inputs {
@@ -669,4 +1157,65 @@ mod tests {
             assert_eq!(got, should_be);
         }
     }
+
+    #[test]
+    fn add_playpen_uses_the_configured_edition() {
+        let inputs = vec![
+            (
+                "<code class=\"language-rust\">let x = 1;</code>",
+                Some(RustEdition::E2018),
+                "<pre class=\"playpen\"><code class=\"language-rust edition2018\">\n\
+                 # #![allow(unused_variables)]\n#fn main() {\nlet x = 1;\n#}</code></pre>",
+            ),
+            (
+                "<code class=\"language-rust edition2015\">fn main() {}</code>",
+                Some(RustEdition::E2018),
+                "<pre class=\"playpen\"><code class=\"language-rust edition2015\">\
+                 fn main() {}</code></pre>",
+            ),
+            (
+                "<code class=\"language-rust\">fn main() {}</code>",
+                None,
+                "<pre class=\"playpen\"><code class=\"language-rust\">fn main() {}</code></pre>",
+            ),
+        ];
+
+        for (src, edition, should_be) in inputs {
+            let got = add_playpen_pre(src, &Playpen::default(), edition);
+            assert_eq!(got, should_be);
+        }
+    }
+
+    #[test]
+    fn describe_a_chapter_with_its_first_paragraph() {
+        let long = "word ".repeat(40);
+        let inputs = vec![
+            ("# Title\n\nThe *first*\nparagraph.\n\nThe second.", Some("The first paragraph.")),
+            ("# Title\n\n![An image](img.png)\n\nUse `mdbook`.", Some("Use mdbook.")),
+            ("# Just a title\n\n```\ncode\n```\n", None),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(chapter_description(src), should_be.map(String::from));
+        }
+
+        let got = chapter_description(&long).unwrap();
+        assert_eq!(got.chars().count(), 160);
+        assert!(got.ends_with("word…"));
+    }
+
+    #[test]
+    fn social_images_need_an_absolute_url() {
+        let site_url = Some(String::from("https://example.com/book/"));
+
+        assert_eq!(
+            social_image_url("img/cover.png", &site_url),
+            Some(String::from("https://example.com/book/img/cover.png"))
+        );
+        assert_eq!(
+            social_image_url("https://cdn.example.com/cover.png", &None),
+            Some(String::from("https://cdn.example.com/cover.png"))
+        );
+        assert_eq!(social_image_url("img/cover.png", &None), None);
+    }
 }
@@ -34,6 +34,13 @@ impl HelperDef for RenderToc {
             .as_str()
             .ok_or_else(|| RenderError::new("Type error for `path`, string expected"))?
             .replace("\"", "");
+        // pages which can be served from anywhere (e.g. the 404 page) use an
+        // absolute `path_to_root`
+        let path_to_root = rc
+            .evaluate_absolute(ctx, "path_to_root", false)?
+            .as_str()
+            .map(String::from)
+            .unwrap_or_else(|| utils::fs::path_to_root(&current));
 
         out.write("<ol class=\"chapter\">")?;
 
@@ -46,33 +53,52 @@ impl HelperDef for RenderToc {
                 continue;
             }
 
+            // Part title
+            if let Some(title) = item.get("part") {
+                while current_level > 1 {
+                    out.write("</ol>")?;
+                    out.write("</li>")?;
+                    current_level -= 1;
+                }
+
+                out.write("<li class=\"part-title\">")?;
+                out.write(&render_name(title))?;
+                out.write("</li>")?;
+                continue;
+            }
+
             let level = if let Some(s) = item.get("section") {
                 s.matches('.').count()
             } else {
                 1
             };
 
-            if level > current_level {
-                while level > current_level {
-                    out.write("<li>")?;
-                    out.write("<ol class=\"section\">")?;
-                    current_level += 1;
-                }
-                out.write("<li>")?;
-            } else if level < current_level {
-                while level < current_level {
-                    out.write("</ol>")?;
-                    out.write("</li>")?;
-                    current_level -= 1;
-                }
+            let affix = level == current_level && !item.contains_key("section");
+            // drafts don't have a page, so they're greyed out
+            let draft = match item.get("path") {
+                Some(path) => path.is_empty(),
+                None => true,
+            };
+
+            while level > current_level {
                 out.write("<li>")?;
-            } else {
-                out.write("<li")?;
-                if item.get("section").is_none() {
-                    out.write(" class=\"affix\"")?;
-                }
-                out.write(">")?;
+                out.write("<ol class=\"section\">")?;
+                current_level += 1;
+            }
+            while level < current_level {
+                out.write("</ol>")?;
+                out.write("</li>")?;
+                current_level -= 1;
+            }
+
+            out.write("<li")?;
+            match (affix, draft) {
+                (true, true) => out.write(" class=\"affix draft\"")?,
+                (true, false) => out.write(" class=\"affix\"")?,
+                (false, true) => out.write(" class=\"draft\"")?,
+                (false, false) => {}
             }
+            out.write(">")?;
 
             // Link
             let path_exists = if let Some(path) = item.get("path") {
@@ -87,7 +113,7 @@ impl HelperDef for RenderToc {
                         .replace("\\", "/");
 
                     // Add link
-                    out.write(&utils::fs::path_to_root(&current))?;
+                    out.write(&path_to_root)?;
                     out.write(&tmp)?;
                     out.write("\"")?;
 
@@ -114,23 +140,7 @@ impl HelperDef for RenderToc {
             }
 
             if let Some(name) = item.get("name") {
-                // Render only inline code blocks
-
-                // filter all events that are not inline code blocks
-                let parser = Parser::new(name).filter(|event| match *event {
-                    Event::Start(Tag::Code)
-                    | Event::End(Tag::Code)
-                    | Event::InlineHtml(_)
-                    | Event::Text(_) => true,
-                    _ => false,
-                });
-
-                // render markdown to html
-                let mut markdown_parsed_name = String::with_capacity(name.len() * 3 / 2);
-                html::push_html(&mut markdown_parsed_name, parser);
-
-                // write to the handlebars template
-                out.write(&markdown_parsed_name)?;
+                out.write(&render_name(name))?;
             }
 
             if path_exists {
@@ -149,3 +159,21 @@ impl HelperDef for RenderToc {
         Ok(())
     }
 }
+
+/// Render a chapter or part name, keeping only its inline code blocks.
+fn render_name(name: &str) -> String {
+    // filter all events that are not inline code blocks
+    let parser = Parser::new(name).filter(|event| match *event {
+        Event::Start(Tag::Code)
+        | Event::End(Tag::Code)
+        | Event::InlineHtml(_)
+        | Event::Text(_) => true,
+        _ => false,
+    });
+
+    // render markdown to html
+    let mut markdown_parsed_name = String::with_capacity(name.len() * 3 / 2);
+    html::push_html(&mut markdown_parsed_name, parser);
+
+    markdown_parsed_name
+}
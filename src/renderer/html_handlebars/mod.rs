@@ -1,9 +1,17 @@
 #![allow(missing_docs)] // FIXME: Document this
 
 pub use self::hbs_renderer::HtmlHandlebars;
+#[cfg(feature = "epub")]
+pub(crate) use self::hbs_renderer::build_header_links;
+pub(crate) use self::hbs_renderer::{header_id, unique_id, HEADER};
 
+mod cache;
+mod feed;
 mod hbs_renderer;
 mod helpers;
+mod print;
+mod redirect;
+mod sitemap;
 
 #[cfg(feature = "search")]
 mod search;
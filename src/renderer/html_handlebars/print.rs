@@ -0,0 +1,443 @@
+//! Putting every chapter on one page (or a few), ready to be printed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use handlebars::html_escape;
+use regex::{Captures, Regex};
+
+use super::hbs_renderer::{header_id, header_link, unique_id, HEADER};
+use book::{Book, BookItem, Chapter};
+use config::{BookConfig, Print, PrintSplit};
+use utils;
+use utils::fs::resolve_relative_path;
+
+lazy_static! {
+    static ref LINK: Regex = Regex::new(r##"(href|src)="([^"#]*)(#[^"]*)?""##).unwrap();
+}
+
+/// Chapters which are printed together, along with their rendered content.
+pub struct PrintGroup<'a> {
+    /// The part of the book the chapters are in, if it has parts.
+    pub part: Option<String>,
+    pub chapters: Vec<(&'a Chapter, String)>,
+}
+
+/// Where each chapter and header ended up on the print pages.
+struct Anchors {
+    /// The ID of each chapter page (e.g. `first/nested.html`).
+    chapters: HashMap<PathBuf, String>,
+    /// The print page each chapter page is on.
+    files: HashMap<PathBuf, String>,
+    /// The ID each header has on its chapter's page, and on the print page.
+    headers: HashMap<(PathBuf, String), String>,
+}
+
+/// The name of one of `count` print pages.
+pub fn print_file(index: usize, count: usize) -> String {
+    if count == 1 {
+        String::from("print.html")
+    } else {
+        format!("print-{}.html", index + 1)
+    }
+}
+
+/// Split the book's (rendered) chapters between print pages.
+pub fn group_chapters<'a>(
+    book: &Book,
+    chapters: Vec<(&'a Chapter, String)>,
+    print: &Print,
+) -> Vec<PrintGroup<'a>> {
+    let mut chapters = chapters.into_iter();
+    let mut groups = vec![PrintGroup {
+        part: None,
+        chapters: Vec::new(),
+    }];
+    let mut part = None;
+    let mut top_level = 0;
+
+    for item in book.iter() {
+        match *item {
+            BookItem::PartTitle(ref title) => {
+                part = Some(title.clone());
+                if print.split == PrintSplit::Part {
+                    groups.push(PrintGroup {
+                        part: part.clone(),
+                        chapters: Vec::new(),
+                    });
+                }
+            }
+            BookItem::Chapter(ref ch) if !ch.is_draft() => {
+                if print.split == PrintSplit::Size && ch.parent_names.is_empty() {
+                    if top_level == print.page_size.max(1) {
+                        groups.push(PrintGroup {
+                            part: part.clone(),
+                            chapters: Vec::new(),
+                        });
+                        top_level = 0;
+                    }
+                    top_level += 1;
+                }
+
+                let chapter = chapters.next().expect("Every rendered chapter is in the book");
+                groups.last_mut().unwrap().chapters.push(chapter);
+            }
+            _ => {}
+        }
+    }
+
+    groups.retain(|group| !group.chapters.is_empty());
+    groups
+}
+
+/// The content of each print page. A book on a single page starts with a
+/// title page.
+///
+/// Headers get IDs which are unique across every chapter, links between
+/// chapters are turned into links to the print pages, and each top-level
+/// chapter starts on a new sheet of paper.
+pub fn render_print_pages(config: &BookConfig, groups: &[PrintGroup]) -> Vec<String> {
+    let mut anchors = Anchors {
+        chapters: HashMap::new(),
+        files: HashMap::new(),
+        headers: HashMap::new(),
+    };
+    let mut id_counter = HashMap::new();
+
+    for (index, group) in groups.iter().enumerate() {
+        for &(ch, _) in &group.chapters {
+            let name = ch.path.with_extension("").to_string_lossy().replace('\\', "/");
+            let id = format!("chapter-{}", utils::normalize_id(&name.replace('/', "-")));
+            id_counter.insert(id.clone(), 1);
+
+            let page = ch.path.with_extension("html");
+            anchors.chapters.insert(page.clone(), id);
+            anchors.files.insert(page, print_file(index, groups.len()));
+        }
+    }
+
+    // Work out every header's ID first, so links to later chapters can be
+    // rewritten
+    let mut header_ids = Vec::new();
+    for &(ch, ref content) in groups.iter().flat_map(|group| &group.chapters) {
+        let page = ch.path.with_extension("html");
+        let mut page_counter = HashMap::new();
+        let mut ids = Vec::new();
+
+        for caps in HEADER.captures_iter(content) {
+            let raw_id = header_id(&caps);
+            let page_id = unique_id(&raw_id, &mut page_counter);
+            let id = unique_id(&raw_id, &mut id_counter);
+            anchors.headers.insert((page.clone(), page_id), id.clone());
+            ids.push(id);
+        }
+        header_ids.push(ids);
+    }
+    let mut header_ids = header_ids.into_iter();
+
+    let mut pages = Vec::new();
+    for (index, group) in groups.iter().enumerate() {
+        let file = print_file(index, groups.len());
+        let mut print_content = if groups.len() == 1 {
+            render_title_page(config)
+        } else {
+            String::new()
+        };
+
+        for &(ch, ref content) in &group.chapters {
+            let page = ch.path.with_extension("html");
+            let content = LINK.replace_all(content, |caps: &Captures| {
+                let fragment = caps.get(3).map(|m| &m.as_str()[1..]);
+                let link = fix_link(&caps[2], fragment, &page, &file, &anchors);
+                format!("{}=\"{}\"", &caps[1], link)
+            });
+
+            let mut ids = header_ids.next().expect("Every chapter's headers have IDs").into_iter();
+            let content = HEADER.replace_all(&content, |caps: &Captures| {
+                let level = caps[1]
+                    .parse()
+                    .expect("Regex should ensure we only ever get numbers here");
+                let id = ids.next().expect("Every header was given an ID");
+                let classes = caps.get(3).map_or("", |classes| classes.as_str());
+                header_link(level, &id, classes, &caps[4])
+            });
+
+            let class = if ch.parent_names.is_empty() {
+                "print-chapter print-page-break"
+            } else {
+                "print-chapter"
+            };
+            print_content.push_str(&format!(
+                "<div class=\"{}\" id=\"{}\">\n{}</div>\n",
+                class, anchors.chapters[&page], content
+            ));
+        }
+
+        pages.push(print_content);
+    }
+
+    if pages.is_empty() {
+        pages.push(render_title_page(config));
+    }
+    pages
+}
+
+/// When the book is split between several print pages, `print.html` has the
+/// title page and links to each of them.
+pub fn render_contents_page(config: &BookConfig, groups: &[PrintGroup]) -> String {
+    let mut contents = render_title_page(config);
+    contents.push_str("<ul class=\"print-pages\">\n");
+
+    for (index, group) in groups.iter().enumerate() {
+        let first = &group.chapters[0].0.name;
+        let last = &group.chapters[group.chapters.len() - 1].0.name;
+        let name = match group.part {
+            Some(ref part) => format!("{}: {} – {}", part, first, last),
+            None if first == last => first.clone(),
+            None => format!("{} – {}", first, last),
+        };
+
+        contents.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            print_file(index, groups.len()),
+            html_escape(&name)
+        ));
+    }
+
+    contents.push_str("</ul>\n");
+    contents
+}
+
+fn render_title_page(config: &BookConfig) -> String {
+    let mut title_page = String::new();
+
+    if let Some(ref title) = config.title {
+        title_page.push_str(&format!(
+            "<h1 class=\"print-title\">{}</h1>\n",
+            html_escape(title)
+        ));
+    }
+    if !config.authors.is_empty() {
+        title_page.push_str(&format!(
+            "<p class=\"print-authors\">{}</p>\n",
+            html_escape(&config.authors.join(", "))
+        ));
+    }
+    if let Some(ref description) = config.description {
+        title_page.push_str(&format!(
+            "<p class=\"print-description\">{}</p>\n",
+            html_escape(description)
+        ));
+    }
+
+    if title_page.is_empty() {
+        title_page
+    } else {
+        format!("<div class=\"print-title-page\">\n{}</div>\n", title_page)
+    }
+}
+
+/// Point a link from one of the chapters somewhere on the print pages (for
+/// chapters) or relative to them (for everything else).
+fn fix_link(
+    path: &str,
+    fragment: Option<&str>,
+    page: &Path,
+    file: &str,
+    anchors: &Anchors,
+) -> String {
+    let original = || match fragment {
+        Some(fragment) => format!("{}#{}", path, fragment),
+        None => path.to_string(),
+    };
+
+    // URLs (`https://...`, `mailto:...`) and absolute paths don't change
+    if path.contains(':') || path.starts_with('/') {
+        return original();
+    }
+
+    let target = if path.is_empty() {
+        page.to_path_buf()
+    } else {
+        resolve_relative_path(page.parent().unwrap_or_else(|| Path::new("")).join(path))
+    };
+
+    if let Some(chapter_id) = anchors.chapters.get(&target) {
+        // fragments which aren't headers (e.g. an HTML element's `id`) are
+        // left alone
+        let id = match fragment {
+            Some(fragment) => anchors
+                .headers
+                .get(&(target.clone(), fragment.to_string()))
+                .map(String::as_str)
+                .unwrap_or(fragment),
+            None => chapter_id,
+        };
+
+        return match anchors.files.get(&target) {
+            Some(target_file) if target_file != file => format!("{}#{}", target_file, id),
+            _ => format!("#{}", id),
+        };
+    }
+
+    if path.is_empty() {
+        return original();
+    }
+
+    // the print pages are at the root of the book
+    let target = target.to_string_lossy().replace('\\', "/");
+    match fragment {
+        Some(fragment) => format!("{}#{}", target, fragment),
+        None => target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(path: &str, parent_names: Vec<String>) -> Chapter {
+        Chapter::new("Chapter", String::new(), path, parent_names)
+    }
+
+    #[test]
+    fn start_with_a_title_page() {
+        let config = BookConfig {
+            title: Some(String::from("Dogs & Cats")),
+            authors: vec![String::from("Someone"), String::from("Someone else")],
+            description: None,
+            ..Default::default()
+        };
+
+        let got = render_print_pages(&config, &[]);
+
+        assert_eq!(
+            got,
+            vec![
+                "<div class=\"print-title-page\">\n\
+                 <h1 class=\"print-title\">Dogs &amp; Cats</h1>\n\
+                 <p class=\"print-authors\">Someone, Someone else</p>\n\
+                 </div>\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn header_ids_are_unique_across_chapters() {
+        let intro = chapter("intro.md", Vec::new());
+        let nested = chapter("guide/usage.md", vec![String::from("Guide")]);
+        let groups = vec![PrintGroup {
+            part: None,
+            chapters: vec![
+                (&intro, String::from("<h1>Usage</h1>\n<p><a href=\"#usage\">here</a></p>\n")),
+                (
+                    &nested,
+                    String::from(
+                        "<h2>Usage</h2>\n<h2>Usage</h2>\n<p><a href=\"#usage-1\">2nd</a> \
+                         <a href=\"../intro.html#usage\">1st</a></p>\n",
+                    ),
+                ),
+            ],
+        }];
+
+        let got = render_print_pages(&BookConfig::default(), &groups);
+
+        assert_eq!(got.len(), 1);
+        assert!(got[0].contains(
+            "<div class=\"print-chapter print-page-break\" id=\"chapter-intro\">\n\
+             <a class=\"header\" href=\"#usage\" id=\"usage\"><h1>Usage</h1></a>\n\
+             <p><a href=\"#usage\">here</a></p>\n</div>"
+        ));
+        assert!(got[0].contains(
+            "<div class=\"print-chapter\" id=\"chapter-guide-usage\">\n\
+             <a class=\"header\" href=\"#usage-1\" id=\"usage-1\"><h2>Usage</h2></a>\n\
+             <a class=\"header\" href=\"#usage-2\" id=\"usage-2\"><h2>Usage</h2></a>\n\
+             <p><a href=\"#usage-2\">2nd</a> <a href=\"#usage\">1st</a></p>\n</div>"
+        ));
+    }
+
+    #[test]
+    fn split_the_book_between_print_pages() {
+        let mut book = Book::new();
+        book.push_item(chapter("intro.md", Vec::new()));
+        book.push_item(BookItem::PartTitle(String::from("Guide")));
+        book.push_item(chapter("one.md", Vec::new()));
+        book.push_item(Chapter::new_draft("Draft", Vec::new()));
+        book.push_item(chapter("two.md", Vec::new()));
+        book.push_item(BookItem::PartTitle(String::from("Reference")));
+        book.push_item(chapter("three.md", Vec::new()));
+        let chapters: Vec<_> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) if !ch.is_draft() => Some((ch, String::new())),
+                _ => None,
+            }).collect();
+
+        let inputs = vec![
+            (PrintSplit::None, 10, vec![4]),
+            (PrintSplit::Part, 10, vec![1, 2, 1]),
+            (PrintSplit::Size, 3, vec![3, 1]),
+        ];
+
+        for (split, page_size, should_be) in inputs {
+            let print = Print {
+                split,
+                page_size,
+                ..Default::default()
+            };
+            let groups = group_chapters(&book, chapters.clone(), &print);
+            let sizes: Vec<_> = groups.iter().map(|group| group.chapters.len()).collect();
+            assert_eq!(sizes, should_be, "{:?}", split);
+        }
+
+        let print = Print {
+            split: PrintSplit::Part,
+            ..Default::default()
+        };
+        let groups = group_chapters(&book, chapters.clone(), &print);
+        let contents = render_contents_page(&BookConfig::default(), &groups);
+        assert!(contents.contains("<li><a href=\"print-1.html\">Chapter</a></li>"));
+        assert!(contents.contains("<a href=\"print-2.html\">Guide: Chapter – Chapter</a>"));
+    }
+
+    #[test]
+    fn fix_links_for_the_print_page() {
+        let mut anchors = Anchors {
+            chapters: HashMap::new(),
+            files: HashMap::new(),
+            headers: HashMap::new(),
+        };
+        for &(page, id, file) in &[
+            ("intro.html", "chapter-intro", "print-1.html"),
+            ("guide/nested.html", "chapter-guide-nested", "print-2.html"),
+        ] {
+            anchors.chapters.insert(PathBuf::from(page), String::from(id));
+            anchors.files.insert(PathBuf::from(page), String::from(file));
+        }
+        anchors.headers.insert(
+            (PathBuf::from("intro.html"), String::from("intro")),
+            String::from("intro-1"),
+        );
+        let page = Path::new("guide/nested.html");
+
+        let inputs = vec![
+            ("../intro.html", None, "print-1.html#chapter-intro"),
+            ("../intro.html", Some("intro"), "print-1.html#intro-1"),
+            ("../intro.html", Some("custom-id"), "print-1.html#custom-id"),
+            ("./../guide/../intro.html", None, "print-1.html#chapter-intro"),
+            ("nested.html", None, "#chapter-guide-nested"),
+            ("images/cat.png", None, "guide/images/cat.png"),
+            ("../other.html", Some("top"), "other.html#top"),
+            ("", Some("unknown"), "#unknown"),
+            ("https://example.com/", Some("top"), "https://example.com/#top"),
+            ("/absolute.html", None, "/absolute.html"),
+        ];
+
+        for (path, fragment, should_be) in inputs {
+            assert_eq!(
+                fix_link(path, fragment, page, "print-2.html", &anchors),
+                should_be
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Stub pages which send readers from where a chapter used to be to where it
+//! is now.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use book::{Book, BookItem};
+use errors::*;
+use utils;
+
+/// Write a stub page for each redirect, failing if one would overwrite a
+/// chapter.
+pub fn create_files(
+    redirects: &BTreeMap<String, String>,
+    book: &Book,
+    destination: &Path,
+) -> Result<()> {
+    let mut pages: HashSet<PathBuf> = book
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft() => Some(ch.path.with_extension("html")),
+            _ => None,
+        }).collect();
+    pages.insert(PathBuf::from("index.html"));
+    pages.insert(PathBuf::from("print.html"));
+    pages.insert(PathBuf::from("404.html"));
+
+    for (from, to) in redirects {
+        let from = Path::new(from.trim_start_matches('/'));
+
+        if pages.contains(from) {
+            bail!(
+                "Unable to redirect \"{}\" to \"{}\" because it is one of the book's pages",
+                from.display(),
+                to
+            );
+        }
+
+        debug!("Redirecting \"{}\" to \"{}\"", from.display(), to);
+        let page = render_redirect(&target_url(from, to));
+        utils::fs::write_file(destination, from, page.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// A redirect's target can be a URL, a path relative to the old page, or a
+/// path starting with `/` which is relative to the root of the book.
+fn target_url(from: &Path, to: &str) -> String {
+    if to.starts_with('/') {
+        utils::fs::path_to_root(from) + to.trim_start_matches('/')
+    } else {
+        to.to_string()
+    }
+}
+
+fn render_redirect(url: &str) -> String {
+    let attribute = escape_attribute(url);
+    let js_string = serde_json::to_string(url).expect("Strings are always valid JSON");
+
+    format!(
+        r##"<!DOCTYPE html>
+<html>
+    <head>
+        <meta charset="UTF-8">
+        <title>Redirecting...</title>
+        <meta http-equiv="refresh" content="0; URL={attr}">
+        <link rel="canonical" href="{attr}">
+    </head>
+    <body>
+        <p>This page has moved to <a href="{attr}">{attr}</a>.</p>
+        <script>
+            // keep the fragment, so links to a section still work
+            var url = {js};
+            if (window.location.hash && url.indexOf("#") === -1) {{
+                url += window.location.hash;
+            }}
+            window.location.replace(url);
+        </script>
+    </body>
+</html>
+"##,
+        attr = attribute,
+        js = js_string
+    )
+}
+
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_targets_can_be_relative_to_the_book() {
+        let inputs = vec![
+            ("old.html", "new.html", "new.html"),
+            ("a/b/old.html", "/new/page.html#section", "../../new/page.html#section"),
+            ("old.html", "https://example.com/", "https://example.com/"),
+        ];
+
+        for (from, to, should_be) in inputs {
+            assert_eq!(target_url(Path::new(from), to), should_be);
+        }
+    }
+
+    #[test]
+    fn stub_pages_escape_the_url() {
+        let got = render_redirect("new.html?a=1&b=\"2\"");
+
+        assert!(got.contains(r#"content="0; URL=new.html?a=1&amp;b=&quot;2&quot;">"#));
+        assert!(got.contains(r#"var url = "new.html?a=1&b=\"2\"";"#));
+    }
+}
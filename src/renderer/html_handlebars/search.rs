@@ -10,18 +10,23 @@ use pulldown_cmark::*;
 use serde_json;
 
 use book::{Book, BookItem};
-use config::Search;
+use config::{Markdown, Search};
 use errors::*;
 use theme::searcher;
 use utils;
 
 /// Creates all files required for search.
-pub fn create_files(search_config: &Search, destination: &Path, book: &Book) -> Result<()> {
+pub fn create_files(
+    search_config: &Search,
+    markdown: &Markdown,
+    destination: &Path,
+    book: &Book,
+) -> Result<()> {
     let mut index = Index::new(&["title", "body", "breadcrumbs"]);
     let mut doc_urls = Vec::with_capacity(book.sections.len());
 
     for item in book.iter() {
-        render_item(&mut index, &search_config, &mut doc_urls, item)?;
+        render_item(&mut index, search_config, markdown, &mut doc_urls, item)?;
     }
 
     let index = write_to_json(index, &search_config, doc_urls)?;
@@ -71,11 +76,12 @@ fn add_doc(
 fn render_item(
     index: &mut Index,
     search_config: &Search,
+    markdown: &Markdown,
     doc_urls: &mut Vec<String>,
     item: &BookItem,
 ) -> Result<()> {
     let chapter = match *item {
-        BookItem::Chapter(ref ch) => ch,
+        BookItem::Chapter(ref ch) if !ch.is_draft() => ch,
         _ => return Ok(()),
     };
 
@@ -85,10 +91,7 @@ fn render_item(
         .chain_err(|| "Could not convert HTML path to str")?;
     let anchor_base = utils::fs::normalize_path(filepath);
 
-    let mut opts = Options::empty();
-    opts.insert(OPTION_ENABLE_TABLES);
-    opts.insert(OPTION_ENABLE_FOOTNOTES);
-    let p = Parser::new_ext(&chapter.content, opts);
+    let p = utils::parse_markdown(&chapter.content, markdown);
 
     let mut in_header = false;
     let max_section_depth = search_config.heading_split_level as i32;
@@ -121,7 +124,15 @@ fn render_item(
             }
             Event::End(Tag::Header(i)) if i <= max_section_depth => {
                 in_header = false;
-                section_id = Some(utils::id_from_content(&heading));
+                let mut id = None;
+                if markdown.heading_attributes {
+                    if let Some((title, attributes)) = utils::split_heading_attributes(&heading) {
+                        id = attributes.id;
+                        let title_len = title.len();
+                        heading.truncate(title_len);
+                    }
+                }
+                section_id = Some(id.unwrap_or_else(|| utils::id_from_content(&heading)));
                 breadcrumbs.push(heading.clone());
             }
             Event::Start(Tag::FootnoteDefinition(name)) => {
@@ -0,0 +1,127 @@
+//! Telling search engines which pages the book has.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use super::hbs_renderer::absolute_url;
+use book::{Book, BookItem};
+use errors::*;
+use utils;
+
+/// Write a `sitemap.xml` listing every rendered chapter (and optionally a
+/// `robots.txt` pointing at it) to the destination directory.
+pub fn create_files(
+    book: &Book,
+    src_dir: &Path,
+    destination: &Path,
+    site_url: &str,
+    robots_txt: bool,
+) -> Result<()> {
+    let sitemap = render_sitemap(book, src_dir, site_url);
+    utils::fs::write_file(destination, "sitemap.xml", sitemap.as_bytes())?;
+    debug!("Creating sitemap.xml ✓");
+
+    if robots_txt {
+        let robots = format!(
+            "User-agent: *\nAllow: /\nSitemap: {}\n",
+            absolute_url(site_url, "sitemap.xml")
+        );
+        utils::fs::write_file(destination, "robots.txt", robots.as_bytes())?;
+        debug!("Creating robots.txt ✓");
+    }
+
+    Ok(())
+}
+
+fn render_sitemap(book: &Book, src_dir: &Path, site_url: &str) -> String {
+    let mut sitemap = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+
+    for item in book.iter() {
+        let ch = match *item {
+            BookItem::Chapter(ref ch) if !ch.is_draft() => ch,
+            _ => continue,
+        };
+
+        let page = ch.path.with_extension("html");
+        let url = absolute_url(site_url, &page.to_string_lossy());
+
+        sitemap.push_str("  <url>\n");
+        sitemap.push_str(&format!("    <loc>{}</loc>\n", escape_xml(&url)));
+        if let Some(modified) = last_modified(&src_dir.join(&ch.path)) {
+            let modified = modified.format("%Y-%m-%dT%H:%M:%S+00:00");
+            sitemap.push_str(&format!("    <lastmod>{}</lastmod>\n", modified));
+        }
+        sitemap.push_str("  </url>\n");
+    }
+
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
+
+/// When the chapter's source file was last changed.
+pub(crate) fn last_modified(path: &Path) -> Option<DateTime<Utc>> {
+    let mut source = path.to_path_buf();
+
+    // the `index` preprocessor renames `README.md` chapters to `index.md`
+    if !source.exists() && source.file_name() == Some(OsStr::new("index.md")) {
+        source = readme_beside(path)?;
+    }
+
+    let modified = fs::metadata(&source).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::from(modified))
+}
+
+fn readme_beside(index_md: &Path) -> Option<PathBuf> {
+    let dir = index_md.parent()?;
+
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            let name = path.file_name().and_then(|name| name.to_str());
+            name.map(str::to_lowercase) == Some(String::from("readme.md"))
+        })
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+    use tempfile::Builder as TempFileBuilder;
+
+    #[test]
+    fn list_every_rendered_chapter() {
+        let temp = TempFileBuilder::new().prefix("sitemap").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "intro.md", b"# Intro").unwrap();
+        utils::fs::write_file(temp.path(), "guide/README.md", b"# Guide").unwrap();
+
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()));
+        book.push_item(Chapter::new_draft("Draft", Vec::new()));
+        book.push_item(Chapter::new("Guide", String::new(), "guide/index.md", Vec::new()));
+        book.push_item(Chapter::new("Missing", String::new(), "a&b.md", Vec::new()));
+
+        let got = render_sitemap(&book, temp.path(), "https://example.com/book/");
+
+        assert!(got.starts_with("<?xml"));
+        assert!(got.contains("book/intro.html</loc>\n    <lastmod>"));
+        assert!(got.contains("book/guide/index.html</loc>\n    <lastmod>"));
+        assert!(got.contains("<loc>https://example.com/book/a&amp;b.html</loc>\n  </url>"));
+        assert_eq!(got.matches("<url>").count(), 3);
+    }
+}
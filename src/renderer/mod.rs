@@ -12,7 +12,12 @@
 //! [RenderContext]: struct.RenderContext.html
 
 pub use self::html_handlebars::HtmlHandlebars;
+#[cfg(feature = "epub")]
+pub use self::epub::EpubRenderer;
+pub(crate) use self::html_handlebars::{header_id, unique_id, HEADER};
 
+#[cfg(feature = "epub")]
+mod epub;
 mod html_handlebars;
 
 use serde_json;
@@ -45,6 +50,14 @@ pub trait Renderer {
     /// Invoke the `Renderer`, passing in all the necessary information for
     /// describing a book.
     fn render(&self, ctx: &RenderContext) -> Result<()>;
+
+    /// Can the `Renderer` update the output of a previous build in place?
+    ///
+    /// If not (the default), the destination directory is emptied before
+    /// each build.
+    fn supports_incremental_builds(&self) -> bool {
+        false
+    }
 }
 
 /// The context provided to all renderers.
@@ -102,10 +102,17 @@ function playpen_text(playpen) {
 
         let text = playpen_text(code_block);
 
+        let classes = code_block.querySelector('code').classList;
+        let edition = "2015";
+        if (classes.contains("edition2018")) {
+            edition = "2018";
+        }
+
         var params = {
             version: "stable",
             optimize: "0",
-            code: text
+            code: text,
+            edition: edition
         };
 
         if (text.indexOf("#![feature") !== -1) {
@@ -127,25 +134,25 @@ function playpen_text(playpen) {
         .catch(error => result_block.innerText = "Playground Communication: " + error.message);
     }
 
-    // Syntax highlighting Configuration
-    hljs.configure({
-        tabReplace: '    ', // 4 spaces
-        languages: [],      // Languages used for auto-detection
-    });
-
     if (window.ace) {
         // language-rust class needs to be removed for editable
         // blocks or highlightjs will capture events
         Array
             .from(document.querySelectorAll('code.editable'))
             .forEach(function (block) { block.classList.remove('language-rust'); });
+    }
+
+    // highlight.js isn't loaded when the code was highlighted while
+    // building the book
+    if (window.hljs) {
+        // Syntax highlighting Configuration
+        hljs.configure({
+            tabReplace: '    ', // 4 spaces
+            languages: [],      // Languages used for auto-detection
+        });
 
         Array
-            .from(document.querySelectorAll('code:not(.editable)'))
-            .forEach(function (block) { hljs.highlightBlock(block); });
-    } else {
-        Array
-            .from(document.querySelectorAll('code'))
+            .from(document.querySelectorAll(window.ace ? 'code:not(.editable)' : 'code'))
             .forEach(function (block) { hljs.highlightBlock(block); });
     }
 
@@ -430,6 +437,47 @@ function playpen_text(playpen) {
     });
 })();
 
+(function languages() {
+    var languageToggleButton = document.getElementById('language-toggle');
+    var languagePopup = document.getElementById('language-list');
+
+    // Only multilingual books have a language switcher
+    if (!languageToggleButton) { return; }
+
+    function showLanguages() {
+        languagePopup.style.display = 'block';
+        languageToggleButton.setAttribute('aria-expanded', true);
+        languagePopup.querySelector('a').focus();
+    }
+
+    function hideLanguages() {
+        languagePopup.style.display = 'none';
+        languageToggleButton.setAttribute('aria-expanded', false);
+        languageToggleButton.focus();
+    }
+
+    languageToggleButton.addEventListener('click', function () {
+        if (languagePopup.style.display === 'block') {
+            hideLanguages();
+        } else {
+            showLanguages();
+        }
+    });
+
+    document.addEventListener('click', function(e) {
+        if (languagePopup.style.display === 'block' && !languageToggleButton.contains(e.target) && !languagePopup.contains(e.target)) {
+            hideLanguages();
+        }
+    });
+
+    document.addEventListener('keydown', function (e) {
+        if (e.key === 'Escape' && languagePopup.contains(e.target)) {
+            e.preventDefault();
+            hideLanguages();
+        }
+    });
+})();
+
 (function sidebar() {
     var html = document.querySelector("html");
     var sidebar = document.getElementById("sidebar");
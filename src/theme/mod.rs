@@ -43,7 +43,7 @@ pub static FONT_AWESOME_OTF: &'static [u8] = include_bytes!("FontAwesome/fonts/F
 ///
 /// You should only ever use the static variables directly if you want to
 /// override the user's theme with the defaults.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Hash)]
 pub struct Theme {
     pub index: Vec<u8>,
     pub header: Vec<u8>,
@@ -0,0 +1,663 @@
+//! Markdown extensions which pulldown-cmark doesn't have, implemented by
+//! rewriting the events it parses.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+
+use config::Markdown;
+
+/// The kinds of admonition which can be written as `> **Kind**` block quotes.
+const QUOTED_ADMONITIONS: &[&str] = &[
+    "note",
+    "tip",
+    "info",
+    "important",
+    "warning",
+    "caution",
+    "danger",
+];
+
+lazy_static! {
+    static ref FENCE: Regex = Regex::new(r"^:::\s*(?:([a-zA-Z][\w-]*)(?:\s+(.*))?)?$").unwrap();
+    static ref TASK: Regex = Regex::new(r"^\[([ xX])\](\s|$)").unwrap();
+    static ref HEADING_ATTRIBUTES: Regex =
+        Regex::new(r#"\s*\{\s*((?:[#.][^\s{}#."]+\s*)+)\}\s*$"#).unwrap();
+}
+
+/// Apply the extensions which are turned on to a document's events.
+pub fn apply<'a>(events: Vec<Event<'a>>, config: &Markdown) -> Vec<Event<'a>> {
+    let mut events = events;
+
+    if config.admonitions {
+        events = fenced_admonitions(quoted_admonitions(events));
+    }
+    if config.definition_lists {
+        events = definition_lists(events);
+    }
+    if config.tasklists {
+        events = tasklists(events);
+    }
+    if config.strikethrough {
+        events = strikethrough(events);
+    }
+
+    events
+}
+
+/// The ID and classes given to a heading with `{#id .class}`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeadingAttributes {
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+}
+
+/// Split `{#id .class}` off the end of a heading's text, returning the rest
+/// of the text and the attributes.
+pub fn split_heading_attributes(text: &str) -> Option<(&str, HeadingAttributes)> {
+    let caps = HEADING_ATTRIBUTES.captures(text)?;
+    let mut attributes = HeadingAttributes::default();
+
+    for attribute in caps[1].split_whitespace() {
+        let (kind, value) = attribute.split_at(1);
+        if kind == "#" {
+            attributes.id = Some(value.to_string());
+        } else {
+            attributes.classes.push(value.to_string());
+        }
+    }
+
+    let start = caps.get(0).expect("The whole match is always there").start();
+    Some((&text[..start], attributes))
+}
+
+/// Render headings ending with `{#id .class}` with that ID and those
+/// classes.
+pub fn heading_attributes<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut heading = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Header(_)) => heading = Some(vec![event]),
+            Event::End(Tag::Header(level)) => {
+                let mut events = heading.take().expect("Headings don't nest");
+                events.push(Event::End(Tag::Header(level)));
+                push_heading(level, events, &mut out);
+            }
+            event => match heading {
+                Some(ref mut events) => events.push(event),
+                None => out.push(event),
+            },
+        }
+    }
+
+    out
+}
+
+fn push_heading<'a>(level: i32, mut events: Vec<Event<'a>>, out: &mut Vec<Event<'a>>) {
+    let last = events.len() - 2;
+    let split = match events[last] {
+        Event::Text(ref text) => split_heading_attributes(text)
+            .map(|(title, attributes)| (title.trim_end().to_string(), attributes)),
+        _ => None,
+    };
+
+    let (title, attributes) = match split {
+        Some(split) => split,
+        None => {
+            out.extend(events);
+            return;
+        }
+    };
+
+    let mut tag = format!("<h{}", level);
+    if let Some(ref id) = attributes.id {
+        tag.push_str(&format!(" id=\"{}\"", id));
+    }
+    if !attributes.classes.is_empty() {
+        tag.push_str(&format!(" class=\"{}\"", attributes.classes.join(" ")));
+    }
+    tag.push('>');
+
+    events[last] = Event::Text(Cow::from(title));
+    let end = events.len() - 1;
+    out.push(Event::Html(Cow::from(tag)));
+    out.extend(events.drain(1..end));
+    out.push(Event::Html(Cow::from(format!("</h{}>\n", level))));
+}
+
+/// Block quotes starting with a bold admonition kind, e.g.
+/// `> **Warning**: be careful`.
+fn quoted_admonitions<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    // whether each block quote we're in is an admonition
+    let mut quotes = Vec::new();
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                let kind = quoted_admonition_kind(events.as_slice());
+                quotes.push(kind.is_some());
+
+                let kind = match kind {
+                    Some(kind) => kind,
+                    None => {
+                        out.push(event);
+                        continue;
+                    }
+                };
+                out.push(admonition_start(&kind.to_lowercase(), &kind));
+
+                // the rest of the first paragraph, without the title and the
+                // separator after it
+                let mut paragraph = Vec::new();
+                for event in events.by_ref().skip(4) {
+                    match event {
+                        Event::End(Tag::Paragraph) => break,
+                        Event::Text(ref text) if paragraph.is_empty() => {
+                            let text = text.trim_start_matches(':').trim_start();
+                            if !text.is_empty() {
+                                paragraph.push(Event::Text(Cow::from(text.to_string())));
+                            }
+                        }
+                        Event::SoftBreak | Event::HardBreak if paragraph.is_empty() => {}
+                        event => paragraph.push(event),
+                    }
+                }
+                if !paragraph.is_empty() {
+                    push_paragraph(paragraph, &mut out);
+                }
+            }
+            Event::End(Tag::BlockQuote) => {
+                if quotes.pop() == Some(true) {
+                    out.push(Event::Html(Cow::from("</div>\n")));
+                } else {
+                    out.push(event);
+                }
+            }
+            event => out.push(event),
+        }
+    }
+
+    out
+}
+
+/// The admonition's kind, if a block quote starts with `**Kind**`.
+fn quoted_admonition_kind(events: &[Event]) -> Option<String> {
+    if events.len() < 4
+        || events[0] != Event::Start(Tag::Paragraph)
+        || events[1] != Event::Start(Tag::Strong)
+        || events[3] != Event::End(Tag::Strong)
+    {
+        return None;
+    }
+
+    let kind = match events[2] {
+        Event::Text(ref text) => text.trim_end_matches(':'),
+        _ => return None,
+    };
+
+    if QUOTED_ADMONITIONS.contains(&&*kind.to_lowercase()) {
+        Some(kind.to_string())
+    } else {
+        None
+    }
+}
+
+/// Admonitions between `::: kind Optional title` and `:::` lines.
+fn fenced_admonitions<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut depth = 0;
+
+    let mut out = map_paragraphs(events, |inline, out| {
+        let mut lines = split_lines(inline);
+
+        if let Some(fence) = fence(&lines[0]) {
+            match fence {
+                Some((kind, title)) => {
+                    out.push(admonition_start(&kind, &title));
+                    depth += 1;
+                    lines.remove(0);
+                }
+                None if depth > 0 => {
+                    out.push(Event::Html(Cow::from("</div>\n")));
+                    depth -= 1;
+                    lines.remove(0);
+                }
+                None => {}
+            }
+        }
+
+        let closes = depth > 0 && lines.len() > 1 && fence(&lines[lines.len() - 1]) == Some(None);
+        if closes {
+            lines.pop();
+        }
+        if !lines.is_empty() {
+            push_paragraph(join_lines(lines), out);
+        }
+        if closes {
+            out.push(Event::Html(Cow::from("</div>\n")));
+            depth -= 1;
+        }
+    });
+
+    for _ in 0..depth {
+        out.push(Event::Html(Cow::from("</div>\n")));
+    }
+    out
+}
+
+/// Is this line a `:::` fence? Fences which open an admonition have its kind
+/// and title.
+fn fence(line: &[Event]) -> Option<Option<(String, String)>> {
+    if line.len() != 1 {
+        return None;
+    }
+    let text = match line[0] {
+        Event::Text(ref text) => text,
+        _ => return None,
+    };
+    let caps = FENCE.captures(text.trim())?;
+
+    let kind = match caps.get(1) {
+        Some(kind) => kind.as_str().to_lowercase(),
+        None => return Some(None),
+    };
+    let title = match caps.get(2) {
+        Some(title) => title.as_str().to_string(),
+        None => capitalize(&kind),
+    };
+
+    Some(Some((kind, title)))
+}
+
+fn admonition_start<'a>(kind: &str, title: &str) -> Event<'a> {
+    Event::Html(Cow::from(format!(
+        "<div class=\"admonition {}\">\n<p class=\"admonition-title\">{}</p>\n",
+        kind,
+        escape_html(title)
+    )))
+}
+
+/// A paragraph of a term followed by lines starting with `: ` which define
+/// it. Consecutive terms are put in the same list.
+fn definition_lists<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    map_paragraphs(events, |inline, out| {
+        let mut lines = split_lines(inline);
+        let is_definition = |line: &[Event]| match line.first() {
+            Some(Event::Text(text)) => text.starts_with(": "),
+            _ => false,
+        };
+
+        if lines.len() < 2 || !is_definition(&lines[1]) {
+            push_paragraph(join_lines(lines), out);
+            return;
+        }
+
+        let list_end = Event::Html(Cow::from("</dl>\n"));
+        if out.last() == Some(&list_end) {
+            out.pop();
+        } else {
+            out.push(Event::Html(Cow::from("<dl>\n")));
+        }
+
+        let term = lines.remove(0);
+        out.push(Event::Html(Cow::from("<dt>")));
+        out.extend(term);
+        out.push(Event::Html(Cow::from("</dt>\n")));
+
+        // lines which don't start with `: ` carry on the previous definition
+        let mut definitions: Vec<Vec<Vec<Event>>> = Vec::new();
+        for mut line in lines {
+            if is_definition(&line) {
+                let text = match line.remove(0) {
+                    Event::Text(text) => text,
+                    _ => unreachable!("Definitions start with text"),
+                };
+                line.insert(0, Event::Text(Cow::from(text[2..].to_string())));
+                definitions.push(vec![line]);
+            } else if let Some(definition) = definitions.last_mut() {
+                definition.push(line);
+            }
+        }
+
+        for definition in definitions {
+            out.push(Event::Html(Cow::from("<dd>")));
+            out.extend(join_lines(definition));
+            out.push(Event::Html(Cow::from("</dd>\n")));
+        }
+        out.push(list_end);
+    })
+}
+
+/// List items starting with `[ ]` or `[x]` get a checkbox.
+fn tasklists<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut item = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Item) => {
+                item = Some(out.len());
+                out.push(event);
+            }
+            Event::Start(Tag::Paragraph) => out.push(event),
+            Event::Text(ref text) if item.is_some() => {
+                let start = item.take().expect("Just checked");
+                let caps = match TASK.captures(text) {
+                    Some(caps) => caps,
+                    None => {
+                        out.push(event.clone());
+                        continue;
+                    }
+                };
+
+                let checkbox = if &caps[1] == " " {
+                    "<input disabled=\"\" type=\"checkbox\"/>"
+                } else {
+                    "<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"
+                };
+                out[start] = Event::Html(Cow::from("<li class=\"task-list-item\">"));
+                out.push(Event::InlineHtml(Cow::from(checkbox)));
+                out.push(Event::Text(Cow::from(text[3..].to_string())));
+            }
+            event => {
+                item = None;
+                out.push(event);
+            }
+        }
+    }
+
+    out
+}
+
+/// Text between pairs of `~~` is struck through.
+fn strikethrough<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    // the inline events since the last block tag
+    let mut run = Vec::new();
+    let mut in_code_block = false;
+
+    for event in events {
+        match event {
+            Event::Text(_)
+            | Event::InlineHtml(_)
+            | Event::SoftBreak
+            | Event::HardBreak
+            | Event::FootnoteReference(_)
+            | Event::Start(Tag::Emphasis)
+            | Event::End(Tag::Emphasis)
+            | Event::Start(Tag::Strong)
+            | Event::End(Tag::Strong)
+            | Event::Start(Tag::Code)
+            | Event::End(Tag::Code)
+            | Event::Start(Tag::Link(..))
+            | Event::End(Tag::Link(..))
+            | Event::Start(Tag::Image(..))
+            | Event::End(Tag::Image(..))
+                if !in_code_block =>
+            {
+                run.push(event);
+                continue;
+            }
+            _ => {}
+        }
+
+        strike_run(&mut run, &mut out);
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            _ => {}
+        }
+        out.push(event);
+    }
+
+    strike_run(&mut run, &mut out);
+    out
+}
+
+fn strike_run<'a>(run: &mut Vec<Event<'a>>, out: &mut Vec<Event<'a>>) {
+    let mut in_code = false;
+    let mut markers = 0;
+    for event in run.iter() {
+        match *event {
+            Event::Start(Tag::Code) => in_code = true,
+            Event::End(Tag::Code) => in_code = false,
+            Event::Text(ref text) if !in_code => markers += text.matches("~~").count(),
+            _ => {}
+        }
+    }
+
+    // an unpaired `~~` is left alone
+    let mut markers = markers - markers % 2;
+    if markers == 0 {
+        out.append(run);
+        return;
+    }
+
+    let mut struck = false;
+    for event in run.drain(..) {
+        match event {
+            Event::Start(Tag::Code) => in_code = true,
+            Event::End(Tag::Code) => in_code = false,
+            Event::Text(ref text) if !in_code && text.contains("~~") => {
+                for (i, piece) in text.split("~~").enumerate() {
+                    if i > 0 && markers > 0 {
+                        let tag = if struck { "</del>" } else { "<del>" };
+                        out.push(Event::InlineHtml(Cow::from(tag)));
+                        struck = !struck;
+                        markers -= 1;
+                    } else if i > 0 {
+                        out.push(Event::Text(Cow::from("~~")));
+                    }
+                    if !piece.is_empty() {
+                        out.push(Event::Text(Cow::from(piece.to_string())));
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+}
+
+/// Replace each paragraph with the events `replace` pushes, given the
+/// paragraph's contents.
+fn map_paragraphs<'a, F>(events: Vec<Event<'a>>, mut replace: F) -> Vec<Event<'a>>
+where
+    F: FnMut(Vec<Event<'a>>, &mut Vec<Event<'a>>),
+{
+    let mut out = Vec::with_capacity(events.len());
+    let mut paragraph = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Paragraph) => paragraph = Some(Vec::new()),
+            Event::End(Tag::Paragraph) => {
+                let inline = paragraph.take().expect("Paragraphs don't nest");
+                replace(inline, &mut out);
+            }
+            event => match paragraph {
+                Some(ref mut inline) => inline.push(event),
+                None => out.push(event),
+            },
+        }
+    }
+
+    out
+}
+
+fn push_paragraph<'a>(inline: Vec<Event<'a>>, out: &mut Vec<Event<'a>>) {
+    out.push(Event::Start(Tag::Paragraph));
+    out.extend(inline);
+    out.push(Event::End(Tag::Paragraph));
+}
+
+/// Split a paragraph's contents at each soft line break.
+fn split_lines(inline: Vec<Event>) -> Vec<Vec<Event>> {
+    let mut lines = vec![Vec::new()];
+
+    for event in inline {
+        match event {
+            Event::SoftBreak => lines.push(Vec::new()),
+            event => lines.last_mut().expect("There's always a line").push(event),
+        }
+    }
+
+    lines
+}
+
+fn join_lines(lines: Vec<Vec<Event>>) -> Vec<Event> {
+    let mut inline = Vec::new();
+
+    for (i, line) in lines.into_iter().enumerate() {
+        if i > 0 {
+            inline.push(Event::SoftBreak);
+        }
+        inline.extend(line);
+    }
+
+    inline
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::html;
+    use utils::parse_markdown;
+
+    fn render(text: &str, config: &Markdown) -> String {
+        let mut html = String::new();
+        html::push_html(&mut html, parse_markdown(text, config).into_iter());
+        html
+    }
+
+    fn all_extensions() -> Markdown {
+        Markdown {
+            strikethrough: true,
+            tasklists: true,
+            definition_lists: true,
+            heading_attributes: true,
+            smart_punctuation: true,
+            admonitions: true,
+            math: false,
+        }
+    }
+
+    #[test]
+    fn extensions_are_off_by_default() {
+        let src = "~~old~~\n\n- [x] done\n";
+        let should_be = "<p>~~old~~</p>\n<ul>\n<li>[x] done</li>\n</ul>\n";
+
+        assert_eq!(render(src, &Markdown::default()), should_be);
+    }
+
+    #[test]
+    fn strike_through_pairs_of_tildes() {
+        let src = "~~old **bold**~~ new ~~ `~~code~~`";
+        let should_be = "<p><del>old <strong>bold</strong></del> new ~~ \
+                         <code>~~code~~</code></p>\n";
+
+        assert_eq!(render(src, &all_extensions()), should_be);
+    }
+
+    #[test]
+    fn task_lists_have_checkboxes() {
+        let src = "- [ ] todo\n- [x] done\n- [link]\n";
+        let should_be = "<ul>\n\
+                         <li class=\"task-list-item\"><input disabled=\"\" type=\"checkbox\"/> \
+                         todo</li>\n\
+                         <li class=\"task-list-item\"><input disabled=\"\" type=\"checkbox\" \
+                         checked=\"\"/> done</li>\n\
+                         <li>[link]</li>\n\
+                         </ul>\n";
+
+        assert_eq!(render(src, &all_extensions()), should_be);
+    }
+
+    #[test]
+    fn terms_and_their_definitions() {
+        let src = "Apple\n: A *fruit*\n: A company\nbased in California\n\nPear\n: A fruit\n";
+        let should_be = "<dl>\n\
+                         <dt>Apple</dt>\n\
+                         <dd>A <em>fruit</em></dd>\n\
+                         <dd>A company\nbased in California</dd>\n\
+                         <dt>Pear</dt>\n\
+                         <dd>A fruit</dd>\n\
+                         </dl>\n";
+
+        assert_eq!(render(src, &all_extensions()), should_be);
+    }
+
+    #[test]
+    fn admonitions_can_be_block_quotes_or_fenced() {
+        let src = "> **Warning**: be careful\n> out there\n\n\
+                   > **Not** an admonition\n\n\
+                   ::: tip Try this\nIt *works*.\n:::\n\n\
+                   ::: note\n\n- one\n\n:::\n";
+        let should_be = "<div class=\"admonition warning\">\n\
+                         <p class=\"admonition-title\">Warning</p>\n\
+                         <p>be careful\nout there</p>\n\
+                         </div>\n\
+                         <blockquote>\n<p><strong>Not</strong> an admonition</p>\n</blockquote>\n\
+                         <div class=\"admonition tip\">\n\
+                         <p class=\"admonition-title\">Try this</p>\n\
+                         <p>It <em>works</em>.</p>\n\
+                         </div>\n\
+                         <div class=\"admonition note\">\n\
+                         <p class=\"admonition-title\">Note</p>\n\
+                         <ul>\n<li>one</li>\n</ul>\n\
+                         </div>\n";
+
+        assert_eq!(render(src, &all_extensions()), should_be);
+    }
+
+    #[test]
+    fn a_stray_fence_is_left_alone() {
+        let src = "Some text\n:::\n";
+        let should_be = "<p>Some text\n:::</p>\n";
+
+        assert_eq!(render(src, &all_extensions()), should_be);
+    }
+
+    #[test]
+    fn split_off_heading_attributes() {
+        let (title, attributes) = split_heading_attributes("Usage {#how-to .big .wide}").unwrap();
+
+        assert_eq!(title, "Usage");
+        assert_eq!(attributes.id, Some(String::from("how-to")));
+        assert_eq!(attributes.classes, vec!["big", "wide"]);
+        assert!(split_heading_attributes("A {set}").is_none());
+    }
+
+    #[test]
+    fn headings_can_have_an_id_and_classes() {
+        let src = "# Usage {#how-to .big}\n\n## Plain\n";
+        let events = heading_attributes(parse_markdown(src, &all_extensions()));
+        let mut got = String::new();
+        html::push_html(&mut got, events.into_iter());
+
+        assert_eq!(
+            got,
+            "<h1 id=\"how-to\" class=\"big\">Usage</h1>\n<h2>Plain</h2>\n"
+        );
+    }
+}
@@ -24,6 +24,24 @@ pub fn normalize_path(path: &str) -> String {
         .collect::<String>()
 }
 
+/// Lexically resolve any `.` and `..` components in a relative path, without
+/// touching the disk.
+pub fn resolve_relative_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut resolved = PathBuf::new();
+
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(c) => resolved.push(c),
+        }
+    }
+
+    resolved
+}
+
 /// Write the given data to a file, creating it first if necessary
 pub fn write_file<P: AsRef<Path>>(build_dir: &Path, filename: P, content: &[u8]) -> Result<()> {
     let path = build_dir.join(filename);
@@ -88,6 +106,11 @@ pub fn create_file(path: &Path) -> Result<File> {
     File::create(path).map_err(|e| e.into())
 }
 
+/// A directory in the book's root for things which are expensive to generate
+/// and can be reused by later builds. It's kept out of the build directory so
+/// it isn't published along with the book.
+pub const CACHE_DIR: &str = ".mdbook-cache";
+
 /// Removes all the content of a directory but not the directory itself
 pub fn remove_dir_content(dir: &Path) -> Result<()> {
     for item in fs::read_dir(dir)? {
@@ -151,6 +174,7 @@ pub fn copy_files_except_ext(
                     continue;
                 }
             }
+
             debug!(
                 "creating path for file: {:?}",
                 &to.join(
@@ -189,8 +213,9 @@ pub fn copy_files_except_ext(
 mod tests {
     extern crate tempfile;
 
-    use super::copy_files_except_ext;
+    use super::{copy_files_except_ext, resolve_relative_path};
     use std::fs;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn copy_files_except_ext_test() {
@@ -252,4 +277,17 @@ mod tests {
             panic!("output/sub_dir/file.png should exist")
         }
     }
+
+    #[test]
+    fn normalize_relative_paths() {
+        let inputs = vec![
+            ("first/../second.md", "second.md"),
+            ("./first/./nested.md", "first/nested.md"),
+            ("/absolute.md", "absolute.md"),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(resolve_relative_path(Path::new(src)), PathBuf::from(should_be));
+        }
+    }
 }
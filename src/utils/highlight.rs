@@ -0,0 +1,622 @@
+//! Syntax highlighting for code blocks while the book is being built.
+//!
+//! The highlighted code uses the same CSS classes as highlight.js, so the
+//! themes' stylesheets work either way.
+
+use regex::Regex;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Flavour {
+    Plain,
+    Rust,
+    C,
+    Python,
+    Shell,
+    Json,
+    Toml,
+    Yaml,
+}
+
+struct Language {
+    flavour: Flavour,
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    built_ins: &'static [&'static str],
+    /// Keywords which are followed by the name of a function or class.
+    definitions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    flavour: Flavour::Rust,
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    literals: &["true", "false", "Some", "None", "Ok", "Err"],
+    built_ins: &[
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "f32", "f64", "bool", "char", "str", "String", "Vec", "Option", "Result", "Box",
+    ],
+    definitions: &["fn"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+};
+
+const C: Language = Language {
+    flavour: Flavour::C,
+    keywords: &[
+        "auto", "break", "case", "class", "const", "continue", "default", "delete", "do", "else",
+        "enum", "extern", "for", "goto", "if", "inline", "namespace", "new", "private",
+        "protected", "public", "return", "sizeof", "static", "struct", "switch", "template",
+        "this", "typedef", "typename", "union", "using", "virtual", "volatile", "while",
+    ],
+    literals: &["true", "false", "NULL", "nullptr"],
+    built_ins: &[
+        "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned",
+        "void", "size_t",
+    ],
+    definitions: &[],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const GO: Language = Language {
+    flavour: Flavour::Plain,
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough",
+        "for", "func", "go", "goto", "if", "import", "interface", "map", "package", "range",
+        "return", "select", "struct", "switch", "type", "var",
+    ],
+    literals: &["true", "false", "nil", "iota"],
+    built_ins: &[
+        "bool", "byte", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64",
+        "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "append", "len",
+        "make", "panic",
+    ],
+    definitions: &["func"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const JAVA: Language = Language {
+    flavour: Flavour::Plain,
+    keywords: &[
+        "abstract", "break", "case", "catch", "class", "continue", "default", "do", "else",
+        "enum", "extends", "final", "finally", "for", "if", "implements", "import",
+        "instanceof", "interface", "new", "package", "private", "protected", "public",
+        "return", "static", "super", "switch", "synchronized", "this", "throw", "throws", "try",
+        "void", "while",
+    ],
+    literals: &["true", "false", "null"],
+    built_ins: &[
+        "boolean", "byte", "char", "double", "float", "int", "long", "short", "String",
+    ],
+    definitions: &["class", "interface"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const JAVASCRIPT: Language = Language {
+    flavour: Flavour::Plain,
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "export", "extends", "finally", "for", "from", "function", "if",
+        "import", "in", "instanceof", "interface", "let", "new", "of", "return", "switch",
+        "this", "throw", "try", "type", "typeof", "var", "void", "while", "yield",
+    ],
+    literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    built_ins: &[
+        "Array", "Object", "String", "Number", "Boolean", "Promise", "Map", "Set", "JSON",
+        "Math", "console", "window", "document",
+    ],
+    definitions: &["function", "class"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const JSON: Language = Language {
+    flavour: Flavour::Json,
+    keywords: &[],
+    literals: &["true", "false", "null"],
+    built_ins: &[],
+    definitions: &[],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+};
+
+const PYTHON: Language = Language {
+    flavour: Flavour::Python,
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+        "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+        "with", "yield",
+    ],
+    literals: &["True", "False", "None"],
+    built_ins: &[
+        "dict", "float", "int", "len", "list", "open", "print", "range", "set", "str", "tuple",
+    ],
+    definitions: &["def", "class"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    flavour: Flavour::Shell,
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "in", "do", "done", "case", "esac", "while",
+        "until", "function", "return", "export", "local",
+    ],
+    literals: &["true", "false"],
+    built_ins: &["cd", "echo", "exit", "read", "set", "source", "unset"],
+    definitions: &["function"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const TOML: Language = Language {
+    flavour: Flavour::Toml,
+    keywords: &[],
+    literals: &["true", "false"],
+    built_ins: &[],
+    definitions: &[],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const YAML: Language = Language {
+    flavour: Flavour::Yaml,
+    keywords: &[],
+    literals: &["true", "false", "null", "yes", "no"],
+    built_ins: &[],
+    definitions: &[],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+fn language(name: &str) -> Option<&'static Language> {
+    let language = match &*name.to_lowercase() {
+        "rust" | "rs" => &RUST,
+        "c" | "h" | "cpp" | "c++" | "cc" | "hpp" => &C,
+        "go" | "golang" => &GO,
+        "java" => &JAVA,
+        "javascript" | "js" | "typescript" | "ts" => &JAVASCRIPT,
+        "json" => &JSON,
+        "python" | "py" => &PYTHON,
+        "bash" | "sh" | "shell" | "zsh" | "console" => &SHELL,
+        "toml" => &TOML,
+        "yaml" | "yml" => &YAML,
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+/// Can code written in this language be highlighted?
+pub fn is_supported(language_name: &str) -> bool {
+    language(language_name).is_some()
+}
+
+/// Highlight some code, returning HTML with highlight.js's `<span>`s, or
+/// `None` if the language isn't supported.
+///
+/// No `<span>` goes over the end of a line, so the code can still be split
+/// into lines (e.g. to hide some of them).
+pub fn highlight(code: &str, language_name: &str) -> Option<String> {
+    let language = language(language_name)?;
+    let mut highlighter = Highlighter {
+        language,
+        code,
+        pos: 0,
+        html: String::with_capacity(code.len() * 2),
+        previous_word: None,
+    };
+
+    highlighter.run();
+    Some(highlighter.html)
+}
+
+lazy_static! {
+    static ref NUMBER: Regex = Regex::new(r"^[0-9][0-9a-zA-Z_]*(\.[0-9][0-9a-zA-Z_]*)?").unwrap();
+    static ref IDENTIFIER: Regex = Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*").unwrap();
+    static ref RUST_CHAR: Regex = Regex::new(r"^b?'(\\.[^']*|[^\\'])'").unwrap();
+    static ref RUST_LIFETIME: Regex = Regex::new(r"^'[a-zA-Z_][a-zA-Z0-9_]*").unwrap();
+    static ref RUST_RAW_STRING: Regex = Regex::new(r##"^b?r(#*)""##).unwrap();
+    static ref SHELL_VARIABLE: Regex =
+        Regex::new(r"^\$(\{[^}\n]*\}|[a-zA-Z_][a-zA-Z0-9_]*|[0-9@#?$!*-])").unwrap();
+    static ref TOML_KEY: Regex = Regex::new(r#"^[a-zA-Z0-9_.\-"' ]+?\s*="#).unwrap();
+    static ref YAML_KEY: Regex = Regex::new(r"^(- )?[a-zA-Z0-9_.\-]+:(\s|$)").unwrap();
+    static ref JSON_KEY: Regex = Regex::new(r#"^"(\\.|[^\\"])*"\s*:"#).unwrap();
+}
+
+struct Highlighter<'a> {
+    language: &'static Language,
+    code: &'a str,
+    pos: usize,
+    html: String,
+    previous_word: Option<&'a str>,
+}
+
+impl<'a> Highlighter<'a> {
+    fn run(&mut self) {
+        while self.pos < self.code.len() {
+            if !self.next_token() {
+                let ch = self.rest().chars().next().expect("Not at the end yet");
+                if !ch.is_whitespace() {
+                    self.previous_word = None;
+                }
+                self.push(None, ch.len_utf8());
+            }
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.code[self.pos..]
+    }
+
+    /// The character before the current position, or a newline at the start
+    /// of the code.
+    fn previous_char(&self) -> char {
+        self.code[..self.pos].chars().next_back().unwrap_or('\n')
+    }
+
+    /// Is everything before the current position on this line whitespace?
+    fn at_line_start(&self) -> bool {
+        let line_start = self.code[..self.pos].rfind('\n').map_or(0, |i| i + 1);
+        self.code[line_start..self.pos].trim().is_empty()
+    }
+
+    /// Highlight the next `len` bytes, one line at a time.
+    fn push(&mut self, class: Option<&str>, len: usize) {
+        let text = &self.code[self.pos..self.pos + len];
+        self.pos += len;
+
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.html.push('\n');
+            }
+            match class {
+                Some(class) if !line.is_empty() => {
+                    self.html.push_str("<span class=\"hljs-");
+                    self.html.push_str(class);
+                    self.html.push_str("\">");
+                    push_escaped(&mut self.html, line);
+                    self.html.push_str("</span>");
+                }
+                _ => push_escaped(&mut self.html, line),
+            }
+        }
+    }
+
+    /// Highlight the token at the current position, returning whether there
+    /// was one.
+    fn next_token(&mut self) -> bool {
+        let rest = self.rest();
+        let language = self.language;
+
+        for comment in language.line_comments {
+            // `#` only starts a shell comment at the start of a word
+            let starts_word = self.previous_char().is_whitespace();
+            if rest.starts_with(comment) && (language.flavour != Flavour::Shell || starts_word) {
+                let len = rest.find('\n').unwrap_or(rest.len());
+                self.push(Some("comment"), len);
+                return true;
+            }
+        }
+
+        if let Some((open, close)) = language.block_comment {
+            if rest.starts_with(open) {
+                let (_, body) = rest.split_at(open.len());
+                let len = body
+                    .find(close)
+                    .map_or(rest.len(), |end| open.len() + end + close.len());
+                self.push(Some("comment"), len);
+                return true;
+            }
+        }
+
+        if self.flavoured_token() {
+            return true;
+        }
+
+        if let Some(quote) = rest.chars().next().filter(|ch| language.quotes.contains(ch)) {
+            // apostrophes in words (e.g. "don't") aren't quotes
+            let in_word = self.previous_char().is_alphanumeric();
+            if quote == '"' || !in_word {
+                let len = string_length(rest, quote, language.flavour);
+                self.push(Some("string"), len);
+                return true;
+            }
+        }
+
+        let previous = self.previous_char();
+        let in_word = previous.is_alphanumeric() || previous == '_';
+        if in_word {
+            return false;
+        }
+
+        if let Some(number) = NUMBER.find(rest) {
+            self.push(Some("number"), number.end());
+            return true;
+        }
+
+        if let Some(word) = IDENTIFIER.find(rest) {
+            self.identifier(word.as_str());
+            return true;
+        }
+
+        false
+    }
+
+    fn identifier(&mut self, word: &'a str) {
+        let language = self.language;
+        let after = &self.rest()[word.len()..];
+
+        let is_definition = match self.previous_word {
+            Some(previous) => language.definitions.contains(&previous),
+            None => false,
+        };
+
+        let class = if is_definition {
+            Some("title")
+        } else if language.keywords.contains(&word) {
+            Some("keyword")
+        } else if language.literals.contains(&word) {
+            Some("literal")
+        } else if language.built_ins.contains(&word) {
+            Some("built_in")
+        } else {
+            None
+        };
+
+        // macros, e.g. `println!`
+        if language.flavour == Flavour::Rust && after.starts_with('!') && !after.starts_with("!=")
+        {
+            self.push(Some("built_in"), word.len() + 1);
+        } else {
+            self.push(class, word.len());
+        }
+
+        self.previous_word = Some(word);
+    }
+
+    /// Tokens which only some languages have.
+    fn flavoured_token(&mut self) -> bool {
+        let rest = self.rest();
+
+        match self.language.flavour {
+            Flavour::Rust => {
+                if rest.starts_with("#[") || rest.starts_with("#![") {
+                    let len = bracketed_length(rest);
+                    self.push(Some("meta"), len);
+                    return true;
+                }
+                if let Some(raw) = RUST_RAW_STRING.captures(rest) {
+                    let end = format!("\"{}", &raw[1]);
+                    let start = raw[0].len();
+                    let len = rest[start..]
+                        .find(&end)
+                        .map_or(rest.len(), |i| start + i + end.len());
+                    self.push(Some("string"), len);
+                    return true;
+                }
+                if rest.starts_with("b\"") {
+                    let len = 1 + string_length(&rest[1..], '"', Flavour::Rust);
+                    self.push(Some("string"), len);
+                    return true;
+                }
+                if let Some(ch) = RUST_CHAR.find(rest) {
+                    self.push(Some("string"), ch.end());
+                    return true;
+                }
+                if let Some(lifetime) = RUST_LIFETIME.find(rest) {
+                    self.push(Some("symbol"), lifetime.end());
+                    return true;
+                }
+            }
+            Flavour::C if rest.starts_with('#') && self.at_line_start() => {
+                let len = rest.find('\n').unwrap_or(rest.len());
+                self.push(Some("meta"), len);
+                return true;
+            }
+            Flavour::Python if rest.starts_with('@') && self.at_line_start() => {
+                let len = 1 + IDENTIFIER.find(&rest[1..]).map_or(0, |word| word.end());
+                self.push(Some("meta"), len);
+                return true;
+            }
+            Flavour::Shell => {
+                if let Some(variable) = SHELL_VARIABLE.find(rest) {
+                    self.push(Some("variable"), variable.end());
+                    return true;
+                }
+            }
+            Flavour::Json => {
+                if let Some(key) = JSON_KEY.find(rest) {
+                    let len = key.as_str().trim_end_matches(':').trim_end().len();
+                    self.push(Some("attribute"), len);
+                    return true;
+                }
+            }
+            Flavour::Toml if self.at_line_start() => {
+                if rest.starts_with('[') {
+                    let len = rest.find(']').map_or(rest.len(), |end| end + 1);
+                    self.push(Some("section"), len);
+                    return true;
+                }
+                if let Some(key) = TOML_KEY.find(rest) {
+                    let len = key.as_str().trim_end_matches('=').trim_end().len();
+                    self.push(Some("attribute"), len);
+                    return true;
+                }
+            }
+            Flavour::Yaml if self.at_line_start() => {
+                if let Some(key) = YAML_KEY.find(rest) {
+                    let key = key.as_str().trim_end();
+                    if key.starts_with("- ") {
+                        self.push(None, 2);
+                        self.push(Some("attribute"), key.len() - 3);
+                    } else {
+                        self.push(Some("attribute"), key.len() - 1);
+                    }
+                    return true;
+                }
+            }
+            _ => {}
+        }
+
+        false
+    }
+}
+
+/// The length of the string at the start of `text`, including its quotes.
+fn string_length(text: &str, quote: char, flavour: Flavour) -> usize {
+    let triple: String = [quote; 3].iter().collect();
+    let has_triple_quotes = flavour == Flavour::Python || flavour == Flavour::Toml;
+    if has_triple_quotes && text.starts_with(&triple) {
+        return text[3..].find(&triple).map_or(text.len(), |end| end + 6);
+    }
+
+    // single quoted strings in the shell don't have escapes
+    let has_escapes = !(flavour == Flavour::Shell && quote == '\'');
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, ch)) = chars.next() {
+        if ch == '\\' && has_escapes {
+            chars.next();
+        } else if ch == quote {
+            return i + 1;
+        }
+    }
+
+    text.len()
+}
+
+/// The length of the `[...]` at the start of `text` (after a `#` or `#!`),
+/// including any brackets inside it.
+fn bracketed_length(text: &str) -> usize {
+    let mut depth = 0;
+
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '[' => depth += 1,
+            ']' if depth == 1 => return i + 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    text.len()
+}
+
+fn push_escaped(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            _ => html.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_rust() {
+        let src = "#[derive(Debug)]\nfn main() {\n    // say hi\n    let x: u32 = 5;\n    \
+                   println!(\"{} <{}>\", x, 'a');\n}\n";
+        let should_be = "<span class=\"hljs-meta\">#[derive(Debug)]</span>\n\
+                         <span class=\"hljs-keyword\">fn</span> \
+                         <span class=\"hljs-title\">main</span>() {\n    \
+                         <span class=\"hljs-comment\">// say hi</span>\n    \
+                         <span class=\"hljs-keyword\">let</span> x: \
+                         <span class=\"hljs-built_in\">u32</span> = \
+                         <span class=\"hljs-number\">5</span>;\n    \
+                         <span class=\"hljs-built_in\">println!</span>(\
+                         <span class=\"hljs-string\">&quot;{} &lt;{}&gt;&quot;</span>, x, \
+                         <span class=\"hljs-string\">'a'</span>);\n}\n";
+
+        assert_eq!(highlight(src, "rust").unwrap(), should_be);
+    }
+
+    #[test]
+    fn spans_stop_at_the_end_of_each_line() {
+        let src = "/* one\ntwo */\n# let hidden = r#\"raw\"#;";
+        let should_be = "<span class=\"hljs-comment\">/* one</span>\n\
+                         <span class=\"hljs-comment\">two */</span>\n\
+                         # <span class=\"hljs-keyword\">let</span> hidden = \
+                         <span class=\"hljs-string\">r#&quot;raw&quot;#</span>;";
+
+        assert_eq!(highlight(src, "rust").unwrap(), should_be);
+    }
+
+    #[test]
+    fn rust_lifetimes_arent_strings() {
+        let got = highlight("fn f<'a>(x: &'a str) {}", "rs").unwrap();
+
+        assert!(got.contains("&lt;<span class=\"hljs-symbol\">'a</span>&gt;"));
+        assert!(got.contains("&amp;<span class=\"hljs-symbol\">'a</span> "));
+    }
+
+    #[test]
+    fn highlight_configuration_files() {
+        let toml = highlight("[book]\ntitle = \"Example\" # comment", "toml").unwrap();
+        assert_eq!(
+            toml,
+            "<span class=\"hljs-section\">[book]</span>\n\
+             <span class=\"hljs-attribute\">title</span> = \
+             <span class=\"hljs-string\">&quot;Example&quot;</span> \
+             <span class=\"hljs-comment\"># comment</span>"
+        );
+
+        let json = highlight("{\"enable\": true}", "json").unwrap();
+        assert_eq!(
+            json,
+            "{<span class=\"hljs-attribute\">&quot;enable&quot;</span>: \
+             <span class=\"hljs-literal\">true</span>}"
+        );
+
+        let yaml = highlight("- name: don't\n  count: 2", "yaml").unwrap();
+        assert_eq!(
+            yaml,
+            "- <span class=\"hljs-attribute\">name</span>: don't\n  \
+             <span class=\"hljs-attribute\">count</span>: <span class=\"hljs-number\">2</span>"
+        );
+    }
+
+    #[test]
+    fn highlight_the_shell() {
+        let got = highlight("echo \"$HOME\" 'it#s' # done", "bash").unwrap();
+
+        assert_eq!(
+            got,
+            "<span class=\"hljs-built_in\">echo</span> \
+             <span class=\"hljs-string\">&quot;$HOME&quot;</span> \
+             <span class=\"hljs-string\">'it#s'</span> \
+             <span class=\"hljs-comment\"># done</span>"
+        );
+    }
+
+    #[test]
+    fn unknown_languages_arent_highlighted() {
+        assert!(!is_supported("brainfuck"));
+        assert_eq!(highlight("+++", "brainfuck"), None);
+    }
+}
@@ -0,0 +1,1217 @@
+//! Equations written in TeX, rendered to MathML while building the book.
+//!
+//! Equations are taken out of the markdown before it's parsed, so
+//! pulldown-cmark doesn't treat `_`, `*` or `\` in them as markdown, and put
+//! back as MathML afterwards.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Tag};
+use regex::{Captures, Regex};
+
+/// The start and end of the placeholder for an equation, which are in the
+/// Unicode private use area so they won't be in any text.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new("\u{E000}([0-9]+)\u{E001}").unwrap();
+}
+
+/// An equation taken out of the markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equation {
+    /// The equation as it was written, including its delimiters.
+    pub source: String,
+    pub tex: String,
+    pub display: bool,
+}
+
+/// Replace the equations in some markdown with placeholders. Equations are
+/// between `$` or `\(` and `\)` (inline) and `$$` or `\[` and `\]` (display).
+pub fn protect(text: &str) -> (String, Vec<Equation>) {
+    // Equations can go over several lines, so they're found in the whole
+    // text, skipping the lines of code blocks
+    let mut equations = Vec::new();
+    let code_lines = fenced_lines(text);
+    let mut protected = String::with_capacity(text.len());
+    let mut pos = 0;
+    let mut copied = 0;
+
+    while pos < text.len() {
+        if let Some(end) = code_lines.iter().find(|&&(start, _)| start == pos).map(|r| r.1) {
+            pos = end;
+            continue;
+        }
+
+        let rest = &text[pos..];
+        let found = if rest.starts_with('`') {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            pos += code_span_length(rest, ticks);
+            continue;
+        } else if rest.starts_with("\\$") || rest.starts_with("\\\\") {
+            pos += 2;
+            continue;
+        } else if rest.starts_with("$$") {
+            find_end(rest, "$$", "$$", &code_lines, pos).map(|len| (len, 2, 2, true))
+        } else if rest.starts_with("\\[") {
+            find_end(rest, "\\[", "\\]", &code_lines, pos).map(|len| (len, 2, 2, true))
+        } else if rest.starts_with("\\(") {
+            find_end(rest, "\\(", "\\)", &code_lines, pos).map(|len| (len, 2, 2, false))
+        } else if rest.starts_with('$') {
+            inline_dollar_length(rest).map(|len| (len, 1, 1, false))
+        } else {
+            None
+        };
+
+        match found {
+            Some((len, open, close, display)) => {
+                let source = &rest[..len];
+                protected.push_str(&text[copied..pos]);
+                protected.push(PLACEHOLDER_START);
+                protected.push_str(&equations.len().to_string());
+                protected.push(PLACEHOLDER_END);
+                equations.push(Equation {
+                    source: source.to_string(),
+                    tex: source[open..len - close].trim().to_string(),
+                    display,
+                });
+                pos += len;
+                copied = pos;
+            }
+            None => pos += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    protected.push_str(&text[copied..]);
+    (protected, equations)
+}
+
+/// Put the equations back into the parsed markdown as MathML, or as they
+/// were written if they ended up in code, a link or an image.
+pub fn restore(events: Vec<Event>, equations: &[Equation]) -> Vec<Event<'static>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut in_code = false;
+    let source =
+        |text: &str| Cow::from(replace_placeholders(text, |eq| eq.source.clone(), equations));
+    let mathml = |text: &str| Cow::from(replace_placeholders(text, render_equation, equations));
+
+    for event in events {
+        let event = match event {
+            Event::Start(tag) => {
+                match tag {
+                    Tag::CodeBlock(_) | Tag::Code => in_code = true,
+                    _ => {}
+                }
+                Event::Start(restore_tag(tag, source))
+            }
+            Event::End(tag) => {
+                match tag {
+                    Tag::CodeBlock(_) | Tag::Code => in_code = false,
+                    _ => {}
+                }
+                Event::End(restore_tag(tag, source))
+            }
+            Event::Text(ref text) if !in_code && text.contains(PLACEHOLDER_START) => {
+                split_text(text, equations, &mut out);
+                continue;
+            }
+            Event::Text(text) => Event::Text(source(&text)),
+            Event::Html(html) => Event::Html(mathml(&html)),
+            Event::InlineHtml(html) => Event::InlineHtml(mathml(&html)),
+            Event::FootnoteReference(name) => Event::FootnoteReference(source(&name)),
+            Event::SoftBreak => Event::SoftBreak,
+            Event::HardBreak => Event::HardBreak,
+        };
+        out.push(event);
+    }
+
+    out
+}
+
+fn restore_tag<F>(tag: Tag, source: F) -> Tag<'static>
+where
+    F: Fn(&str) -> Cow<'static, str>,
+{
+    match tag {
+        Tag::Paragraph => Tag::Paragraph,
+        Tag::Rule => Tag::Rule,
+        Tag::Header(level) => Tag::Header(level),
+        Tag::BlockQuote => Tag::BlockQuote,
+        Tag::CodeBlock(info) => Tag::CodeBlock(source(&info)),
+        Tag::List(start) => Tag::List(start),
+        Tag::Item => Tag::Item,
+        Tag::FootnoteDefinition(name) => Tag::FootnoteDefinition(source(&name)),
+        Tag::Table(alignments) => Tag::Table(alignments),
+        Tag::TableHead => Tag::TableHead,
+        Tag::TableRow => Tag::TableRow,
+        Tag::TableCell => Tag::TableCell,
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Code => Tag::Code,
+        Tag::Link(dest, title) => Tag::Link(source(&dest), source(&title)),
+        Tag::Image(dest, title) => Tag::Image(source(&dest), source(&title)),
+    }
+}
+
+fn split_text(text: &str, equations: &[Equation], out: &mut Vec<Event<'static>>) {
+    let mut last = 0;
+
+    for caps in PLACEHOLDER.captures_iter(text) {
+        let whole = caps.get(0).expect("The whole match is always there");
+        let equation = caps[1].parse().ok().and_then(|i: usize| equations.get(i));
+        let equation = match equation {
+            Some(equation) => equation,
+            None => continue,
+        };
+
+        if whole.start() > last {
+            out.push(Event::Text(Cow::from(text[last..whole.start()].to_string())));
+        }
+        out.push(Event::InlineHtml(Cow::from(render_equation(equation))));
+        last = whole.end();
+    }
+
+    if last < text.len() {
+        out.push(Event::Text(Cow::from(text[last..].to_string())));
+    }
+}
+
+fn replace_placeholders<F>(text: &str, replacement: F, equations: &[Equation]) -> String
+where
+    F: Fn(&Equation) -> String,
+{
+    PLACEHOLDER
+        .replace_all(text, |caps: &Captures| {
+            match caps[1].parse().ok().and_then(|i: usize| equations.get(i)) {
+                Some(equation) => replacement(equation),
+                None => caps[0].to_string(),
+            }
+        }).into_owned()
+}
+
+/// The byte ranges of the lines of fenced code blocks, which can't have
+/// equations in them.
+fn fenced_lines(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut start = 0;
+
+    for line in lines_with_endings(text) {
+        let end = start + line.len();
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let fence_char = trimmed.chars().next().filter(|&c| c == '`' || c == '~');
+        let fence_len =
+            fence_char.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+        let is_fence = indent < 4 && fence_len >= 3;
+
+        match fence {
+            Some((c, len)) => {
+                ranges.push((start, end));
+                let closes = is_fence && fence_char == Some(c) && fence_len >= len;
+                if closes && trimmed.trim_start_matches(c).trim().is_empty() {
+                    fence = None;
+                }
+            }
+            None if is_fence => {
+                ranges.push((start, end));
+                fence = fence_char.map(|c| (c, fence_len));
+            }
+            None => {}
+        }
+
+        start = end;
+    }
+
+    ranges
+}
+
+/// The length of a code span starting with `ticks` backticks, or just of the
+/// backticks if it isn't closed.
+fn code_span_length(text: &str, ticks: usize) -> usize {
+    let mut pos = ticks;
+
+    while let Some(i) = text[pos..].find('`') {
+        let start = pos + i;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == ticks {
+            return start + run;
+        }
+        pos = start + run;
+    }
+
+    ticks
+}
+
+/// The length of the equation between `open` and `close` at the start of
+/// `text`, if it's closed before a blank line or a code block.
+fn find_end(
+    text: &str,
+    open: &str,
+    close: &str,
+    code_lines: &[(usize, usize)],
+    offset: usize,
+) -> Option<usize> {
+    let end = text[open.len()..].find(close)? + open.len();
+    let body = &text[open.len()..end];
+
+    let in_code = code_lines
+        .iter()
+        .any(|&(start, _)| start > offset && start < offset + end);
+    if body.trim().is_empty() || in_code || is_blank_line_in(body) {
+        return None;
+    }
+
+    Some(end + close.len())
+}
+
+/// The length of an inline `$...$` equation at the start of `text`. Like
+/// Pandoc, the opening `$` can't be followed by a space and the closing one
+/// can't come after a space or before a digit, so amounts of money aren't
+/// equations.
+fn inline_dollar_length(text: &str) -> Option<usize> {
+    let after_open = text[1..].chars().next()?;
+    if after_open.is_whitespace() {
+        return None;
+    }
+
+    let mut escaped = false;
+    let mut previous = '$';
+    for (i, ch) in text.char_indices().skip(1) {
+        match ch {
+            '\\' if !escaped => {
+                escaped = true;
+                previous = ch;
+                continue;
+            }
+            '$' if !escaped => {
+                return match text[i + 1..].chars().next() {
+                    _ if previous.is_whitespace() => None,
+                    Some(next) if next.is_ascii_digit() => None,
+                    _ => Some(i + 1),
+                };
+            }
+            '\n' if previous == '\n' => return None,
+            // the equation would overlap a code span
+            '`' => return None,
+            _ => {}
+        }
+        escaped = false;
+        if ch != '\r' && !(ch == ' ' && previous == '\n') {
+            previous = ch;
+        }
+    }
+
+    None
+}
+
+fn is_blank_line_in(text: &str) -> bool {
+    let lines: Vec<_> = text.split('\n').collect();
+    lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|line| line.trim().is_empty())
+}
+
+/// The lines of some text, keeping their line endings.
+fn lines_with_endings(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' {
+            lines.push(&text[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+
+    lines
+}
+
+/// Render an equation to a MathML `<math>` element, keeping the TeX as an
+/// annotation.
+pub fn render_equation(equation: &Equation) -> String {
+    let mut parser = Parser {
+        tokens: tokenize(&equation.tex),
+        pos: 0,
+        display: equation.display,
+    };
+    let mut row = parser.row();
+
+    // a row only stops early at something which closes a group, so anything
+    // left over was never opened
+    while let Some(token) = parser.next() {
+        let stray = match token {
+            Token::Command(ref name) if name == "\\" => {
+                row.push(String::from("<mspace linebreak=\"newline\"/>"));
+                None
+            }
+            Token::Command(name) => Some(format!("\\{}", name)),
+            Token::Close => Some(String::from("}")),
+            Token::Column => Some(String::from("&")),
+            _ => None,
+        };
+        if let Some(stray) = stray {
+            warn!("Unexpected \"{}\" in the equation {}", stray, equation.source);
+            row.push(format!("<merror><mtext>{}</mtext></merror>", escape(&stray)));
+        }
+        row.extend(parser.row());
+    }
+
+    format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"{}\">\
+         <semantics><mrow>{}</mrow>\
+         <annotation encoding=\"application/x-tex\">{}</annotation>\
+         </semantics></math>",
+        if equation.display { "block" } else { "inline" },
+        row.join(""),
+        escape(&equation.tex)
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(String),
+    Char(char),
+    Open,
+    Close,
+    Superscript,
+    Subscript,
+    Column,
+}
+
+fn tokenize(tex: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = tex.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let token = match ch {
+            '\\' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    match chars.next() {
+                        Some(c) => name.push(c),
+                        None => continue,
+                    }
+                }
+                Token::Command(name)
+            }
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Superscript,
+            '_' => Token::Subscript,
+            '&' => Token::Column,
+            c if c.is_whitespace() => continue,
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    display: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Elements up to the end of the current group, table cell or `\right`.
+    fn row(&mut self) -> Vec<String> {
+        let mut elements = Vec::new();
+
+        loop {
+            match self.peek() {
+                None | Some(&Token::Close) | Some(&Token::Column) => break,
+                Some(Token::Command(name))
+                    if name == "right" || name == "end" || name == "\\" =>
+                {
+                    break
+                }
+                _ => {}
+            }
+
+            if let Some(element) = self.scripted() {
+                elements.push(element);
+            }
+        }
+
+        elements
+    }
+
+    /// Elements up to a `]`, for the optional argument of `\sqrt`.
+    fn optional_argument(&mut self) -> Option<String> {
+        if self.peek() != Some(&Token::Char('[')) {
+            return None;
+        }
+        self.pos += 1;
+
+        let mut elements = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            if token == Token::Char(']') {
+                self.pos += 1;
+                break;
+            }
+            if token == Token::Close {
+                break;
+            }
+            if let Some(element) = self.scripted() {
+                elements.push(element);
+            }
+        }
+
+        Some(mrow(elements))
+    }
+
+    /// An element with any sub- and superscripts after it.
+    fn scripted(&mut self) -> Option<String> {
+        let limits = self.has_limits();
+        let base = self.atom()?;
+        let mut sub = None;
+        let mut sup = None;
+
+        loop {
+            match self.peek() {
+                Some(&Token::Subscript) if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.argument());
+                }
+                Some(&Token::Superscript) if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.argument());
+                }
+                Some(&Token::Char('\'')) if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some(&Token::Char('\'')) {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(format!("<mo>{}</mo>", primes));
+                }
+                _ => break,
+            }
+        }
+
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        Some(match (sub, sup) {
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, sub, sup),
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (None, None) => base,
+        })
+    }
+
+    /// Are the scripts of the next element written above and below it, like
+    /// the limits of a sum in a displayed equation?
+    fn has_limits(&self) -> bool {
+        match self.peek() {
+            Some(Token::Command(name)) => {
+                name == "lim"
+                    || name == "max"
+                    || name == "min"
+                    || name == "underbrace"
+                    || name == "overbrace"
+                    || (self.display && LARGE_OPERATORS.contains(&name.as_str()))
+            }
+            _ => false,
+        }
+    }
+
+    /// The argument of a command or script: a group or a single element.
+    fn argument(&mut self) -> String {
+        match self.peek() {
+            Some(&Token::Open) => self.atom().unwrap_or_default(),
+            Some(&Token::Char(c)) if c.is_ascii_digit() => {
+                self.pos += 1;
+                format!("<mn>{}</mn>", c)
+            }
+            Some(_) => self.atom().unwrap_or_default(),
+            None => String::from("<mrow></mrow>"),
+        }
+    }
+
+    /// The raw text of a group, for commands like `\text`.
+    fn text_argument(&mut self) -> String {
+        let mut text = String::new();
+        if self.peek() != Some(&Token::Open) {
+            if let Some(token) = self.next() {
+                push_token_text(&token, &mut text);
+            }
+            return text;
+        }
+        self.pos += 1;
+
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Close if depth == 0 => break,
+                Token::Close => depth -= 1,
+                Token::Open => depth += 1,
+                _ => {}
+            }
+            push_token_text(&token, &mut text);
+        }
+
+        text
+    }
+
+    fn atom(&mut self) -> Option<String> {
+        let token = self.next()?;
+
+        let element = match token {
+            Token::Open => {
+                let row = self.row();
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                }
+                mrow(row)
+            }
+            Token::Close | Token::Column => return None,
+            Token::Superscript | Token::Subscript => {
+                // a script without anything before it
+                let argument = self.argument();
+                let tag = if token == Token::Superscript {
+                    "msup"
+                } else {
+                    "msub"
+                };
+                format!("<{0}><mrow></mrow>{1}</{0}>", tag, argument)
+            }
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&Token::Char(c)) = self.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    number.push(c);
+                    self.pos += 1;
+                }
+                format!("<mn>{}</mn>", number)
+            }
+            Token::Char(c) if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            Token::Char(c) => operator(&c.to_string()),
+            Token::Command(name) => self.command(&name),
+        };
+
+        Some(element)
+    }
+
+    fn command(&mut self, name: &str) -> String {
+        if let Some(&(_, symbol)) = GREEK.iter().find(|&&(n, _)| n == name) {
+            if symbol.chars().all(char::is_uppercase) {
+                return format!("<mi mathvariant=\"normal\">{}</mi>", symbol);
+            }
+            return format!("<mi>{}</mi>", symbol);
+        }
+        if let Some(&(_, symbol)) = SYMBOLS.iter().find(|&&(n, _)| n == name) {
+            return operator(symbol);
+        }
+        if let Some(&(_, symbol)) = IDENTIFIERS.iter().find(|&&(n, _)| n == name) {
+            return format!("<mi>{}</mi>", symbol);
+        }
+        if FUNCTIONS.contains(&name) {
+            return format!("<mi>{}</mi><mo>&#x2061;</mo>", name);
+        }
+        if let Some(&(_, width)) = SPACES.iter().find(|&&(n, _)| n == name) {
+            return format!("<mspace width=\"{}\"/>", width);
+        }
+        if let Some(&(_, accent)) = ACCENTS.iter().find(|&&(n, _)| n == name) {
+            let argument = self.argument();
+            return format!(
+                "<mover accent=\"true\">{}<mo>{}</mo></mover>",
+                argument, accent
+            );
+        }
+
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.argument();
+                let bottom = self.argument();
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )
+            }
+            "sqrt" => match self.optional_argument() {
+                Some(index) => {
+                    let radicand = self.argument();
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                }
+                None => format!("<msqrt>{}</msqrt>", self.argument()),
+            },
+            "underline" => format!(
+                "<munder accentunder=\"true\">{}<mo>_</mo></munder>",
+                self.argument()
+            ),
+            "underbrace" => format!("<munder>{}<mo>⏟</mo></munder>", self.argument()),
+            "overbrace" => format!("<mover>{}<mo>⏞</mo></mover>", self.argument()),
+            "text" | "textrm" | "mbox" => {
+                format!("<mtext>{}</mtext>", escape(&self.text_argument()))
+            }
+            "mathrm" | "operatorname" | "textup" => format!(
+                "<mi mathvariant=\"normal\">{}</mi>",
+                escape(&self.text_argument())
+            ),
+            "mathit" | "textit" => format!("<mi>{}</mi>", escape(&self.text_argument())),
+            "mathbf" | "textbf" | "boldsymbol" => {
+                let text = map_letters(&self.text_argument(), bold);
+                format!("<mi>{}</mi>", escape(&text))
+            }
+            "mathbb" => {
+                let text = map_letters(&self.text_argument(), double_struck);
+                format!("<mi>{}</mi>", escape(&text))
+            }
+            "mathcal" => {
+                let text = map_letters(&self.text_argument(), script);
+                format!("<mi>{}</mi>", escape(&text))
+            }
+            "left" | "bigl" | "Bigl" => {
+                let open = self.delimiter();
+                let row = self.row();
+                let right = Token::Command(String::from("right"));
+                let close = if self.peek() == Some(&right) {
+                    self.pos += 1;
+                    self.delimiter()
+                } else {
+                    String::new()
+                };
+                format!("<mrow>{}{}{}</mrow>", open, row.join(""), close)
+            }
+            "right" | "bigr" | "Bigr" | "big" | "Big" | "bigg" | "Bigg" => self.delimiter(),
+            "begin" => self.environment(),
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => String::new(),
+            _ => {
+                warn!("Unable to render the TeX command \\{} in an equation", name);
+                format!("<merror><mtext>\\{}</mtext></merror>", escape(name))
+            }
+        }
+    }
+
+    /// The delimiter after `\left` or `\right`, which is nothing for `.`.
+    fn delimiter(&mut self) -> String {
+        let delimiter = match self.next() {
+            Some(Token::Char('.')) | None => return String::new(),
+            Some(Token::Char(c)) => c.to_string(),
+            Some(Token::Command(name)) => match SYMBOLS.iter().find(|&&(n, _)| n == name) {
+                Some(&(_, symbol)) => symbol.to_string(),
+                None => name,
+            },
+            Some(_) => return String::new(),
+        };
+
+        format!("<mo stretchy=\"true\">{}</mo>", escape(&delimiter))
+    }
+
+    /// A `\begin{...}` ... `\end{...}` environment, e.g. a matrix.
+    fn environment(&mut self) -> String {
+        let name = self.text_argument();
+        if name == "array" {
+            // the column specification isn't needed
+            self.text_argument();
+        }
+
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(mrow(self.row()));
+            match self.next() {
+                Some(Token::Column) => {}
+                Some(Token::Command(ref command)) if command == "\\" => {
+                    rows.push(cells);
+                    cells = Vec::new();
+                }
+                Some(Token::Command(ref command)) if command == "end" => {
+                    self.text_argument();
+                    break;
+                }
+                _ => break,
+            }
+        }
+        rows.push(cells);
+
+        // a trailing `\\` leaves an empty row
+        if rows.len() > 1 && rows.last() == Some(&vec![mrow(Vec::new())]) {
+            rows.pop();
+        }
+
+        let align = match &*name {
+            "cases" => " columnalign=\"left\"",
+            "aligned" | "align" | "align*" | "split" => " columnalign=\"right left\"",
+            _ => "",
+        };
+        let mut table = format!("<mtable{}>", align);
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd>{}</mtd>", cell));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        let (open, close) = match &*name {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        let fence = |delimiter: &str| {
+            if delimiter.is_empty() {
+                String::new()
+            } else {
+                format!("<mo stretchy=\"true\">{}</mo>", delimiter)
+            }
+        };
+        format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+    }
+}
+
+fn mrow(elements: Vec<String>) -> String {
+    if elements.len() == 1 {
+        elements.into_iter().next().expect("There's one element")
+    } else {
+        format!("<mrow>{}</mrow>", elements.join(""))
+    }
+}
+
+fn operator(symbol: &str) -> String {
+    let symbol = match symbol {
+        "-" => "−",
+        "*" => "∗",
+        "'" => "′",
+        other => other,
+    };
+    format!("<mo>{}</mo>", escape(symbol))
+}
+
+fn push_token_text(token: &Token, text: &mut String) {
+    match *token {
+        Token::Char(c) => text.push(c),
+        Token::Command(ref name) if name.len() == 1 => text.push_str(name),
+        Token::Command(ref name) => {
+            text.push('\\');
+            text.push_str(name);
+        }
+        Token::Open => text.push('{'),
+        Token::Close => text.push('}'),
+        Token::Superscript => text.push('^'),
+        Token::Subscript => text.push('_'),
+        Token::Column => text.push('&'),
+    }
+}
+
+fn map_letters(text: &str, map: fn(char) -> Option<char>) -> String {
+    text.chars().map(|c| map(c).unwrap_or(c)).collect()
+}
+
+fn offset_letter(c: char, upper: u32, lower: u32) -> Option<char> {
+    if c.is_ascii_uppercase() {
+        ::std::char::from_u32(upper + (c as u32 - 'A' as u32))
+    } else if c.is_ascii_lowercase() {
+        ::std::char::from_u32(lower + (c as u32 - 'a' as u32))
+    } else {
+        None
+    }
+}
+
+fn bold(c: char) -> Option<char> {
+    if c.is_ascii_digit() {
+        return ::std::char::from_u32(0x1D7CE + (c as u32 - '0' as u32));
+    }
+    offset_letter(c, 0x1D400, 0x1D41A)
+}
+
+fn double_struck(c: char) -> Option<char> {
+    // some letters were in Unicode before the rest
+    let exception = match c {
+        'C' => 'ℂ',
+        'H' => 'ℍ',
+        'N' => 'ℕ',
+        'P' => 'ℙ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'Z' => 'ℤ',
+        _ => return offset_letter(c, 0x1D538, 0x1D552),
+    };
+    Some(exception)
+}
+
+fn script(c: char) -> Option<char> {
+    let exception = match c {
+        'B' => 'ℬ',
+        'E' => 'ℰ',
+        'F' => 'ℱ',
+        'H' => 'ℋ',
+        'I' => 'ℐ',
+        'L' => 'ℒ',
+        'M' => 'ℳ',
+        'R' => 'ℛ',
+        'e' => 'ℯ',
+        'g' => 'ℊ',
+        'o' => 'ℴ',
+        _ => return offset_letter(c, 0x1D49C, 0x1D4B6),
+    };
+    Some(exception)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Operators whose limits go above and below them in displayed equations.
+const LARGE_OPERATORS: &[&str] = &[
+    "sum", "prod", "coprod", "bigcup", "bigcap", "bigoplus", "bigotimes", "bigvee", "bigwedge",
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh",
+    "tanh", "log", "ln", "lg", "exp", "lim", "max", "min", "sup", "inf", "det", "dim", "ker",
+    "deg", "gcd", "arg", "Pr",
+];
+
+const GREEK: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+];
+
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("aleph", "ℵ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+];
+
+const SYMBOLS: &[(&str, &str)] = &[
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("oint", "∮"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("bigoplus", "⨁"),
+    ("bigotimes", "⨂"),
+    ("bigvee", "⋁"),
+    ("bigwedge", "⋀"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("wedge", "∧"),
+    ("land", "∧"),
+    ("vee", "∨"),
+    ("lor", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("approx", "≈"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("equiv", "≡"),
+    ("propto", "∝"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("mid", "∣"),
+    ("parallel", "∥"),
+    ("perp", "⊥"),
+    ("angle", "∠"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("vert", "|"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("Vert", "‖"),
+    ("|", "‖"),
+    ("{", "{"),
+    ("}", "}"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("%", "%"),
+    ("$", "$"),
+    ("#", "#"),
+    ("&", "&"),
+    ("_", "_"),
+    ("prime", "′"),
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    (" ", "0.25em"),
+    ("!", "-0.1667em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+const ACCENTS: &[(&str, &str)] = &[
+    ("hat", "^"),
+    ("widehat", "^"),
+    ("bar", "¯"),
+    ("overline", "¯"),
+    ("vec", "→"),
+    ("overrightarrow", "→"),
+    ("dot", "˙"),
+    ("ddot", "¨"),
+    ("tilde", "~"),
+    ("widetilde", "~"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mathml(tex: &str) -> String {
+        let equation = Equation {
+            source: String::new(),
+            tex: tex.to_string(),
+            display: false,
+        };
+        let rendered = render_equation(&equation);
+        let start = rendered.find("<mrow>").unwrap() + "<mrow>".len();
+        let end = rendered.find("</mrow><annotation").unwrap();
+        rendered[start..end].to_string()
+    }
+
+    #[test]
+    fn find_the_equations() {
+        let src = "Inline $a_1 * b_1$ and \\(x\\), costs $5 or $10, `$not$`\n\n\
+                   $$\n\\sum_i x_i\n$$\n\n```\n$$ code $$\n```\n\\[y\\] \\$z\\$";
+        let (protected, equations) = protect(src);
+
+        assert_eq!(
+            protected,
+            "Inline \u{E000}0\u{E001} and \u{E000}1\u{E001}, costs $5 or $10, `$not$`\n\n\
+             \u{E000}2\u{E001}\n\n```\n$$ code $$\n```\n\u{E000}3\u{E001} \\$z\\$"
+        );
+        let tex: Vec<_> = equations.iter().map(|eq| (eq.tex.as_str(), eq.display)).collect();
+        assert_eq!(
+            tex,
+            vec![
+                ("a_1 * b_1", false),
+                ("x", false),
+                ("\\sum_i x_i", true),
+                ("y", true),
+            ]
+        );
+        assert_eq!(equations[0].source, "$a_1 * b_1$");
+    }
+
+    #[test]
+    fn equations_arent_parsed_as_markdown() {
+        use pulldown_cmark::{html, Parser};
+
+        let (protected, equations) = protect("Where $a_1 * b_1$ and `$x$`");
+        let events = restore(Parser::new(&protected).collect(), &equations);
+        let mut got = String::new();
+        html::push_html(&mut got, events.into_iter());
+
+        assert!(got.starts_with("<p>Where <math xmlns=\"http://www.w3.org/1998/Math/MathML\""));
+        assert!(got.contains("<annotation encoding=\"application/x-tex\">a_1 * b_1</annotation>"));
+        assert!(got.ends_with(" and <code>$x$</code></p>\n"));
+    }
+
+    #[test]
+    fn render_tex_to_mathml() {
+        let inputs = vec![
+            ("x^2", "<msup><mi>x</mi><mn>2</mn></msup>"),
+            ("x_{i+1}", "<msub><mi>x</mi><mrow><mi>i</mi><mo>+</mo><mn>1</mn></mrow></msub>"),
+            ("\\frac{1}{2}", "<mfrac><mn>1</mn><mn>2</mn></mfrac>"),
+            ("\\sqrt[3]{x}", "<mroot><mi>x</mi><mn>3</mn></mroot>"),
+            ("a - \\alpha", "<mi>a</mi><mo>−</mo><mi>α</mi>"),
+            ("\\mathbb{R}", "<mi>ℝ</mi>"),
+            ("\\text{if } x", "<mtext>if</mtext><mi>x</mi>"),
+            ("\\sin x", "<mi>sin</mi><mo>&#x2061;</mo><mi>x</mi>"),
+            (
+                "\\left( x \\right)",
+                "<mrow><mo stretchy=\"true\">(</mo><mi>x</mi><mo stretchy=\"true\">)</mo></mrow>",
+            ),
+            ("a < b", "<mi>a</mi><mo>&lt;</mo><mi>b</mi>"),
+        ];
+
+        for (tex, should_be) in inputs {
+            assert_eq!(mathml(tex), should_be, "{}", tex);
+        }
+    }
+
+    #[test]
+    fn sums_have_limits_in_displayed_equations() {
+        let equation = Equation {
+            source: String::new(),
+            tex: String::from("\\sum_{i=0}^n i"),
+            display: true,
+        };
+        let got = render_equation(&equation);
+
+        assert!(got.contains("display=\"block\""));
+        assert!(got.contains(
+            "<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>0</mn></mrow>"
+        ));
+    }
+
+    #[test]
+    fn render_a_matrix() {
+        let got = mathml("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}");
+
+        assert_eq!(
+            got,
+            "<mrow><mo stretchy=\"true\">(</mo><mtable>\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>\
+             </mtable><mo stretchy=\"true\">)</mo></mrow>"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(mathml("\\foo"), "<merror><mtext>\\foo</mtext></merror>");
+    }
+
+    #[test]
+    fn unbalanced_equations_are_rendered_in_full() {
+        assert_eq!(
+            mathml("a \\\\ b"),
+            "<mi>a</mi><mspace linebreak=\"newline\"/><mi>b</mi>"
+        );
+        assert_eq!(
+            mathml("x}y"),
+            "<mi>x</mi><merror><mtext>}</mtext></merror><mi>y</mi>"
+        );
+        assert_eq!(
+            mathml("a & b \\end{x} c"),
+            "<mi>a</mi><merror><mtext>&amp;</mtext></merror><mi>b</mi>\
+             <merror><mtext>\\end</mtext></merror><mi>x</mi><mi>c</mi>"
+        );
+    }
+}
@@ -1,7 +1,11 @@
 #![allow(missing_docs)] // FIXME: Document this
 
 pub mod fs;
+mod extensions;
+mod highlight;
+mod math;
 mod string;
+use config::{HtmlConfig, Markdown};
 use errors::Error;
 use regex::Regex;
 
@@ -11,7 +15,11 @@ use pulldown_cmark::{
 
 use std::borrow::Cow;
 
-pub use self::string::{take_lines, RangeArgument};
+pub use self::extensions::{split_heading_attributes, HeadingAttributes};
+pub use self::string::{
+    line_and_column, strip_anchors, take_anchored_lines, take_lines,
+    take_rustdoc_include_anchored_lines, take_rustdoc_include_lines, RangeArgument,
+};
 
 /// Replaces multiple consecutive whitespace characters with a single space character.
 pub fn collapse_whitespace<'a>(text: &'a str) -> Cow<'a, str> {
@@ -104,18 +112,63 @@ pub fn render_markdown(text: &str, curly_quotes: bool) -> String {
 }
 
 pub fn render_markdown_with_base(text: &str, curly_quotes: bool, base: &str) -> String {
-    let mut s = String::with_capacity(text.len() * 3 / 2);
+    render(text, curly_quotes, base, false, &Markdown::default())
+}
+
+/// Render markdown to HTML with the HTML renderer's settings, i.e. its
+/// markdown extensions and whether code is highlighted while building the
+/// book.
+pub fn render_markdown_with_config(text: &str, html_config: &HtmlConfig) -> String {
+    render(
+        text,
+        html_config.curly_quotes,
+        "",
+        html_config.build_time_highlighting,
+        &html_config.markdown,
+    )
+}
 
+/// Parse markdown into events, applying the extensions which are turned on.
+/// The search index uses this too, so it sees the same document as the
+/// HTML renderer.
+pub fn parse_markdown<'a>(text: &'a str, markdown: &Markdown) -> Vec<Event<'a>> {
     let mut opts = Options::empty();
     opts.insert(OPTION_ENABLE_TABLES);
     opts.insert(OPTION_ENABLE_FOOTNOTES);
 
-    let p = Parser::new_ext(text, opts);
-    let mut converter = EventQuoteConverter::new(curly_quotes);
-    let events = p
+    let events = if markdown.math {
+        let (protected, equations) = math::protect(text);
+        let events = Parser::new_ext(&protected, opts).collect();
+        math::restore(events, &equations)
+    } else {
+        Parser::new_ext(text, opts).collect()
+    };
+    extensions::apply(events, markdown)
+}
+
+fn render(
+    text: &str,
+    curly_quotes: bool,
+    base: &str,
+    highlight_code: bool,
+    markdown: &Markdown,
+) -> String {
+    let mut s = String::with_capacity(text.len() * 3 / 2);
+
+    let mut events = parse_markdown(text, markdown);
+    if markdown.heading_attributes {
+        events = extensions::heading_attributes(events);
+    }
+
+    let quotes = curly_quotes || markdown.smart_punctuation;
+    let mut converter = EventQuoteConverter::new(quotes, markdown.smart_punctuation);
+    let mut highlighter = CodeHighlighter::new(highlight_code);
+    let events = events
+        .into_iter()
         .map(clean_codeblock_headers)
         .map(|event| adjust_links(event, base))
-        .map(|event| converter.convert(event));
+        .map(|event| converter.convert(event))
+        .flat_map(|event| highlighter.highlight(event));
 
     html::push_html(&mut s, events);
     s
@@ -123,13 +176,17 @@ pub fn render_markdown_with_base(text: &str, curly_quotes: bool, base: &str) ->
 
 struct EventQuoteConverter {
     enabled: bool,
+    dashes: bool,
     convert_text: bool,
 }
 
 impl EventQuoteConverter {
-    fn new(enabled: bool) -> Self {
+    /// Convert quotes to curly quotes, and also dashes and ellipses if
+    /// `dashes` is set.
+    fn new(enabled: bool, dashes: bool) -> Self {
         EventQuoteConverter {
             enabled,
+            dashes,
             convert_text: true,
         }
     }
@@ -149,13 +206,70 @@ impl EventQuoteConverter {
                 event
             }
             Event::Text(ref text) if self.convert_text => {
-                Event::Text(Cow::from(convert_quotes_to_curly(text)))
+                let text = convert_quotes_to_curly(text);
+                if self.dashes {
+                    Event::Text(Cow::from(convert_dashes(&text)))
+                } else {
+                    Event::Text(Cow::from(text))
+                }
             }
             _ => event,
         }
     }
 }
 
+struct CodeHighlighter {
+    enabled: bool,
+    language: Option<String>,
+    code: String,
+}
+
+impl CodeHighlighter {
+    fn new(enabled: bool) -> Self {
+        CodeHighlighter {
+            enabled,
+            language: None,
+            code: String::new(),
+        }
+    }
+
+    /// Collect the text of a code block, replacing it with highlighted HTML
+    /// once the block ends. Editable blocks are left alone, because the
+    /// editor does its own highlighting.
+    fn highlight<'a>(&mut self, event: Event<'a>) -> Vec<Event<'a>> {
+        if !self.enabled {
+            return vec![event];
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(ref info)) => {
+                let mut attributes = info.split(',');
+                let language = attributes.next().unwrap_or("");
+                let editable = attributes.any(|attribute| attribute == "editable");
+
+                if highlight::is_supported(language) && !editable {
+                    self.language = Some(language.to_string());
+                    self.code.clear();
+                }
+            }
+            Event::Text(ref text) if self.language.is_some() => {
+                self.code.push_str(text);
+                return Vec::new();
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some(language) = self.language.take() {
+                    let html = highlight::highlight(&self.code, &language)
+                        .expect("Only supported languages are highlighted");
+                    return vec![Event::Html(Cow::from(html)), event];
+                }
+            }
+            _ => {}
+        }
+
+        vec![event]
+    }
+}
+
 fn clean_codeblock_headers(event: Event) -> Event {
     match event {
         Event::Start(Tag::CodeBlock(ref info)) => {
@@ -198,6 +312,12 @@ fn convert_quotes_to_curly(original_text: &str) -> String {
         }).collect()
 }
 
+fn convert_dashes(text: &str) -> String {
+    text.replace("---", "—")
+        .replace("--", "–")
+        .replace("...", "…")
+}
+
 /// Prints a "backtrace" of some `Error`.
 pub fn log_backtrace(e: &Error) {
     error!("Error: {}", e);
@@ -210,7 +330,8 @@ pub fn log_backtrace(e: &Error) {
 #[cfg(test)]
 mod tests {
     mod render_markdown {
-        use super::super::render_markdown;
+        use super::super::{render_markdown, render_markdown_with_config};
+        use config::HtmlConfig;
 
         #[test]
         fn preserves_external_links() {
@@ -329,6 +450,47 @@ more text with spaces
             assert_eq!(render_markdown(input, false), expected);
             assert_eq!(render_markdown(input, true), expected);
         }
+
+        #[test]
+        fn code_blocks_can_be_highlighted_while_rendering() {
+            let input = r#"
+```rust,no_run
+let x = "<b>";
+```
+
+```rust,editable
+let y = 1;
+```
+
+```unknown
+let z = 2;
+```
+"#;
+
+            let expected = r#"<pre><code class="language-rust,no_run"><span class="hljs-keyword">let</span> x = <span class="hljs-string">&quot;&lt;b&gt;&quot;</span>;
+</code></pre>
+<pre><code class="language-rust,editable">let y = 1;
+</code></pre>
+<pre><code class="language-unknown">let z = 2;
+</code></pre>
+"#;
+            let html_config = HtmlConfig {
+                build_time_highlighting: true,
+                ..Default::default()
+            };
+            assert_eq!(render_markdown_with_config(input, &html_config), expected);
+        }
+
+        #[test]
+        fn smart_punctuation_converts_dashes_and_ellipses_too() {
+            let mut html_config = HtmlConfig::default();
+            html_config.markdown.smart_punctuation = true;
+
+            assert_eq!(
+                render_markdown_with_config("\"Wait -- what---no...\" `--`", &html_config),
+                "<p>“Wait – what—no…” <code>--</code></p>\n"
+            );
+        }
     }
 
     mod html_munging {
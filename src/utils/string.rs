@@ -1,4 +1,6 @@
 use itertools::Itertools;
+use memchr::{self, Memchr};
+use regex::Regex;
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 
 // This trait is already contained in the standard lib, however it is unstable.
@@ -55,9 +57,124 @@ pub fn take_lines<R: RangeArgument<usize>>(s: &str, range: R) -> String {
     }
 }
 
+lazy_static! {
+    static ref ANCHOR_START: Regex = Regex::new(r"ANCHOR:\s*(?P<anchor_name>[\w_-]+)").unwrap();
+    static ref ANCHOR_END: Regex = Regex::new(r"ANCHOR_END:\s*(?P<anchor_name>[\w_-]+)").unwrap();
+}
+
+fn is_anchor(line: &str) -> bool {
+    ANCHOR_START.is_match(line) || ANCHOR_END.is_match(line)
+}
+
+/// Take the lines between an `ANCHOR: name` line and the matching
+/// `ANCHOR_END: name`, returning `None` if the anchor doesn't exist.
+///
+/// If there is no `ANCHOR_END` everything up to the end of the string is
+/// taken. Any other anchors inside the region are removed.
+pub fn take_anchored_lines(s: &str, anchor: &str) -> Option<String> {
+    let mut retained = Vec::new();
+    let mut anchor_found = false;
+
+    for line in s.lines() {
+        if anchor_found {
+            match ANCHOR_END.captures(line) {
+                Some(ref cap) if &cap["anchor_name"] == anchor => break,
+                _ => if !is_anchor(line) {
+                    retained.push(line);
+                },
+            }
+        } else if let Some(cap) = ANCHOR_START.captures(line) {
+            anchor_found = &cap["anchor_name"] == anchor;
+        }
+    }
+
+    if anchor_found {
+        Some(retained.join("\n"))
+    } else {
+        None
+    }
+}
+
+/// Remove every line containing an `ANCHOR` or `ANCHOR_END` marker.
+pub fn strip_anchors(s: &str) -> String {
+    s.split('\n').filter(|line| !is_anchor(line)).join("\n")
+}
+
+/// Take every line of a string, hiding (by prefixing with `# `) the ones
+/// outside `range` so only the range is shown to readers of a Rust example.
+pub fn take_rustdoc_include_lines<R: RangeArgument<usize>>(s: &str, range: R) -> String {
+    let start = *range.start().unwrap_or(&0);
+    let end = range.end().cloned();
+
+    s.lines()
+        .enumerate()
+        .filter(|&(_, line)| !is_anchor(line))
+        .map(|(index, line)| {
+            let visible = index >= start && end.map(|end| index < end).unwrap_or(true);
+            if visible {
+                line.to_string()
+            } else {
+                hide_line(line)
+            }
+        }).join("\n")
+}
+
+/// Take every line of a string, hiding (by prefixing with `# `) the ones
+/// outside the named anchor. Returns `None` if the anchor doesn't exist.
+pub fn take_rustdoc_include_anchored_lines(s: &str, anchor: &str) -> Option<String> {
+    let mut output = Vec::new();
+    let mut within_anchor = false;
+    let mut anchor_found = false;
+
+    for line in s.lines() {
+        if let Some(cap) = ANCHOR_START.captures(line) {
+            if &cap["anchor_name"] == anchor {
+                within_anchor = true;
+                anchor_found = true;
+            }
+        } else if let Some(cap) = ANCHOR_END.captures(line) {
+            if &cap["anchor_name"] == anchor {
+                within_anchor = false;
+            }
+        } else if within_anchor {
+            output.push(line.to_string());
+        } else {
+            output.push(hide_line(line));
+        }
+    }
+
+    if anchor_found {
+        Some(output.join("\n"))
+    } else {
+        None
+    }
+}
+
+fn hide_line(line: &str) -> String {
+    if line.is_empty() {
+        String::from("#")
+    } else {
+        format!("# {}", line)
+    }
+}
+
+/// Find the line and column (both starting from 1) of a byte offset into a
+/// string.
+pub fn line_and_column(s: &str, offset: usize) -> (usize, usize) {
+    let previous_text = &s.as_bytes()[..offset];
+    let line = Memchr::new(b'\n', previous_text).count() + 1;
+    let start_of_line = memchr::memrchr(b'\n', previous_text).map_or(0, |i| i + 1);
+    let column = s[start_of_line..offset].chars().count() + 1;
+
+    (line, column)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::take_lines;
+    use super::{
+        line_and_column, strip_anchors, take_anchored_lines, take_lines,
+        take_rustdoc_include_anchored_lines, take_rustdoc_include_lines,
+    };
 
     #[test]
     fn take_lines_test() {
@@ -70,4 +187,64 @@ mod tests {
         assert_eq!(take_lines(s, 4..3), "");
         assert_eq!(take_lines(s, ..100), s);
     }
+
+    #[test]
+    fn line_and_column_test() {
+        let s = "first\nsécond line";
+        assert_eq!(line_and_column(s, 0), (1, 1));
+        assert_eq!(line_and_column(s, 3), (1, 4));
+        assert_eq!(line_and_column(s, 6), (2, 1));
+        assert_eq!(line_and_column(s, 14), (2, 8));
+    }
+
+    #[test]
+    fn take_anchored_lines_test() {
+        let s = "fn main() {\n    // ANCHOR: body\n    let x = 1;\n    // ANCHOR: inner\n    \
+                 println!(\"{}\", x);\n    // ANCHOR_END: inner\n    // ANCHOR_END: body\n}";
+        assert_eq!(
+            take_anchored_lines(s, "body"),
+            Some(String::from("    let x = 1;\n    println!(\"{}\", x);"))
+        );
+        assert_eq!(
+            take_anchored_lines(s, "inner"),
+            Some(String::from("    println!(\"{}\", x);"))
+        );
+        assert_eq!(take_anchored_lines(s, "missing"), None);
+        // an anchor which is never closed runs until the end of the string
+        assert_eq!(
+            take_anchored_lines("# ANCHOR: open\nfoo\nbar", "open"),
+            Some(String::from("foo\nbar"))
+        );
+    }
+
+    #[test]
+    fn strip_anchors_test() {
+        let s = "// ANCHOR: all\nfn main() {}\n// ANCHOR_END: all\n";
+        assert_eq!(strip_anchors(s), "fn main() {}\n");
+        assert_eq!(strip_anchors("no anchors\n"), "no anchors\n");
+    }
+
+    #[test]
+    fn take_rustdoc_include_lines_test() {
+        let s = "use std::fmt;\n\nfn main() {\n    println!(\"hi\");\n}";
+        assert_eq!(
+            take_rustdoc_include_lines(s, 2..4),
+            "# use std::fmt;\n#\nfn main() {\n    println!(\"hi\");\n# }"
+        );
+        assert_eq!(
+            take_rustdoc_include_lines(s, 3..),
+            "# use std::fmt;\n#\n# fn main() {\n    println!(\"hi\");\n}"
+        );
+        assert_eq!(take_rustdoc_include_lines(s, ..), s);
+    }
+
+    #[test]
+    fn take_rustdoc_include_anchored_lines_test() {
+        let s = "fn main() {\n    // ANCHOR: body\n    let x = 1;\n    // ANCHOR_END: body\n}";
+        assert_eq!(
+            take_rustdoc_include_anchored_lines(s, "body"),
+            Some(String::from("# fn main() {\n    let x = 1;\n# }"))
+        );
+        assert_eq!(take_rustdoc_include_anchored_lines(s, "missing"), None);
+    }
 }
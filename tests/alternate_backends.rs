@@ -69,6 +69,28 @@ fn backends_receive_render_context_via_stdin() {
     assert!(got.is_ok());
 }
 
+#[test]
+#[cfg(not(windows))]
+fn part_titles_are_passed_to_backends() {
+    use mdbook::book::BookItem;
+    use mdbook::renderer::RenderContext;
+    use mdbook::utils::fs::write_file;
+    use std::fs::File;
+
+    let temp = TempFileBuilder::new().prefix("output").tempdir().unwrap();
+    let out_file = temp.path().join("out.txt");
+    let cmd = tee_command(&out_file);
+
+    let (_, book_dir) = dummy_book_with_backend("cat-to-file", &cmd);
+    let summary = b"# Summary\n\n# Part I\n\n- [Chapter 1](./chapter_1.md)\n";
+    write_file(&book_dir.path().join("src"), "SUMMARY.md", summary).unwrap();
+
+    MDBook::load(book_dir.path()).unwrap().build().unwrap();
+
+    let got = RenderContext::from_json(File::open(&out_file).unwrap()).unwrap();
+    assert_eq!(got.book.sections[0], BookItem::PartTitle(String::from("Part I")));
+}
+
 fn dummy_book_with_backend(name: &str, command: &str) -> (MDBook, TempDir) {
     let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
 
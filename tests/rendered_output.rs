@@ -9,12 +9,14 @@ mod dummy_book;
 
 use dummy_book::{assert_contains_strings, assert_doesnt_contain_strings, DummyBook};
 
+use mdbook::book::BookItem;
 use mdbook::config::Config;
 use mdbook::errors::*;
 use mdbook::utils::fs::{file_to_string, write_file};
 use mdbook::MDBook;
 use select::document::Document;
 use select::predicate::{Class, Name, Predicate};
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
@@ -119,10 +121,41 @@ fn check_correct_relative_links_in_print_page() {
 
     assert_contains_strings(
         first.join("print.html"),
-        &[r##"<a href="second/../first/nested.html">the first section</a>,"##],
+        &[
+            r##"<div class="print-chapter print-page-break" id="chapter-first-index">"##,
+            r##"<div class="print-chapter" id="chapter-first-nested">"##,
+            r##"<a href="#chapter-first-nested">the first section</a>,"##,
+        ],
     );
 }
 
+#[test]
+fn the_print_page_starts_with_a_title_page() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut cfg = Config::default();
+    cfg.book.title = Some(String::from("Dummy Book"));
+    cfg.book.authors = vec![String::from("Someone")];
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let print = file_to_string(temp.path().join("book").join("print.html")).unwrap();
+    let title_page = print.find(r#"<h1 class="print-title">Dummy Book</h1>"#).unwrap();
+    let first_chapter = print.find(r#"<div class="print-chapter"#).unwrap();
+    assert!(title_page < first_chapter);
+    assert!(print.contains(r#"<p class="print-authors">Someone</p>"#));
+
+    // every header's ID is only used once
+    let doc = Document::from(print.as_str());
+    let ids: Vec<_> = doc
+        .find(Class("header"))
+        .filter_map(|header| header.attr("id"))
+        .collect();
+    let mut unique_ids = ids.clone();
+    unique_ids.sort();
+    unique_ids.dedup();
+    assert_eq!(ids.len(), unique_ids.len());
+}
+
 #[test]
 fn rendered_code_has_playpen_stuff() {
     let temp = DummyBook::new().build().unwrap();
@@ -331,16 +364,17 @@ fn able_to_include_files_in_chapters() {
 
 /// Ensure cyclic includes are capped so that no exceptions occur
 #[test]
-fn recursive_includes_are_capped() {
+fn recursive_includes_are_not_expanded() {
     let temp = DummyBook::new().build().unwrap();
     let md = MDBook::load(temp.path()).unwrap();
     md.build().unwrap();
 
     let recursive = temp.path().join("book/first/recursive.html");
-    let content = &["Around the world, around the world
-Around the world, around the world
-Around the world, around the world"];
-    assert_contains_strings(&recursive, content);
+    assert_contains_strings(&recursive, &["Around the world, around the world"]);
+    assert_doesnt_contain_strings(
+        &recursive,
+        &["Around the world, around the world\nAround the world"],
+    );
 }
 
 #[test]
@@ -370,6 +404,555 @@ fn book_with_a_reserved_filename_does_not_build() {
     assert!(got.is_err());
 }
 
+#[test]
+fn rendering_errors_name_the_chapter() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    write_file(&src_path, "SUMMARY.md", b"[Intro](intro.md)\n[print](print.md)").unwrap();
+    write_file(&src_path, "intro.md", b"# Intro").unwrap();
+    write_file(&src_path, "print.md", b"# Print").unwrap();
+
+    let md = MDBook::load(tmp_dir.path()).unwrap();
+    let got = md.build().unwrap_err();
+
+    let messages: Vec<String> = got.iter().map(|e| e.to_string()).collect();
+    assert!(
+        messages.contains(&String::from("Unable to render \"print\" (print.md)")),
+        "{:?}",
+        messages
+    );
+}
+
+#[test]
+fn parallel_rendering_is_deterministic() {
+    let mut outputs = Vec::new();
+
+    for &jobs in &[1, 4] {
+        let temp = DummyBook::new().build().unwrap();
+        let mut cfg = Config::default();
+        cfg.build.jobs = Some(jobs);
+        let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+        md.build().unwrap();
+
+        let book_dir = temp.path().join("book");
+        outputs.push((
+            file_to_string(book_dir.join("print.html")).unwrap(),
+            file_to_string(book_dir.join("first").join("nested.html")).unwrap(),
+        ));
+    }
+
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn broken_includes_only_fail_the_build_in_strict_mode() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    write_file(&src_path, "SUMMARY.md", b"[Intro](intro.md)").unwrap();
+    write_file(&src_path, "intro.md", b"# Intro\n\n{{#include missing.rs}}").unwrap();
+
+    let md = MDBook::load(tmp_dir.path()).unwrap();
+    md.build().unwrap();
+    let intro = tmp_dir.path().join("book").join("intro.html");
+    assert_contains_strings(intro, &["{{#include missing.rs}}"]);
+
+    let mut cfg = Config::default();
+    cfg.build.strict = true;
+    let md = MDBook::load_with_config(tmp_dir.path(), cfg).unwrap();
+    let got = md.build().unwrap_err();
+
+    match *got.kind() {
+        ErrorKind::IncludeFailed(ref errors) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].chapter, Path::new("intro.md"));
+            assert_eq!((errors[0].line, errors[0].column), (3, 1));
+        }
+        ref other => panic!("Unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn the_print_page_can_be_disabled_or_split() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+
+    let mut cfg = Config::default();
+    cfg.set("output.html.print.enable", false).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert!(!book_dir.join("print.html").exists());
+    assert_doesnt_contain_strings(book_dir.join("intro.html"), &["print-button"]);
+
+    let mut cfg = Config::default();
+    cfg.set("output.html.print.split", "size").unwrap();
+    cfg.set("output.html.print.page-size", 3).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("print.html"),
+        &[
+            r#"<li><a href="print-1.html">Dummy Book – Recursive</a></li>"#,
+            r#"<li><a href="print-2.html">Second Chapter – Conclusion</a></li>"#,
+        ],
+    );
+    assert_doesnt_contain_strings(book_dir.join("print.html"), &["window.print"]);
+    assert_contains_strings(
+        book_dir.join("print-2.html"),
+        &[
+            r#"id="chapter-conclusion""#,
+            r#"<a href="print-1.html#chapter-first-nested">the first section</a>"#,
+            "window.print",
+        ],
+    );
+    assert_doesnt_contain_strings(book_dir.join("print-2.html"), &[r#"id="chapter-intro""#]);
+    assert!(!book_dir.join("print-3.html").exists());
+}
+
+#[test]
+fn code_can_be_highlighted_while_building_the_book() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+
+    let mut cfg = Config::default();
+    cfg.set("output.html.build-time-highlighting", true).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("first/nested.html"),
+        &[r#"<pre class="playpen"><code class="language-rust">
+# #![allow(unused_variables)]
+#fn main() {
+<span class="hljs-built_in">assert!</span>(<span class="hljs-literal">true</span>);"#],
+    );
+    assert_doesnt_contain_strings(book_dir.join("first/nested.html"), &["highlight.js"]);
+    assert!(!book_dir.join("highlight.js").exists());
+}
+
+#[test]
+fn equations_can_be_rendered_while_building_the_book() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"- [Maths](maths.md)").unwrap();
+    let maths = "# Maths\n\nWhere $x_1 * y_1$ costs $5, and\n\n$$\n\\frac{a}{b}\n$$\n\n\
+                 `$x$`\n";
+    write_file(&src, "maths.md", maths.as_bytes()).unwrap();
+
+    let mut cfg = Config::default();
+    cfg.set("output.html.markdown.math", true).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/maths.html"),
+        &[
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="inline">"#,
+            "<msub><mi>x</mi><mn>1</mn></msub><mo>∗</mo><msub><mi>y</mi><mn>1</mn></msub>",
+            r#"<annotation encoding="application/x-tex">x_1 * y_1</annotation>"#,
+            "</math> costs $5, and</p>",
+            r#"display="block"><semantics><mrow><mfrac><mi>a</mi><mi>b</mi></mfrac></mrow>"#,
+            "<code>$x$</code>",
+        ],
+    );
+}
+
+#[test]
+#[cfg(not(windows))]
+fn diagrams_are_rendered_to_svg_and_cached() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"- [Design](design.md)").unwrap();
+    let design = "# Design\n\n```svg\n<?xml version=\"1.0\"?>\n\
+                  <svg><circle r=\"1\"/>\n\n</svg>\n```\n";
+    write_file(&src, "design.md", design.as_bytes()).unwrap();
+
+    let mut cfg = Config::default();
+    cfg.set("preprocessor.diagrams.languages.svg", "cat").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let page = temp.path().join("book/design.html");
+    assert_contains_strings(
+        &page,
+        &["<div class=\"diagram\">\n<svg><circle r=\"1\"/>\n</svg>\n</div>"],
+    );
+    assert_doesnt_contain_strings(&page, &["<?xml", "<code"]);
+
+    // diagrams which haven't changed are taken from the cache
+    let cache = temp.path().join(".mdbook-cache/diagrams");
+    let cached: Vec<_> = fs::read_dir(&cache).unwrap().map(|e| e.unwrap().path()).collect();
+    assert_eq!(cached.len(), 1);
+    fs::write(&cached[0], "<svg><rect/></svg>").unwrap();
+    md.build().unwrap();
+    assert_contains_strings(&page, &["<svg><rect/></svg>"]);
+}
+
+#[test]
+#[cfg(not(windows))]
+fn diagram_errors_point_at_the_chapter_and_line() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"- [Design](design.md)").unwrap();
+    write_file(
+        &src,
+        "design.md",
+        b"# Design\n\n{{#include intro.md}}\n\n```dot\ndigraph {\n```\n",
+    ).unwrap();
+    write_file(&src, "intro.md", b"Some\nlines\nof\nintroduction.\n").unwrap();
+
+    let mut cfg = Config::default();
+    cfg.set("preprocessor.diagrams.languages.dot", "false").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    let got = md.build().unwrap_err();
+
+    let messages: Vec<_> = got.iter().map(|e| e.to_string()).collect();
+    assert!(messages.contains(&String::from(
+        "Unable to render the dot diagram on line 5 of design.md"
+    )));
+}
+
+#[test]
+#[cfg(not(windows))]
+fn diagram_errors_point_at_the_included_file_and_line() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"- [Design](design.md)").unwrap();
+    write_file(&src, "design.md", b"# Design\n\n{{#include parts/graph.md}}\n").unwrap();
+    write_file(&src, "parts/graph.md", b"A graph:\n\n```dot\ndigraph {\n```\n").unwrap();
+
+    let mut cfg = Config::default();
+    cfg.set("preprocessor.diagrams.languages.dot", "false").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    let got = md.build().unwrap_err();
+
+    let messages: Vec<_> = got.iter().map(|e| e.to_string()).collect();
+    assert!(messages.contains(&String::from(
+        "Unable to render the dot diagram on line 3 of parts/graph.md"
+    )));
+}
+
+#[test]
+fn glossary_terms_link_to_a_generated_glossary() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"- [Intro](intro.md)").unwrap();
+    let intro = "# Crates\n\nA crate, `crate` and [crate](https://crates.io).\n\nAnother crate.\n";
+    write_file(&src, "intro.md", intro.as_bytes()).unwrap();
+    write_file(temp.path(), "glossary.toml", b"Crate = \"A compilation unit.\"").unwrap();
+
+    let mut cfg = Config::default();
+    cfg.set("preprocessor.glossary.file", "glossary.toml").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[
+            r##"<a class="header" href="#crates" id="crates">"##,
+            r#"<p>A <a href="glossary.html#crate" title="A compilation unit.">crate</a>, "#,
+            r#"<code>crate</code> and <a href="https://crates.io">crate</a>.</p>"#,
+            "<p>Another crate.</p>",
+        ],
+    );
+    assert_contains_strings(
+        temp.path().join("book/glossary.html"),
+        &[
+            r##"<a class="header" href="#crate" id="crate">"##,
+            "<p>A compilation unit.</p>",
+        ],
+    );
+}
+
+#[test]
+fn draft_chapters_are_listed_but_not_rendered() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Future chapter]()\n\
+                   - [Conclusion](conclusion.md)\n";
+    write_file(&src_path, "SUMMARY.md", summary.as_bytes()).unwrap();
+    write_file(&src_path, "intro.md", b"# Intro").unwrap();
+
+    let md = MDBook::load(tmp_dir.path()).unwrap();
+    md.build().unwrap();
+
+    // only the chapter which isn't a draft gets created
+    assert!(src_path.join("conclusion.md").exists());
+    let sources: Vec<_> = fs::read_dir(&src_path).unwrap().collect();
+    assert_eq!(sources.len(), 3);
+
+    let intro = tmp_dir.path().join("book").join("intro.html");
+    assert_contains_strings(
+        &intro,
+        &[
+            r#"<li class="draft"><strong aria-hidden="true">2.</strong> Future chapter</li>"#,
+            r#"href="conclusion.html" class="nav-chapters next""#,
+        ],
+    );
+
+    let print = tmp_dir.path().join("book").join("print.html");
+    assert_contains_strings(&print, &[r##"<a class="header" href="#intro""##]);
+    assert_doesnt_contain_strings(&print, &[r##"href="#future-chapter""##]);
+
+    if cfg!(feature = "search") {
+        let index = tmp_dir.path().join("book").join("searchindex.js");
+        assert_doesnt_contain_strings(&index, &["Future chapter"]);
+    }
+}
+
+#[test]
+fn part_titles_are_shown_in_the_table_of_contents() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    let summary = [
+        "# Summary",
+        "# Part I: Basics",
+        "- [First](first.md)",
+        "    - [Nested](nested.md)",
+        "# Part II: Advanced",
+        "- [Second](second.md)",
+    ].join("\n\n");
+    write_file(&src_path, "SUMMARY.md", summary.as_bytes()).unwrap();
+
+    let md = MDBook::load(tmp_dir.path()).unwrap();
+    md.build().unwrap();
+
+    let first = tmp_dir.path().join("book").join("first.html");
+    assert_contains_strings(
+        &first,
+        &[
+            r#"<ol class="chapter"><li class="part-title">Part I: Basics</li>"#,
+            r#"</li></ol></li><li class="part-title">Part II: Advanced</li>"#,
+            r#"<strong aria-hidden="true">2.</strong> Second"#,
+        ],
+    );
+
+    let mut cfg = Config::default();
+    cfg.build.restart_numbering_per_part = true;
+    let md = MDBook::load_with_config(tmp_dir.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(&first, &[r#"<strong aria-hidden="true">1.</strong> Second"#]);
+}
+
+#[test]
+fn front_matter_is_stripped_and_used_by_the_page() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Unfinished](unfinished.md)\n";
+    let intro = "+++\ntitle = \"Getting started\"\ndescription = \"How to begin\"\n\
+                 author = \"Someone\"\n+++\n# Intro\n";
+    write_file(&src_path, "SUMMARY.md", summary.as_bytes()).unwrap();
+    write_file(&src_path, "intro.md", intro.as_bytes()).unwrap();
+    write_file(&src_path, "unfinished.md", b"---\ndraft: true\n---\n# Unfinished").unwrap();
+
+    let md = MDBook::load(tmp_dir.path()).unwrap();
+    let metadata: Vec<_> = md
+        .iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => Some((ch.is_draft(), ch.metadata.len())),
+            _ => None,
+        }).collect();
+    assert_eq!(metadata, vec![(false, 3), (true, 1)]);
+    md.build().unwrap();
+
+    let intro = tmp_dir.path().join("book").join("intro.html");
+    assert_contains_strings(
+        &intro,
+        &[
+            "<title>Getting started - </title>",
+            r#"<meta name="description" content="How to begin">"#,
+            r#"<meta name="author" content="Someone">"#,
+        ],
+    );
+    assert_doesnt_contain_strings(&intro, &["+++", "title = "]);
+    assert!(!tmp_dir.path().join("book").join("unfinished.html").exists());
+}
+
+#[test]
+fn pages_have_their_own_description_and_social_media_tags() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Custom](nested/custom.md)\n";
+    let custom = "---\ndescription: Written by hand\nimage: https://cdn.example.com/a.png\n---\n";
+    write_file(&src_path, "SUMMARY.md", summary.as_bytes()).unwrap();
+    write_file(&src_path, "intro.md", b"# Intro\n\nThe first\nparagraph.\n\nMore.").unwrap();
+    write_file(&src_path, "nested/custom.md", custom.as_bytes()).unwrap();
+
+    let mut cfg = Config::default();
+    cfg.book.title = Some(String::from("Book"));
+    cfg.set("output.html.site-url", "https://example.com/book/").unwrap();
+    cfg.set("output.html.social-image", "cover.png").unwrap();
+    let md = MDBook::load_with_config(tmp_dir.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let book_dir = tmp_dir.path().join("book");
+    assert_contains_strings(
+        book_dir.join("intro.html"),
+        &[
+            r#"<meta name="description" content="The first paragraph.">"#,
+            r#"<meta property="og:title" content="Intro - Book">"#,
+            r#"<meta property="og:url" content="https://example.com/book/intro.html">"#,
+            r#"<meta property="og:image" content="https://example.com/book/cover.png">"#,
+            r#"<meta name="twitter:card" content="summary_large_image">"#,
+        ],
+    );
+    assert_contains_strings(
+        book_dir.join("index.html"),
+        &[r#"<meta property="og:url" content="https://example.com/book/">"#],
+    );
+    assert_contains_strings(
+        book_dir.join("nested").join("custom.html"),
+        &[
+            r#"<meta property="og:description" content="Written by hand">"#,
+            r#"<meta property="og:url" content="https://example.com/book/nested/custom.html">"#,
+            r#"<meta property="og:image" content="https://cdn.example.com/a.png">"#,
+        ],
+    );
+}
+
+#[test]
+fn site_url_adds_a_sitemap_and_canonical_links() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+    assert!(!book_dir.join("sitemap.xml").exists());
+    assert_doesnt_contain_strings(book_dir.join("intro.html"), &[r#"rel="canonical""#]);
+
+    let mut cfg = Config::default();
+    cfg.set("output.html.site-url", "https://example.com/v2").unwrap();
+    cfg.set("output.html.robots-txt", true).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("first").join("nested.html"),
+        &[r#"<link rel="canonical" href="https://example.com/v2/first/nested.html">"#],
+    );
+    assert_contains_strings(
+        book_dir.join("sitemap.xml"),
+        &[
+            "<loc>https://example.com/v2/intro.html</loc>\n    <lastmod>",
+            "<loc>https://example.com/v2/first/nested.html</loc>",
+        ],
+    );
+    assert_contains_strings(
+        book_dir.join("robots.txt"),
+        &["Sitemap: https://example.com/v2/sitemap.xml"],
+    );
+}
+
+#[test]
+fn redirects_are_written_for_moved_chapters() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+
+    let mut redirects = BTreeMap::new();
+    redirects.insert("/old/intro.html", "/intro.html#some-section");
+    redirects.insert("/nested.html", "first/nested.html");
+    let mut cfg = Config::default();
+    cfg.set("output.html.redirect", &redirects).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("old").join("intro.html"),
+        &[
+            r#"<meta http-equiv="refresh" content="0; URL=../intro.html#some-section">"#,
+            "window.location.hash",
+        ],
+    );
+    assert_contains_strings(
+        book_dir.join("nested.html"),
+        &[r#"<a href="first/nested.html">"#],
+    );
+
+    redirects.insert("/first/nested.html", "/intro.html");
+    let mut cfg = Config::default();
+    cfg.set("output.html.redirect", &redirects).unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    assert!(md.build().is_err());
+}
+
+#[test]
+fn a_feed_lists_the_releases() {
+    let tmp_dir = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src_path = tmp_dir.path().join("src");
+    let summary = "# Summary\n\n- [Intro](intro.md)\n- [Releases](releases/README.md)\n    \
+                   - [v1.0](releases/v1.0.md)\n    - [v1.1](releases/v1.1.md)\n";
+    write_file(&src_path, "SUMMARY.md", summary.as_bytes()).unwrap();
+    write_file(&src_path, "intro.md", b"# Intro").unwrap();
+    write_file(&src_path, "releases/README.md", b"# Releases").unwrap();
+    let v1_0 = "---\ndate: 2018-01-01\n---\n# v1.0\n\nThe *first* release.\n";
+    write_file(&src_path, "releases/v1.0.md", v1_0.as_bytes()).unwrap();
+    let v1_1 = "+++\ndate = 2018-02-01T12:00:00Z\n+++\n# v1.1\n\nA bug fix.\n";
+    write_file(&src_path, "releases/v1.1.md", v1_1.as_bytes()).unwrap();
+
+    let mut cfg = Config::default();
+    cfg.book.title = Some(String::from("Changelog"));
+    cfg.set("output.html.site-url", "https://example.com/").unwrap();
+    cfg.set("output.html.feed.section", "releases/README.md").unwrap();
+    let md = MDBook::load_with_config(tmp_dir.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    let book_dir = tmp_dir.path().join("book");
+    let feed = file_to_string(book_dir.join("feed.xml")).unwrap();
+    let v1_1 = feed.find("<title>v1.1</title>").unwrap();
+    let v1_0 = feed.find("<title>v1.0</title>").unwrap();
+    assert!(v1_1 < v1_0);
+    assert!(!feed.contains("<title>Intro</title>"));
+    assert_contains_strings(
+        book_dir.join("feed.xml"),
+        &[
+            "<title>Changelog</title>",
+            "<link href=\"https://example.com/releases/v1.0.html\"/>",
+            "<updated>2018-02-01T12:00:00+00:00</updated>",
+            "&lt;p&gt;The &lt;em&gt;first&lt;/em&gt; release.&lt;/p&gt;",
+        ],
+    );
+    assert_contains_strings(
+        book_dir.join("intro.html"),
+        &[r#"type="application/atom+xml" title="Changelog" href="https://example.com/feed.xml">"#],
+    );
+}
+
+#[test]
+fn a_404_page_is_rendered_with_absolute_links() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("404.html"),
+        &[
+            "Document not found (404)",
+            r#"<link rel="stylesheet" href="/css/general.css">"#,
+            r#"<a href="/first/nested.html">"#,
+        ],
+    );
+
+    write_file(&temp.path().join("src"), "404.md", b"# Gone\n\nNothing here.").unwrap();
+    let mut cfg = Config::default();
+    cfg.set("output.html.site-url", "https://example.com/v2/").unwrap();
+    let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        book_dir.join("404.html"),
+        &[
+            "Nothing here.",
+            r#"href="https://example.com/v2/css/general.css""#,
+            r#"<a href="https://example.com/v2/first/nested.html">"#,
+        ],
+    );
+    assert_doesnt_contain_strings(book_dir.join("404.html"), &[r#"rel="canonical""#]);
+}
+
 #[test]
 fn by_default_mdbook_use_index_preprocessor_to_convert_readme_to_index() {
     let temp = DummyBook::new().build().unwrap();
@@ -411,14 +994,161 @@ fn theme_dir_overrides_work_correctly() {
     dummy_book::assert_contains_strings(built_index, &["This is a modified index.hbs!"]);
 }
 
+#[test]
+fn rebuilding_only_renders_chapters_which_changed() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    // Tamper with the output so we can tell whether a page was rendered again
+    let marker = "<!-- left over from the previous build -->";
+    write_file(&book_dir, "second.html", marker.as_bytes()).unwrap();
+    write_file(&book_dir, "conclusion.html", marker.as_bytes()).unwrap();
+    write_file(&temp.path().join("src"), "conclusion.md", b"# Conclusion\n\nEdited").unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_contains_strings(book_dir.join("second.html"), &[marker]);
+    assert_contains_strings(book_dir.join("conclusion.html"), &["Edited"]);
+    assert_contains_strings(book_dir.join("print.html"), &["Edited"]);
+
+    // Changing the table of contents means every page needs to be rendered
+    let mut summary = file_to_string(temp.path().join("src").join("SUMMARY.md")).unwrap();
+    summary = summary.replace("[Second Chapter]", "[Renamed Chapter]");
+    write_file(&temp.path().join("src"), "SUMMARY.md", summary.as_bytes()).unwrap();
+
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert_doesnt_contain_strings(book_dir.join("second.html"), &[marker]);
+    assert_contains_strings(book_dir.join("intro.html"), &["Renamed Chapter"]);
+}
+
+#[test]
+fn rebuilding_removes_files_which_were_removed_from_the_source() {
+    let temp = DummyBook::new().build().unwrap();
+    let book_dir = temp.path().join("book");
+    write_file(&temp.path().join("src"), "images/removed.png", b"PNG").unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert!(book_dir.join("images/removed.png").exists());
+    // the build cache isn't published with the book
+    assert!(temp.path().join(".mdbook-cache").exists());
+    let published: Vec<_> = fs::read_dir(&book_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains("cache"))
+        .collect();
+    assert!(published.is_empty(), "{:?} were published", published);
+
+    fs::remove_file(temp.path().join("src/images/removed.png")).unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    assert!(!book_dir.join("images/removed.png").exists());
+    assert!(book_dir.join("intro.html").exists());
+}
+
+#[test]
+fn each_language_of_a_multilingual_book_is_rendered() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    let book_toml = r#"
+        [book]
+        title = "Multilingual"
+        multilingual = true
+
+        [language.en]
+        name = "English"
+        default = true
+
+        [language.fr]
+        name = "Français"
+        title = "Multilingue"
+        "#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    write_file(&src, "en/SUMMARY.md", b"- [One](one.md)\n- [Two](two.md)").unwrap();
+    write_file(&src, "en/one.md", b"# One").unwrap();
+    write_file(&src, "en/two.md", b"# Two").unwrap();
+    write_file(&src, "fr/SUMMARY.md", b"- [Un](one.md)\n- [Deux](two.md)").unwrap();
+    write_file(&src, "fr/one.md", b"# Un").unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book_dir = temp.path().join("book");
+    assert_contains_strings(
+        book_dir.join("one.html"),
+        &[r#"<html lang="en""#, "<h1>One</h1>", r#"href="fr/index.html""#],
+    );
+    assert_contains_strings(
+        book_dir.join("fr").join("one.html"),
+        &[
+            r#"<html lang="fr""#,
+            "<h1>Un</h1>",
+            "Multilingue",
+            r#"href="../index.html""#,
+        ],
+    );
+    // "two.md" hasn't been translated, so falls back to English
+    assert_contains_strings(book_dir.join("fr").join("two.html"), &["Two", "Deux"]);
+}
+
+#[test]
+fn translations_are_linked_to_where_they_are_published() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    let book_toml = r#"
+        [book]
+        title = "Multilingual"
+        multilingual = true
+
+        [language.en]
+        name = "English"
+        default = true
+
+        [language.fr]
+        name = "Français"
+
+        [output.html]
+        site-url = "https://example.com/book/"
+        "#;
+    write_file(temp.path(), "book.toml", book_toml.as_bytes()).unwrap();
+    write_file(&src, "en/SUMMARY.md", b"- [One](one.md)").unwrap();
+    write_file(&src, "en/one.md", b"# One").unwrap();
+    write_file(&src, "fr/SUMMARY.md", b"- [Un](one.md)").unwrap();
+    write_file(&src, "fr/one.md", b"# Un").unwrap();
+
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book_dir = temp.path().join("book");
+    assert_contains_strings(
+        book_dir.join("one.html"),
+        &[r#"<link rel="canonical" href="https://example.com/book/one.html">"#],
+    );
+    assert_contains_strings(
+        book_dir.join("fr/one.html"),
+        &[r#"<link rel="canonical" href="https://example.com/book/fr/one.html">"#],
+    );
+    assert_contains_strings(
+        book_dir.join("fr/sitemap.xml"),
+        &["<loc>https://example.com/book/fr/one.html</loc>"],
+    );
+    assert_doesnt_contain_strings(
+        book_dir.join("fr/sitemap.xml"),
+        &["<loc>https://example.com/book/one.html</loc>"],
+    );
+}
+
 #[cfg(feature = "search")]
 mod search {
     extern crate serde_json;
-    use dummy_book::DummyBook;
-    use mdbook::utils::fs::file_to_string;
+    use dummy_book::{assert_contains_strings, DummyBook};
+    use mdbook::config::Config;
+    use mdbook::utils::fs::{file_to_string, write_file};
     use mdbook::MDBook;
     use std::fs::File;
     use std::path::Path;
+    use tempfile::Builder as TempFileBuilder;
 
     fn read_book_index(root: &Path) -> serde_json::Value {
         let index = root.join("book/searchindex.js");
@@ -463,6 +1193,40 @@ mod search {
         assert_eq!(docs[&conclusion]["body"], "I put &lt;HTML&gt; in here!");
     }
 
+    #[test]
+    fn markdown_extensions_are_rendered_and_indexed() {
+        let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+        let src = temp.path().join("src");
+        write_file(&src, "SUMMARY.md", b"- [Usage](usage.md)").unwrap();
+        let usage = "# Usage\n\n## Installing {#install .wide}\n\n\
+                     > **Note**: use ~~the old~~ a new version\n";
+        write_file(&src, "usage.md", usage.as_bytes()).unwrap();
+
+        let mut cfg = Config::default();
+        cfg.set("output.html.markdown.strikethrough", true).unwrap();
+        cfg.set("output.html.markdown.heading-attributes", true).unwrap();
+        cfg.set("output.html.markdown.admonitions", true).unwrap();
+        let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+        md.build().unwrap();
+
+        assert_contains_strings(
+            temp.path().join("book/usage.html"),
+            &[
+                r##"<a class="header" href="#install" id="install">"##,
+                r#"<h2 class="wide">Installing</h2>"#,
+                r#"<div class="admonition note">"#,
+                "<p>use <del>the old</del> a new version</p>",
+            ],
+        );
+
+        let index = read_book_index(temp.path());
+        let doc_urls = index["doc_urls"].as_array().unwrap();
+        let install = doc_urls.iter().position(|url| url == "usage.html#install").unwrap();
+        let docs = &index["index"]["documentStore"]["docs"];
+        assert_eq!(docs[&install.to_string()]["title"], "Installing");
+        assert_eq!(docs[&install.to_string()]["body"], "Note use the old a new version");
+    }
+
     // Setting this to `true` may cause issues with `cargo watch`,
     // since it may not finish writing the fixture before the tests
     // are run again.
@@ -514,3 +1278,46 @@ mod search {
         }
     }
 }
+
+#[cfg(feature = "epub")]
+mod epub {
+    use dummy_book::DummyBook;
+    use mdbook::config::Config;
+    use mdbook::MDBook;
+    use std::fs;
+
+    #[test]
+    fn the_epub_renderer_generates_a_single_file() {
+        let temp = DummyBook::new().build().unwrap();
+        let mut cfg = Config::default();
+        cfg.set("output.epub.filename", "dummy").unwrap();
+        let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+        md.build().unwrap();
+
+        let epub = fs::read(temp.path().join("book").join("dummy.epub")).unwrap();
+        let epub = String::from_utf8_lossy(&epub);
+
+        assert!(epub.starts_with("PK\u{3}\u{4}"));
+        assert!(epub.contains("mimetypeapplication/epub+zip"));
+        for file in &[
+            "META-INF/container.xml",
+            "OEBPS/content.opf",
+            "OEBPS/toc.ncx",
+            "OEBPS/nav.xhtml",
+            "OEBPS/intro.html",
+            "OEBPS/first/nested.html",
+        ] {
+            assert!(epub.contains(file), "{} is missing from the EPUB", file);
+        }
+    }
+
+    #[test]
+    fn invalid_epub_configuration_is_an_error() {
+        let temp = DummyBook::new().build().unwrap();
+        let mut cfg = Config::default();
+        cfg.set("output.epub.curly-quotes", "yes").unwrap();
+        let md = MDBook::load_with_config(temp.path(), cfg).unwrap();
+
+        assert!(md.build().is_err());
+    }
+}
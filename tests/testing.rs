@@ -1,10 +1,14 @@
 extern crate mdbook;
+extern crate tempfile;
 
 mod dummy_book;
 
 use dummy_book::DummyBook;
 
+use mdbook::utils::fs::write_file;
 use mdbook::MDBook;
+use std::process::Command;
+use tempfile::Builder as TempFileBuilder;
 
 #[test]
 fn mdbook_can_correctly_test_a_passing_book() {
@@ -21,3 +25,74 @@ fn mdbook_detects_book_with_failing_tests() {
 
     assert!(md.test(vec![]).is_err());
 }
+
+#[test]
+fn code_block_attributes_are_honoured() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"[Chapter](chapter.md)").unwrap();
+    write_file(
+        &src,
+        "chapter.md",
+        b"```rust,should_panic\npanic!(\"oops\");\n```\n\n\
+          ```rust,compile_fail\nlet x: u32 = \"not a number\";\n```\n\n\
+          ```rust,no_run\nloop {}\n```\n\n\
+          ```rust,ignore\nthis isn't Rust\n```\n\n\
+          ```rust,edition2018\nasync fn later() {}\nfn main() {}\n```\n\n\
+          ```text\nneither is this\n```\n",
+    ).unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+
+    md.test(vec![]).unwrap();
+}
+
+#[test]
+fn the_preprocessed_book_is_tested() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "SUMMARY.md", b"[Chapter](chapter.md)").unwrap();
+    write_file(&src, "chapter.md", b"```rust\n{{#include broken.rs}}\n```\n").unwrap();
+    write_file(&src, "broken.rs", b"let x: u32 = \"not a number\";").unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    let got = md.test(vec![]).unwrap_err();
+
+    assert_eq!(got.to_string(), "1 code block failed");
+}
+
+#[test]
+fn rust_and_test_config_is_passed_to_rustdoc() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let root = temp.path();
+
+    write_file(root, "my_crate.rs", b"pub fn answer() -> u32 { 42 }").unwrap();
+    let status = Command::new("rustc")
+        .arg("--crate-type=lib")
+        .arg("--out-dir=target")
+        .arg("my_crate.rs")
+        .current_dir(root)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    write_file(
+        root,
+        "book.toml",
+        b"[rust]\nedition = \"2018\"\n\n\
+          [test.externs]\nmy_crate = \"target/libmy_crate.rlib\"\n\n\
+          [test.env]\nMDBOOK_TEST_VALUE = \"42\"\n",
+    ).unwrap();
+    let src = root.join("src");
+    write_file(&src, "SUMMARY.md", b"[Chapter](chapter.md)").unwrap();
+    write_file(
+        &src,
+        "chapter.md",
+        b"```rust\nasync fn only_in_2018() {}\n\
+          assert_eq!(my_crate::answer().to_string(), env!(\"MDBOOK_TEST_VALUE\"));\n```\n",
+    ).unwrap();
+
+    let mut md = MDBook::load(root).unwrap();
+
+    md.test(vec![]).unwrap();
+}